@@ -42,4 +42,8 @@ const int I2C_CLOCK_SPEED = 1000000;
 #define DEBUG_SERIAL Serial
 #define TRANSMITTER_SERIAL Serial5
 
+// Send telemetry as COBS frames with a CRC-16 instead of newline JSON,
+// the bridge must be started with `framing = "cobs"` as well
+// #define TRANSMITTER_BINARY_FRAMES
+
 #endif
\ No newline at end of file
@@ -72,7 +72,7 @@ void setup(void)
     // }
     // DEBUG_SERIAL.println("Throttle is zero. Ready to fly!");
     delay(5000);
-    TRANSMITTER_SERIAL.println("Waiting for command to arm...");
+    transmitter.sendMessage("Waiting for command to arm...");
     DEBUG_SERIAL.println("Waiting for command to arm...");
     while (true)
     {
@@ -84,7 +84,7 @@ void setup(void)
             if (receivedData == "command->arm")
             {
                 DEBUG_SERIAL.println("Armed...");
-                TRANSMITTER_SERIAL.println("Armed...");
+                transmitter.sendMessage("Armed...");
                 break;
             }
         }
@@ -115,7 +115,7 @@ void loop()
         if (receivedData == "command->abort")
         {
             DEBUG_SERIAL.println("Aborting...");
-            TRANSMITTER_SERIAL.println("Aborting...");
+            transmitter.sendMessage("Aborting...");
             while (true)
                 ;
         }
@@ -2,6 +2,25 @@
 #include "consts.h"
 #include <ArduinoJson.h>
 
+// Message types, must match `protocol/src/frame.rs`
+const uint8_t FRAME_TYPE_SERIAL_DATA = 0x01;
+const uint8_t FRAME_TYPE_TEXT = 0x02;
+const size_t MAX_FRAME_BODY = 255;
+
+// CRC-16/CCITT-FALSE
+static uint16_t crc16(uint16_t crc, const uint8_t *data, size_t length)
+{
+    for (size_t i = 0; i < length; i++)
+    {
+        crc ^= (uint16_t)data[i] << 8;
+        for (int bit = 0; bit < 8; bit++)
+        {
+            crc = (crc & 0x8000) ? (crc << 1) ^ 0x1021 : crc << 1;
+        }
+    }
+    return crc;
+}
+
 TransmitterController::TransmitterController() : lastTransmitTime(0) {}
 
 void TransmitterController::transmitData(TransmitterData data)
@@ -17,6 +36,11 @@ void TransmitterController::transmitData(TransmitterData data)
 
 void TransmitterController::sendData(TransmitterData data)
 {
+#ifdef TRANSMITTER_BINARY_FRAMES
+    sendFrame(FRAME_TYPE_SERIAL_DATA, (const uint8_t *)&data, sizeof(TransmitterData));
+    return;
+#endif
+
     StaticJsonDocument<512> doc;
 
     doc["elapsed_time"] = data.elapsedTime;
@@ -54,6 +78,60 @@ void TransmitterController::sendData(TransmitterData data)
     TRANSMITTER_SERIAL.println(jsonString);
 }
 
+void TransmitterController::sendMessage(const String &message)
+{
+#ifdef TRANSMITTER_BINARY_FRAMES
+    size_t length = min((size_t)message.length(), MAX_FRAME_BODY);
+    sendFrame(FRAME_TYPE_TEXT, (const uint8_t *)message.c_str(), length);
+#else
+    TRANSMITTER_SERIAL.println(message);
+#endif
+}
+
+// Writes COBS(type | body | crc16) followed by a zero delimiter
+void TransmitterController::sendFrame(uint8_t type, const uint8_t *body, size_t length)
+{
+    uint8_t payload[1 + MAX_FRAME_BODY + 2];
+    length = min(length, MAX_FRAME_BODY);
+
+    payload[0] = type;
+    memcpy(payload + 1, body, length);
+    uint16_t crc = crc16(0xFFFF, payload, length + 1);
+    payload[length + 1] = crc & 0xFF;
+    payload[length + 2] = crc >> 8;
+    size_t payloadLength = length + 3;
+
+    uint8_t encoded[sizeof(payload) + sizeof(payload) / 254 + 2];
+    size_t codeIndex = 0;
+    size_t encodedLength = 1;
+    uint8_t code = 1;
+
+    for (size_t i = 0; i < payloadLength; i++)
+    {
+        if (payload[i] == 0)
+        {
+            encoded[codeIndex] = code;
+            codeIndex = encodedLength++;
+            code = 1;
+        }
+        else
+        {
+            encoded[encodedLength++] = payload[i];
+            code++;
+            if (code == 0xFF)
+            {
+                encoded[codeIndex] = code;
+                codeIndex = encodedLength++;
+                code = 1;
+            }
+        }
+    }
+    encoded[codeIndex] = code;
+    encoded[encodedLength++] = 0x00;
+
+    TRANSMITTER_SERIAL.write(encoded, encodedLength);
+}
+
 String TransmitterController::receiveData()
 {
     if (TRANSMITTER_SERIAL.available())
@@ -53,11 +53,13 @@ private:
     const unsigned long transmitInterval = 20; // Print every 100ms
 
     void sendData(TransmitterData data);
+    void sendFrame(uint8_t type, const uint8_t *body, size_t length);
 
 public:
     TransmitterController();
 
     void transmitData(TransmitterData data);
+    void sendMessage(const String &message);
     String receiveData();
 };
 
@@ -8,7 +8,6 @@ const CHART_HISTORY: usize = 10000;
 
 #[derive(Clone)]
 pub struct AccelerometerView {
-    open: bool,
     accel_x: AllocRingBuffer<(f64, f64)>,
     accel_y: AllocRingBuffer<(f64, f64)>,
     accel_z: AllocRingBuffer<(f64, f64)>,
@@ -17,29 +16,14 @@ pub struct AccelerometerView {
 impl AccelerometerView {
     pub fn new() -> Self {
         Self {
-            open: false,
             accel_x: AllocRingBuffer::new(CHART_HISTORY),
             accel_y: AllocRingBuffer::new(CHART_HISTORY),
             accel_z: AllocRingBuffer::new(CHART_HISTORY),
         }
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
-        if ui
-            .button(if self.open {
-                "Close Accelerometer View"
-            } else {
-                "Open Accelerometer View"
-            })
-            .clicked()
-        {
-            self.open = !self.open;
-        }
-    }
-
     pub fn window(&mut self, ctx: &egui::Context, received_data: &Arc<Mutex<ReceivedData>>) {
         egui::Window::new("Accelerometer Data")
-            // .open(&mut self.open)
             .default_size([600.0, 400.0])
             .resizable(true)
             .show(ctx, |ui| {
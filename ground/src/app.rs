@@ -2,14 +2,17 @@ use crate::accelerometer_view::AccelerometerView;
 use crate::attitude_view::AttitudeView;
 use crate::chat_view::ChatView;
 use crate::commands_view::CommandsView;
+use crate::companion_view::CompanionView;
 use crate::data::ReceivedData;
 use crate::drone_view::DroneView;
+use crate::link_stats_view::LinkStatsView;
+use crate::logs_view::LogsView;
 use crate::notes::NoteEditorView;
 use crate::pid_view::PIDControlView;
 use crate::rc_control::RCControl;
 use crate::rc_view::RCView;
 use chrono::Local;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{Receiver, Sender};
 use eframe::egui::{self, RichText};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
@@ -27,6 +30,9 @@ pub struct MyApp {
     notes: NoteEditorView,
     pub chat_view: ChatView,
     commands_view: CommandsView,
+    logs_view: LogsView,
+    link_stats_view: LinkStatsView,
+    companion_view: CompanionView,
     received_data: Arc<Mutex<ReceivedData>>,
     start_time: Instant,
     last_received_time: Arc<Mutex<Instant>>,
@@ -53,6 +59,8 @@ enum WindowType {
     RCControl,
     PIDControl,
     Notes,
+    Logs,
+    Companion,
 }
 
 impl MyApp {
@@ -64,25 +72,25 @@ impl MyApp {
         drone_to_ui_rx: Receiver<String>,
     ) -> Self {
         Self {
-            drone_view: DroneView::default(),
-            attitude_view: AttitudeView::default(),
+            drone_view: DroneView,
+            attitude_view: AttitudeView,
             accelerometer_view: AccelerometerView::new(),
-            rc_view: RCView::default(),
+            rc_view: RCView,
             rc_control: RCControl::new(ui_to_drone_tx.clone()),
             chat_view: ChatView::new(
-                ui_to_drone_tx.clone(),
-                ui_to_drone_rx.clone(),
-                drone_to_ui_tx.clone(),
-                drone_to_ui_rx.clone(),
-            ),
-            commands_view: CommandsView::new(
                 ui_to_drone_tx.clone(),
                 ui_to_drone_rx,
                 drone_to_ui_tx,
                 drone_to_ui_rx,
             ),
+            commands_view: CommandsView::new(ui_to_drone_tx.clone()),
+            logs_view: LogsView::new(ui_to_drone_tx.clone()),
+            link_stats_view: LinkStatsView::default(),
+            companion_view: CompanionView,
             pid_control: PIDControlView::new(ui_to_drone_tx),
-            notes: NoteEditorView::new(PathBuf::from("/home/elden/Documents/projects/jet/ground/notes.txt")),
+            notes: NoteEditorView::new(PathBuf::from(
+                "/home/elden/Documents/projects/jet/ground/notes.txt",
+            )),
             received_data,
             start_time: Instant::now(),
             last_received_time: Arc::new(Mutex::new(Instant::now())),
@@ -146,6 +154,17 @@ impl eframe::App for MyApp {
                     "Last Packet: {:.2}s ago",
                     last_packet_elapsed.as_secs_f32()
                 ));
+                let last_rtt = self
+                    .received_data
+                    .lock()
+                    .ok()
+                    .and_then(|data| data.link.last_rtt);
+                let link_label = match last_rtt {
+                    Some(rtt) => format!("RTT: {} ms", rtt.as_millis()),
+                    None => "RTT: -".to_string(),
+                };
+                ui.toggle_value(&mut self.link_stats_view.open, link_label)
+                    .on_hover_text("Link statistics");
 
                 let connected = last_packet_elapsed < Duration::from_millis(500);
                 ui.colored_label(
@@ -200,6 +219,8 @@ impl eframe::App for MyApp {
                         WindowType::RCControl,
                         WindowType::PIDControl,
                         WindowType::Notes,
+                        WindowType::Logs,
+                        WindowType::Companion,
                     ];
                 }
 
@@ -348,9 +369,39 @@ impl eframe::App for MyApp {
                         self.tabs[self.active_tab].windows.push(WindowType::Notes);
                     }
                 }
+                if ui.button("Flight Logs").clicked() {
+                    if self.tabs[self.active_tab]
+                        .windows
+                        .iter()
+                        .any(|w| *w == WindowType::Logs)
+                    {
+                        self.tabs[self.active_tab]
+                            .windows
+                            .retain(|w| *w != WindowType::Logs);
+                    } else {
+                        self.tabs[self.active_tab].windows.push(WindowType::Logs);
+                    }
+                }
+                if ui.button("Companion").clicked() {
+                    if self.tabs[self.active_tab]
+                        .windows
+                        .iter()
+                        .any(|w| *w == WindowType::Companion)
+                    {
+                        self.tabs[self.active_tab]
+                            .windows
+                            .retain(|w| *w != WindowType::Companion);
+                    } else {
+                        self.tabs[self.active_tab]
+                            .windows
+                            .push(WindowType::Companion);
+                    }
+                }
             });
         });
 
+        self.link_stats_view.window(ctx, &self.received_data);
+
         egui::CentralPanel::default().show(ctx, |_ui| {
             for window in &self.tabs[self.active_tab].windows {
                 match window {
@@ -365,6 +416,8 @@ impl eframe::App for MyApp {
                     WindowType::RCControl => self.rc_control.window(ctx),
                     WindowType::PIDControl => self.pid_control.window(ctx, &self.received_data),
                     WindowType::Notes => self.notes.window(ctx),
+                    WindowType::Logs => self.logs_view.window(ctx, &self.received_data),
+                    WindowType::Companion => self.companion_view.window(ctx, &self.received_data),
                 }
             }
         });
@@ -3,34 +3,12 @@ use eframe::egui;
 use nalgebra as na;
 use std::sync::{Arc, Mutex};
 
-#[derive( Clone)]
-pub struct AttitudeView {
-    open: bool,
-}
-
-impl Default for AttitudeView {
-    fn default() -> Self {
-        Self { open: false }
-    }
-}
+#[derive(Clone, Default)]
+pub struct AttitudeView;
 
 impl AttitudeView {
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
-        if ui
-            .button(if self.open {
-                "Close Attitude View"
-            } else {
-                "Open Attitude View"
-            })
-            .clicked()
-        {
-            self.open = !self.open;
-        }
-    }
-
     pub fn window(&mut self, ctx: &egui::Context, received_data: &Arc<Mutex<ReceivedData>>) {
         egui::Window::new("Drone Attitude")
-            // .open(&mut self.open)
             .resizable(true)
             .show(ctx, |ui| {
                 let data = received_data.lock().unwrap();
@@ -151,12 +129,12 @@ fn draw_sci_fi_drone(ui: &mut egui::Ui, yaw: f32, pitch: f32, roll: f32) -> egui
     painter.line_segment([center, projected_points[4]], (3.0, egui::Color32::YELLOW));
 
     // Draw motors
-    for i in 5..9 {
-        painter.circle_filled(projected_points[i], 5.0, egui::Color32::RED);
+    for point in &projected_points[5..9] {
+        painter.circle_filled(*point, 5.0, egui::Color32::RED);
     }
 
     // Draw axis labels
-    let label_offset = draw_size / 2.0 + 20.0;
+    let _label_offset = draw_size / 2.0 + 20.0;
     painter.text(
         // egui::Pos2::new(center.x + label_offset, center.y),
         projected_points[0],
@@ -1,12 +1,11 @@
 use crate::data::ReceivedData;
 use chrono::Local;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crossbeam_channel::{Receiver, Sender};
 use eframe::egui;
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 pub struct ChatView {
-    open: bool,
     input: String,
     messages: Vec<ChatMessage>,
     pub ui_to_drone_tx: Sender<String>,
@@ -31,7 +30,6 @@ impl ChatView {
         drone_to_ui_rx: Receiver<String>,
     ) -> Self {
         Self {
-            open: false,
             input: String::new(),
             messages: Vec::new(),
             ui_to_drone_tx,
@@ -44,18 +42,8 @@ impl ChatView {
 }
 
 impl ChatView {
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
-        if ui
-            .button(if self.open { "Close Chat" } else { "Open Chat" })
-            .clicked()
-        {
-            self.open = !self.open;
-        }
-    }
-
-    pub fn window(&mut self, ctx: &egui::Context, received_data: &Arc<Mutex<ReceivedData>>) {
+    pub fn window(&mut self, ctx: &egui::Context, _received_data: &Arc<Mutex<ReceivedData>>) {
         egui::Window::new("Drone Chat")
-            // .open(&mut self.open)
             .resizable(true)
             .default_size([400.0, 600.0])
             .show(ctx, |ui| {
@@ -75,6 +63,10 @@ impl ChatView {
                     for message in &self.messages {
                         let (text, color) = if message.is_user {
                             ("You: ", egui::Color32::LIGHT_BLUE)
+                        } else if message.text.starts_with("[error]") {
+                            ("Drone: ", egui::Color32::RED)
+                        } else if message.text.starts_with("[warning]") {
+                            ("Drone: ", egui::Color32::YELLOW)
                         } else {
                             ("Drone: ", egui::Color32::LIGHT_GREEN)
                         };
@@ -1,72 +1,101 @@
-use crate::data::ReceivedData;
-use crossbeam_channel::{unbounded, Receiver, Sender};
+use crate::data::{ControlStatus, ReceivedData};
+use crossbeam_channel::Sender;
 use eframe::egui::{self, RichText};
 use epaint::Color32;
+use protocol::{Command, ControlRequest};
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 pub struct CommandsView {
-    open: bool,
     motor_enabled: bool,
     last_sent_time: std::time::Instant,
     pub ui_to_drone_tx: Sender<String>,
-    pub ui_to_drone_rx: Receiver<String>,
-    pub drone_to_ui_tx: Sender<String>,
-    pub drone_to_ui_rx: Receiver<String>,
 }
 
 impl CommandsView {
-    pub fn new(
-        ui_to_drone_tx: Sender<String>,
-        ui_to_drone_rx: Receiver<String>,
-        drone_to_ui_tx: Sender<String>,
-        drone_to_ui_rx: Receiver<String>,
-    ) -> Self {
+    pub fn new(ui_to_drone_tx: Sender<String>) -> Self {
         Self {
-            open: false,
             motor_enabled: false,
             last_sent_time: std::time::Instant::now(),
             ui_to_drone_tx,
-            ui_to_drone_rx,
-            drone_to_ui_tx,
-            drone_to_ui_rx,
         }
     }
 }
 
 impl CommandsView {
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
-        if ui
-            .button(if self.open { "Close" } else { "Open" })
-            .clicked()
-        {
-            self.open = !self.open;
-        }
-    }
-
     pub fn window(&mut self, ctx: &egui::Context, received_data: &Arc<Mutex<ReceivedData>>) {
         if self.last_sent_time.elapsed() > std::time::Duration::from_millis(50) {
             self.last_sent_time = std::time::Instant::now();
 
             if self.motor_enabled {
                 self.ui_to_drone_tx
-                    .send("command->enable_motors".to_string())
+                    .send(Command::EnableMotors.encode())
                     .expect("Failed to send enable motors message");
             }
         }
 
         egui::Window::new("Commands")
-            // .open(&mut self.open)
             .resizable(true)
             .default_size([400.0, 600.0])
             .show(ctx, |ui| {
+                let (control, command_queue, last_answer, arm, abort, reboot) = {
+                    let data = received_data.lock().unwrap();
+                    let latest =
+                        |command: Command| data.deliveries.latest(|c| *c == command).cloned();
+                    (
+                        data.control,
+                        data.command_queue,
+                        data.last_answer.clone(),
+                        latest(Command::Arm),
+                        latest(Command::Abort),
+                        latest(Command::Reboot),
+                    )
+                };
+                let send = |command: Command| {
+                    let message = received_data.lock().unwrap().deliveries.track(command);
+                    self.ui_to_drone_tx
+                        .send(message)
+                        .expect("Failed to send command message");
+                };
+                ui.horizontal(|ui| match control {
+                    Some(ControlStatus::Pilot) => {
+                        ui.colored_label(Color32::GREEN, "You have control");
+                        if ui.button("Release Control").clicked() {
+                            self.ui_to_drone_tx
+                                .send(ControlRequest::Release.encode())
+                                .expect("Failed to send release control message");
+                        }
+                    }
+                    Some(ControlStatus::Observer) => {
+                        ui.colored_label(Color32::YELLOW, "Observer, another client has control");
+                    }
+                    Some(ControlStatus::Available) | None => {
+                        ui.label("Control available");
+                        if ui.button("Take Control").clicked() {
+                            self.ui_to_drone_tx
+                                .send(ControlRequest::Take.encode())
+                                .expect("Failed to send take control message");
+                        }
+                    }
+                });
+                if let Some(queue) = command_queue {
+                    ui.label(format!(
+                        "Bridge queue: {} waiting, {} coalesced, {} dropped",
+                        queue.depth, queue.coalesced, queue.dropped
+                    ));
+                }
+                if let Some(answer) = last_answer {
+                    ui.label(format!("Flight controller: {}", answer));
+                }
+
                 if ui
                     .button(RichText::new("Master Arm").size(32.).color(Color32::GREEN))
                     .clicked()
                 {
-                    self.ui_to_drone_tx
-                        .send("command->arm".to_string())
-                        .expect("Failed to send arm message");
+                    send(Command::Arm);
+                }
+                if let Some(delivery) = &arm {
+                    delivery.label(ui);
                 }
 
                 if ui
@@ -84,9 +113,10 @@ impl CommandsView {
                     .button(RichText::new("Master Abort").size(32.).color(Color32::RED))
                     .clicked()
                 {
-                    self.ui_to_drone_tx
-                        .send("command->abort".to_string())
-                        .expect("Failed to send abort message");
+                    send(Command::Abort);
+                }
+                if let Some(delivery) = &abort {
+                    delivery.label(ui);
                 }
 
                 // button to enable/disable motors
@@ -94,9 +124,10 @@ impl CommandsView {
                     .button(RichText::new("Reboot").size(32.).color(Color32::RED))
                     .clicked()
                 {
-                    self.ui_to_drone_tx
-                        .send("command->reboot".to_string())
-                        .expect("Failed to send reboot message");
+                    send(Command::Reboot);
+                }
+                if let Some(delivery) = &reboot {
+                    delivery.label(ui);
                 }
             });
     }
@@ -0,0 +1,133 @@
+use crate::data::ReceivedData;
+use eframe::egui;
+use epaint::Color32;
+use std::sync::{Arc, Mutex};
+
+// Above this the Pi starts to throttle soon
+const HOT_CPU_C: f32 = 70.0;
+// Below this the Wi-Fi link to the bridge gets unreliable
+const WEAK_SIGNAL_DBM: f32 = -70.0;
+
+// Health of the Raspberry Pi running the bridge and the fix of its GPS receiver
+#[derive(Clone, Default)]
+pub struct CompanionView;
+
+impl CompanionView {
+    pub fn window(&mut self, ctx: &egui::Context, received_data: &Arc<Mutex<ReceivedData>>) {
+        let (companion, gps) = {
+            let data = received_data.lock().unwrap();
+            (data.companion.clone(), data.gps.clone())
+        };
+
+        egui::Window::new("Companion")
+            .resizable(true)
+            .default_size([320.0, 200.0])
+            .show(ctx, |ui| {
+                let Some(health) = companion else {
+                    ui.label("No health data from the bridge");
+                    return;
+                };
+                let missing = || "-".to_string();
+
+                egui::Grid::new("companion").striped(true).show(ui, |ui| {
+                    ui.label("CPU temperature");
+                    match health.cpu_temp_c {
+                        Some(temp) if temp >= HOT_CPU_C => {
+                            ui.colored_label(Color32::RED, format!("{:.1} °C", temp))
+                        }
+                        Some(temp) => ui.label(format!("{:.1} °C", temp)),
+                        None => ui.label(missing()),
+                    };
+                    ui.end_row();
+
+                    ui.label("Load");
+                    ui.label(health.load.map_or_else(missing, |[one, five, fifteen]| {
+                        format!("{:.2} {:.2} {:.2}", one, five, fifteen)
+                    }));
+                    ui.end_row();
+
+                    ui.label("Memory");
+                    ui.label(match (health.mem_total_kb, health.mem_available_kb) {
+                        (Some(total), Some(available)) => format!(
+                            "{} of {} MB used",
+                            (total - available.min(total)) / 1024,
+                            total / 1024
+                        ),
+                        _ => missing(),
+                    });
+                    ui.end_row();
+
+                    ui.label("Throttling");
+                    let (now, since_boot) = health.throttling();
+                    if health.throttled.is_none() {
+                        ui.label(missing());
+                    } else if !now.is_empty() {
+                        ui.colored_label(Color32::RED, now.join(", "));
+                    } else if !since_boot.is_empty() {
+                        ui.colored_label(
+                            Color32::YELLOW,
+                            format!("earlier: {}", since_boot.join(", ")),
+                        );
+                    } else {
+                        ui.colored_label(Color32::GREEN, "none");
+                    }
+                    ui.end_row();
+
+                    ui.label("Wi-Fi");
+                    match &health.wifi {
+                        Some(wifi) => {
+                            let text = format!(
+                                "{} {:.0} dBm, quality {:.0}/70",
+                                wifi.interface, wifi.signal_dbm, wifi.link_quality
+                            );
+                            if wifi.signal_dbm < WEAK_SIGNAL_DBM {
+                                ui.colored_label(Color32::YELLOW, text);
+                            } else {
+                                ui.label(text);
+                            }
+                        }
+                        None => {
+                            ui.label(missing());
+                        }
+                    }
+                    ui.end_row();
+
+                    ui.label("GPS");
+                    match &gps {
+                        Some(gps) if gps.fix.has_position() => {
+                            ui.label(format!(
+                                "{}, {} satellites",
+                                gps.fix.describe(),
+                                gps.satellites
+                            ));
+                            ui.end_row();
+                            ui.label("Position");
+                            let accuracy = gps
+                                .h_acc_m
+                                .map_or_else(String::new, |acc| format!(" ±{:.1} m", acc));
+                            ui.label(format!(
+                                "{:.6}, {:.6}{}, {:.1} m",
+                                gps.lat, gps.lon, accuracy, gps.alt_m
+                            ));
+                            ui.end_row();
+                            ui.label("Ground speed");
+                            ui.label(format!(
+                                "{:.1} m/s towards {:.0}°",
+                                gps.speed_m_s, gps.course_deg
+                            ));
+                        }
+                        Some(gps) => {
+                            ui.colored_label(
+                                Color32::YELLOW,
+                                format!("{}, {} satellites", gps.fix.describe(), gps.satellites),
+                            );
+                        }
+                        None => {
+                            ui.label(missing());
+                        }
+                    }
+                    ui.end_row();
+                });
+            });
+    }
+}
@@ -0,0 +1,62 @@
+use rustls::{ClientConfig, RootCertStore};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+// Read from the working directory, the defaults are used without it
+const CONFIG_PATH: &str = "ground.toml";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // `ws://` or `wss://` address of the bridge
+    pub url: String,
+    // Sent as `Authorization: Bearer <token>`, `auth.token` or this station's key on the bridge
+    pub token: Option<String>,
+    // PEM certificate the bridge's `wss://` certificate is checked against,
+    // for a self-signed one. The public web roots are used without it.
+    pub ca_cert: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            url: "ws://192.168.1.107:8765".to_string(),
+            token: None,
+            ca_cert: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Config, String> {
+        match fs::read_to_string(CONFIG_PATH) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", CONFIG_PATH, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("failed to read {}: {}", CONFIG_PATH, e)),
+        }
+    }
+
+    // TLS trusting only `ca_cert`, `None` for the default
+    pub fn tls(&self) -> Result<Option<Arc<ClientConfig>>, String> {
+        let Some(path) = &self.ca_cert else {
+            return Ok(None);
+        };
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+        let pem = fs::read(path).map_err(|e| error(&e))?;
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+            let cert = cert.map_err(|e| error(&e))?;
+            roots.add(cert).map_err(|e| error(&e))?;
+        }
+        if roots.is_empty() {
+            return Err(error(&"no certificate"));
+        }
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Some(Arc::new(config)))
+    }
+}
@@ -0,0 +1,223 @@
+use eframe::egui;
+use epaint::Color32;
+use protocol::{Command, CommandAck, CommandStatus, SequencedCommand};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// The bridge reports a failure itself after its retries, this only catches a
+// bridge that stopped answering
+const BRIDGE_TIMEOUT: Duration = Duration::from_secs(15);
+const MAX_KEPT: usize = 50;
+
+// Commands sent with a sequence number and what the bridge reported about them
+#[derive(Debug, Clone, Default)]
+pub struct Deliveries {
+    next_seq: u32,
+    recent: VecDeque<Delivery>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    pub seq: u32,
+    pub command: Command,
+    pub status: CommandStatus,
+    pub reason: Option<String>,
+    updated: Instant,
+}
+
+impl Deliveries {
+    // Returns the message to send to the bridge
+    pub fn track(&mut self, command: Command) -> String {
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let line = command.encode();
+        if self.recent.len() == MAX_KEPT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(Delivery {
+            seq: self.next_seq,
+            command,
+            status: CommandStatus::Sent,
+            reason: None,
+            updated: Instant::now(),
+        });
+        SequencedCommand {
+            seq: self.next_seq,
+            line,
+        }
+        .encode()
+    }
+
+    pub fn update(&mut self, ack: CommandAck) {
+        if let Some(delivery) = self.recent.iter_mut().find(|d| d.seq == ack.seq) {
+            delivery.status = ack.status;
+            delivery.reason = ack.reason;
+            delivery.updated = Instant::now();
+        }
+    }
+
+    // Fails everything still open, e.g. when the connection to the bridge drops
+    pub fn fail_open(&mut self, reason: &str) {
+        for delivery in self.recent.iter_mut().filter(|d| d.is_open()) {
+            delivery.status = CommandStatus::Failed;
+            delivery.reason = Some(reason.to_string());
+        }
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        for delivery in self.recent.iter_mut() {
+            if delivery.is_open() && now.duration_since(delivery.updated) > BRIDGE_TIMEOUT {
+                delivery.status = CommandStatus::Failed;
+                delivery.reason = Some("no report from the bridge".to_string());
+            }
+        }
+    }
+
+    // Most recent command `matches` accepts
+    pub fn latest(&self, matches: impl Fn(&Command) -> bool) -> Option<&Delivery> {
+        self.recent.iter().rev().find(|d| matches(&d.command))
+    }
+}
+
+impl Delivery {
+    fn is_open(&self) -> bool {
+        matches!(self.status, CommandStatus::Sent | CommandStatus::Delivered)
+    }
+
+    pub fn label(&self, ui: &mut egui::Ui) {
+        let (text, color) = match self.status {
+            CommandStatus::Sent => ("sent", Color32::GRAY),
+            CommandStatus::Delivered => ("delivered", Color32::YELLOW),
+            CommandStatus::Acknowledged => ("acknowledged", Color32::GREEN),
+            CommandStatus::Failed => ("failed", Color32::RED),
+        };
+        let text = match &self.reason {
+            Some(reason) => format!("#{} {} ({})", self.seq, text, reason),
+            None => format!("#{} {}", self.seq, text),
+        };
+        ui.colored_label(color, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ack(seq: u32, status: CommandStatus, reason: Option<&str>) -> CommandAck {
+        CommandAck {
+            seq,
+            status,
+            reason: reason.map(str::to_string),
+        }
+    }
+
+    fn status(deliveries: &Deliveries, command: Command) -> (u32, CommandStatus, Option<String>) {
+        let delivery = deliveries.latest(|c| *c == command).unwrap();
+        (delivery.seq, delivery.status, delivery.reason.clone())
+    }
+
+    #[test]
+    fn acknowledges_answered_commands() {
+        let mut deliveries = Deliveries::default();
+        assert_eq!(deliveries.track(Command::Arm), "seq->1:command->arm\n");
+        assert_eq!(deliveries.track(Command::Abort), "seq->2:command->abort\n");
+        assert_eq!(
+            status(&deliveries, Command::Arm),
+            (1, CommandStatus::Sent, None)
+        );
+
+        deliveries.update(ack(1, CommandStatus::Delivered, None));
+        assert_eq!(
+            status(&deliveries, Command::Arm).1,
+            CommandStatus::Delivered
+        );
+        deliveries.update(ack(1, CommandStatus::Acknowledged, None));
+        assert_eq!(
+            status(&deliveries, Command::Arm).1,
+            CommandStatus::Acknowledged
+        );
+
+        // Reports for other or unknown commands change nothing
+        deliveries.update(ack(7, CommandStatus::Failed, None));
+        assert_eq!(status(&deliveries, Command::Abort).1, CommandStatus::Sent);
+    }
+
+    #[test]
+    fn retries_then_fails() {
+        let mut deliveries = Deliveries::default();
+        deliveries.track(Command::Arm);
+
+        deliveries.update(ack(1, CommandStatus::Delivered, None));
+        deliveries.update(ack(1, CommandStatus::Sent, Some("attempt 2 of 3")));
+        assert_eq!(
+            status(&deliveries, Command::Arm),
+            (1, CommandStatus::Sent, Some("attempt 2 of 3".to_string()))
+        );
+
+        let reason = "no answer from the flight controller after 3 attempt(s)";
+        deliveries.update(ack(1, CommandStatus::Failed, Some(reason)));
+        assert_eq!(
+            status(&deliveries, Command::Arm),
+            (1, CommandStatus::Failed, Some(reason.to_string()))
+        );
+    }
+
+    #[test]
+    fn times_out_without_reports() {
+        let mut deliveries = Deliveries::default();
+        deliveries.track(Command::Arm);
+        deliveries.track(Command::Reboot);
+        deliveries.update(ack(2, CommandStatus::Acknowledged, None));
+
+        deliveries.expire(Instant::now());
+        assert_eq!(status(&deliveries, Command::Arm).1, CommandStatus::Sent);
+
+        deliveries.expire(Instant::now() + BRIDGE_TIMEOUT + Duration::from_secs(1));
+        assert_eq!(
+            status(&deliveries, Command::Arm),
+            (
+                1,
+                CommandStatus::Failed,
+                Some("no report from the bridge".to_string())
+            )
+        );
+        // Finished commands keep their status
+        assert_eq!(
+            status(&deliveries, Command::Reboot).1,
+            CommandStatus::Acknowledged
+        );
+    }
+
+    #[test]
+    fn fails_open_commands_on_disconnect() {
+        let mut deliveries = Deliveries::default();
+        deliveries.track(Command::Arm);
+        deliveries.track(Command::Abort);
+        deliveries.update(ack(1, CommandStatus::Acknowledged, None));
+        deliveries.update(ack(2, CommandStatus::Delivered, None));
+
+        deliveries.fail_open("connection lost");
+        assert_eq!(
+            status(&deliveries, Command::Arm).1,
+            CommandStatus::Acknowledged
+        );
+        assert_eq!(
+            status(&deliveries, Command::Abort),
+            (
+                2,
+                CommandStatus::Failed,
+                Some("connection lost".to_string())
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_the_latest_commands() {
+        let mut deliveries = Deliveries::default();
+        for _ in 0..MAX_KEPT + 5 {
+            deliveries.track(Command::Arm);
+        }
+        assert_eq!(deliveries.recent.len(), MAX_KEPT);
+        assert_eq!(deliveries.recent.front().unwrap().seq, 6);
+        assert_eq!(status(&deliveries, Command::Arm).0, MAX_KEPT as u32 + 5);
+    }
+}
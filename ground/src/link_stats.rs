@@ -0,0 +1,219 @@
+use protocol::{Ping, Pong};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Upper bounds of the round-trip histogram buckets, the last bucket is open
+pub const RTT_BUCKETS_MS: [u64; 6] = [10, 20, 50, 100, 200, 500];
+// Pings without a pong after this are counted as dropped
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+// Round trips above this are counted as late
+const LATE_RTT: Duration = Duration::from_millis(200);
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Link quality between the ground and the bridge, measured by the WebSocket thread
+#[derive(Debug, Clone, Default)]
+pub struct LinkStats {
+    next_seq: u32,
+    outstanding: VecDeque<(u32, Instant)>,
+    pub sent: u64,
+    pub answered: u64,
+    pub dropped: u64,
+    pub late: u64,
+    pub last_rtt: Option<Duration>,
+    pub min_rtt: Option<Duration>,
+    pub max_rtt: Option<Duration>,
+    rtt_total: Duration,
+    // One count per bucket of `RTT_BUCKETS_MS`, plus one for anything slower
+    pub histogram: [u64; RTT_BUCKETS_MS.len() + 1],
+    messages: VecDeque<Instant>,
+    telemetry: VecDeque<Instant>,
+    // Messages from the bridge, or serial lines in them, that could not be parsed
+    pub parse_errors: u64,
+    // Latest serial line the bridge could not classify
+    pub last_unknown: Option<String>,
+    // Serial counters of the bridge from the latest pong
+    pub bridge: Option<Pong>,
+}
+
+impl LinkStats {
+    pub fn ping(&mut self, now: Instant) -> Ping {
+        self.expire(now);
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.outstanding.push_back((self.next_seq, now));
+        self.sent += 1;
+        Ping { seq: self.next_seq }
+    }
+
+    pub fn pong(&mut self, pong: Pong, now: Instant) {
+        self.bridge = Some(pong);
+        let Some(index) = self
+            .outstanding
+            .iter()
+            .position(|(seq, _)| *seq == pong.seq)
+        else {
+            // Already counted as dropped, or from before a reconnect
+            return;
+        };
+        let (_, sent) = self.outstanding.remove(index).unwrap();
+        // Pings are answered in order, older ones are lost
+        for _ in 0..index {
+            self.outstanding.pop_front();
+            self.dropped += 1;
+        }
+
+        let rtt = now.duration_since(sent);
+        self.answered += 1;
+        self.last_rtt = Some(rtt);
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        self.max_rtt = Some(self.max_rtt.map_or(rtt, |max| max.max(rtt)));
+        self.rtt_total += rtt;
+        if rtt > LATE_RTT {
+            self.late += 1;
+        }
+        let bucket = RTT_BUCKETS_MS
+            .iter()
+            .position(|bound| rtt.as_millis() < *bound as u128)
+            .unwrap_or(RTT_BUCKETS_MS.len());
+        self.histogram[bucket] += 1;
+    }
+
+    pub fn mean_rtt(&self) -> Option<Duration> {
+        (self.answered > 0).then(|| self.rtt_total / self.answered as u32)
+    }
+
+    // Percentage of the pings that got no pong, pings still waiting are not counted
+    pub fn loss(&self) -> Option<f64> {
+        let finished = self.answered + self.dropped;
+        (finished > 0).then(|| self.dropped as f64 * 100.0 / finished as f64)
+    }
+
+    // Counts a message from the bridge, `telemetry` if it carried flight data
+    pub fn received(&mut self, now: Instant, telemetry: bool) {
+        self.messages.push_back(now);
+        if telemetry {
+            self.telemetry.push_back(now);
+        }
+        self.expire(now);
+    }
+
+    pub fn parse_error(&mut self) {
+        self.parse_errors += 1;
+    }
+
+    pub fn unknown_line(&mut self, line: &str) {
+        self.parse_error();
+        self.last_unknown = Some(line.to_string());
+    }
+
+    // Messages and telemetry per second over the last second
+    pub fn rates(&self, now: Instant) -> (usize, usize) {
+        let recent = |times: &VecDeque<Instant>| {
+            times
+                .iter()
+                .filter(|time| now.duration_since(**time) <= RATE_WINDOW)
+                .count()
+        };
+        (recent(&self.messages), recent(&self.telemetry))
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((_, sent)) = self.outstanding.front() {
+            if now.duration_since(*sent) <= PING_TIMEOUT {
+                break;
+            }
+            self.outstanding.pop_front();
+            self.dropped += 1;
+        }
+        for times in [&mut self.messages, &mut self.telemetry] {
+            while times
+                .front()
+                .is_some_and(|time| now.duration_since(*time) > RATE_WINDOW)
+            {
+                times.pop_front();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pong(seq: u32) -> Pong {
+        Pong {
+            seq,
+            serial_lines: 0,
+            serial_errors: 0,
+        }
+    }
+
+    #[test]
+    fn sorts_round_trips_into_buckets() {
+        let mut stats = LinkStats::default();
+        let start = Instant::now();
+        for (i, rtt_ms) in [5, 10, 19, 50, 150, 499, 500, 2000].into_iter().enumerate() {
+            let sent = start + Duration::from_secs(i as u64);
+            let ping = stats.ping(sent);
+            stats.pong(pong(ping.seq), sent + Duration::from_millis(rtt_ms));
+        }
+
+        assert_eq!(stats.histogram, [1, 2, 0, 1, 1, 1, 2]);
+        assert_eq!(stats.answered, 8);
+        assert_eq!(stats.late, 3);
+        assert_eq!(stats.min_rtt, Some(Duration::from_millis(5)));
+        assert_eq!(stats.max_rtt, Some(Duration::from_millis(2000)));
+        assert_eq!(stats.last_rtt, Some(Duration::from_millis(2000)));
+        assert_eq!(stats.loss(), Some(0.0));
+    }
+
+    #[test]
+    fn counts_missing_pongs_as_loss() {
+        let mut stats = LinkStats::default();
+        let start = Instant::now();
+        assert_eq!(stats.loss(), None);
+
+        let pings: Vec<Ping> = (0..4)
+            .map(|i| stats.ping(start + Duration::from_millis(i * 100)))
+            .collect();
+        // The first two pongs never arrive, the third one answers in order
+        stats.pong(pong(pings[2].seq), start + Duration::from_millis(250));
+        assert_eq!((stats.answered, stats.dropped), (1, 2));
+        assert_eq!(stats.mean_rtt(), Some(Duration::from_millis(50)));
+
+        // A pong for a ping already counted as dropped changes nothing
+        stats.pong(pong(pings[0].seq), start + Duration::from_millis(260));
+        assert_eq!((stats.answered, stats.dropped), (1, 2));
+
+        let loss = stats.loss().unwrap();
+        assert!((loss - 200.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.sent, 4);
+    }
+
+    #[test]
+    fn expires_old_pings_and_messages() {
+        let mut stats = LinkStats::default();
+        let start = Instant::now();
+        stats.ping(start);
+        stats.received(start, true);
+        stats.received(start + Duration::from_millis(500), false);
+        assert_eq!(stats.rates(start + Duration::from_millis(500)), (2, 1));
+
+        // The first message leaves the rate window, the ping is still in time
+        let later = start + Duration::from_millis(1200);
+        stats.received(later, false);
+        assert_eq!(stats.rates(later), (2, 0));
+        assert_eq!(stats.dropped, 0);
+
+        // No pong within the timeout, the next ping counts it as dropped
+        let ping = stats.ping(start + PING_TIMEOUT + Duration::from_millis(1));
+        assert_eq!((stats.dropped, stats.outstanding.len()), (1, 1));
+        assert_eq!(stats.loss(), Some(100.0));
+
+        stats.pong(
+            pong(ping.seq),
+            start + PING_TIMEOUT + Duration::from_millis(11),
+        );
+        assert_eq!(stats.histogram[1], 1);
+        assert_eq!(stats.loss(), Some(50.0));
+    }
+}
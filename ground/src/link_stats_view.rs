@@ -0,0 +1,106 @@
+use eframe::egui;
+use egui_plot::{Bar, BarChart, Plot};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::data::ReceivedData;
+use crate::link_stats::RTT_BUCKETS_MS;
+
+fn millis(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format!("{:.1} ms", duration.as_secs_f32() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct LinkStatsView {
+    pub open: bool,
+}
+
+impl LinkStatsView {
+    pub fn window(&mut self, ctx: &egui::Context, received_data: &Arc<Mutex<ReceivedData>>) {
+        let link = received_data.lock().unwrap().link.clone();
+        let (messages, telemetry) = link.rates(Instant::now());
+
+        egui::Window::new("Link Statistics")
+            .open(&mut self.open)
+            .resizable(true)
+            .default_size([360.0, 420.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("link_stats").striped(true).show(ui, |ui| {
+                    ui.label("Round trip");
+                    ui.label(format!(
+                        "last {}, mean {}, min {}, max {}",
+                        millis(link.last_rtt),
+                        millis(link.mean_rtt()),
+                        millis(link.min_rtt),
+                        millis(link.max_rtt)
+                    ));
+                    ui.end_row();
+
+                    ui.label("Pings");
+                    ui.label(format!(
+                        "{} sent, {} answered, {} dropped ({}), {} late",
+                        link.sent,
+                        link.answered,
+                        link.dropped,
+                        link.loss()
+                            .map_or("-".to_string(), |loss| format!("{:.1}% loss", loss)),
+                        link.late
+                    ));
+                    ui.end_row();
+
+                    ui.label("Packet rate");
+                    ui.label(format!("{} msg/s, {} telemetry/s", messages, telemetry));
+                    ui.end_row();
+
+                    ui.label("Parse errors");
+                    ui.label(match link.bridge {
+                        Some(bridge) => format!(
+                            "{} on the ground, {} of {} serial lines on the bridge",
+                            link.parse_errors, bridge.serial_errors, bridge.serial_lines
+                        ),
+                        None => format!("{} on the ground", link.parse_errors),
+                    });
+                    ui.end_row();
+
+                    if let Some(line) = &link.last_unknown {
+                        ui.label("Last unparsed line");
+                        ui.label(format!("{:?}", line));
+                        ui.end_row();
+                    }
+                });
+
+                ui.separator();
+                ui.label("Round trip histogram");
+                let bars = link
+                    .histogram
+                    .iter()
+                    .enumerate()
+                    .map(|(i, count)| Bar::new(i as f64, *count as f64).width(0.8))
+                    .collect();
+                Plot::new("rtt_histogram")
+                    .height(200.0)
+                    .allow_drag(false)
+                    .allow_zoom(false)
+                    .allow_scroll(false)
+                    .x_axis_formatter(|mark, _, _| {
+                        let i = mark.value.round() as usize;
+                        if (mark.value - i as f64).abs() > 0.01 {
+                            return String::new();
+                        }
+                        match (
+                            i.checked_sub(1).map(|j| RTT_BUCKETS_MS[j]),
+                            RTT_BUCKETS_MS.get(i),
+                        ) {
+                            (_, Some(upper)) if i == 0 => format!("<{}", upper),
+                            (Some(lower), Some(upper)) => format!("{}-{}", lower, upper),
+                            (Some(lower), None) => format!("≥{}", lower),
+                            _ => String::new(),
+                        }
+                    })
+                    .show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars)));
+            });
+    }
+}
@@ -0,0 +1,105 @@
+use crate::data::ReceivedData;
+use crossbeam_channel::Sender;
+use eframe::egui;
+use protocol::{LogReply, LogRequest};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// Downloaded flight logs are saved here, relative to the working directory
+const DOWNLOAD_DIR: &str = "flight_logs";
+
+// Lists and downloads the flight logs recorded by the bridge
+#[derive(Clone)]
+pub struct LogsView {
+    pub ui_to_drone_tx: Sender<String>,
+}
+
+impl LogsView {
+    pub fn new(ui_to_drone_tx: Sender<String>) -> Self {
+        Self { ui_to_drone_tx }
+    }
+
+    pub fn window(&mut self, ctx: &egui::Context, received_data: &Arc<Mutex<ReceivedData>>) {
+        egui::Window::new("Flight Logs")
+            .resizable(true)
+            .default_size([400.0, 300.0])
+            .show(ctx, |ui| {
+                let (files, status) = {
+                    let data = received_data.lock().unwrap();
+                    (data.log_files.clone(), data.log_status.clone())
+                };
+
+                ui.horizontal(|ui| {
+                    if ui.button("Refresh").clicked() {
+                        self.ui_to_drone_tx
+                            .send(LogRequest::List.encode())
+                            .expect("Failed to send log list request");
+                    }
+                    ui.label(status);
+                });
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for file in files.iter().rev() {
+                        ui.horizontal(|ui| {
+                            ui.label(&file.name);
+                            ui.label(format!("{:.1} MB", file.size as f64 / (1024.0 * 1024.0)));
+                            if ui.button("Download").clicked() {
+                                self.ui_to_drone_tx
+                                    .send(LogRequest::Get(file.name.clone()).encode())
+                                    .expect("Failed to send log download request");
+                            }
+                        });
+                    }
+                });
+            });
+    }
+}
+
+// Called from the WebSocket thread for every log reply from the bridge
+pub fn handle_reply(reply: LogReply, received_data: &Mutex<ReceivedData>) {
+    let status = match reply {
+        LogReply::List { files } => {
+            let status = format!("{} logs on the bridge", files.len());
+            received_data.lock().unwrap().log_files = files;
+            status
+        }
+        LogReply::Chunk {
+            name,
+            offset,
+            data,
+            last,
+        } => match save_chunk(&name, offset, &data) {
+            Ok(path) if last => format!("Saved {}", path.display()),
+            Ok(_) => format!(
+                "Downloading {} ({} KB)",
+                name,
+                (offset + data.len() as u64) / 1024
+            ),
+            Err(e) => format!("Failed to save {}: {}", name, e),
+        },
+        LogReply::Error { message } => format!("Bridge error: {}", message),
+    };
+    received_data.lock().unwrap().log_status = status;
+}
+
+fn save_chunk(name: &str, offset: u64, data: &str) -> std::io::Result<PathBuf> {
+    // Never trust the name to stay inside the download directory
+    let file_name = Path::new(name)
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid log name"))?;
+    fs::create_dir_all(DOWNLOAD_DIR)?;
+    let path = Path::new(DOWNLOAD_DIR).join(file_name);
+
+    // The first chunk replaces any earlier download of the same log
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(offset > 0)
+        .truncate(offset == 0)
+        .open(&path)?;
+    file.write_all(data.as_bytes())?;
+    Ok(path)
+}
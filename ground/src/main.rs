@@ -3,29 +3,178 @@ mod app;
 mod attitude_view;
 mod chat_view;
 mod commands_view;
+mod companion_view;
+mod config;
 mod data;
+mod deliveries;
 mod drone_view;
+mod link_stats;
+mod link_stats_view;
+mod logs_view;
+mod notes;
 mod pid_view;
 mod rc_control;
 mod rc_view;
-mod notes;
 
 use app::MyApp;
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use data::{ReceivedData, SerialData};
+use config::Config;
+use crossbeam_channel::{unbounded, Sender};
+use data::ReceivedData;
 use eframe::egui;
-use serde_json::Value;
+use protocol::{ControlRequest, ControlStatus, LineKind, Severity, Telemetry};
+use std::io;
+use std::net::TcpStream;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
-use tungstenite::{connect, Message};
+use std::time::{Duration, Instant};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::http::header::{HeaderValue, AUTHORIZATION};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{client_tls_with_config, Connector, Message, WebSocket};
 
-const WEBSOCKET_URL: &str = "ws://192.168.1.107:8765";
 const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_millis(20);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(100);
+const PING_INTERVAL: Duration = Duration::from_millis(500);
+
+// Applies one message from the bridge to the shared state and the chat
+fn handle_telemetry(
+    telemetry: Telemetry,
+    app: &Mutex<MyApp>,
+    received_data: &Arc<Mutex<ReceivedData>>,
+    drone_to_ui_sender: &Sender<String>,
+) {
+    let now = Instant::now();
+    // Update the last received time
+    app.lock().unwrap().update_last_received_time();
+
+    {
+        let mut data = received_data.lock().unwrap();
+        data.aruco_ids = telemetry.aruco_ids.clone();
+        data.markers = telemetry.markers.clone();
+        data.control = telemetry.control;
+        data.command_queue = telemetry.command_queue;
+        data.companion = telemetry.companion.clone();
+        data.gps = telemetry.gps.clone();
+        if let Some(ack) = telemetry.ack.clone() {
+            data.deliveries.update(ack);
+        }
+        if let Some(pong) = telemetry.pong {
+            data.link.pong(pong, now);
+        }
+    }
+
+    if let Some(event) = &telemetry.event {
+        drone_to_ui_sender
+            .send(format!("[bridge] {}", event.describe()))
+            .expect("Failed to send chat message");
+    }
+
+    if let Some(reply) = telemetry.logs.clone() {
+        logs_view::handle_reply(reply, received_data);
+    }
+
+    // The bridge tells what each serial line is
+    let mut data = received_data.lock().unwrap();
+    let chat = match &telemetry.line_kind {
+        // Status-only update from the bridge
+        None => {
+            data.link.received(now, false);
+            None
+        }
+        Some(LineKind::Telemetry) => {
+            match telemetry.serial_data() {
+                Ok(serial_data) => {
+                    data.serial_data = serial_data;
+                    data.link.received(now, true);
+                }
+                Err(_) => {
+                    data.link.received(now, false);
+                    data.link.unknown_line(&telemetry.serial_data);
+                }
+            }
+            None
+        }
+        Some(LineKind::Log { severity }) => {
+            data.link.received(now, false);
+            Some(match severity {
+                Severity::Info => telemetry.serial_data.clone(),
+                Severity::Warning => format!("[warning] {}", telemetry.serial_data),
+                Severity::Error => format!("[error] {}", telemetry.serial_data),
+            })
+        }
+        Some(LineKind::Ack { .. }) => {
+            data.link.received(now, false);
+            data.last_answer = Some(telemetry.serial_data.clone());
+            Some(telemetry.serial_data.clone())
+        }
+        Some(LineKind::Unknown) => {
+            data.link.received(now, false);
+            data.link.unknown_line(&telemetry.serial_data);
+            None
+        }
+    };
+    drop(data);
+
+    if let Some(message) = chat {
+        drone_to_ui_sender
+            .send(message)
+            .expect("Failed to send chat message");
+    }
+}
+
+// Opens the WebSocket to the bridge, sending the token from the config
+fn connect(
+    config: &Config,
+    tls: Option<&Arc<rustls::ClientConfig>>,
+) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, Box<tungstenite::Error>> {
+    let mut request = config.url.as_str().into_client_request()?;
+    if let Some(token) = &config.token {
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(tungstenite::Error::from)?;
+        request.headers_mut().insert(AUTHORIZATION, value);
+    }
+    let uri = request.uri();
+    let host = uri.host().unwrap_or_default();
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("wss") {
+            443
+        } else {
+            80
+        });
+    let stream = TcpStream::connect((host, port)).map_err(tungstenite::Error::Io)?;
+    let connector = tls.map(|tls| Connector::Rustls(Arc::clone(tls)));
+    match client_tls_with_config(request, stream, None, connector) {
+        Ok((socket, _)) => Ok(socket),
+        Err(HandshakeError::Failure(e)) => Err(Box::new(e)),
+        // Only happens on non-blocking streams
+        Err(HandshakeError::Interrupted(_)) => {
+            Err(Box::new(io::Error::from(io::ErrorKind::WouldBlock).into()))
+        }
+    }
+}
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init();
 
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    let tls = match config.tls() {
+        Ok(tls) => tls,
+        Err(e) => {
+            eprintln!("Failed to load the bridge certificate {}", e);
+            std::process::exit(2);
+        }
+    };
+
     let received_data = Arc::new(Mutex::new(ReceivedData::default()));
     let received_data_clone = Arc::clone(&received_data);
 
@@ -45,65 +194,82 @@ fn main() -> Result<(), eframe::Error> {
 
     thread::spawn(move || {
         loop {
-            // let url = Url::parse(WEBSOCKET_URL).expect("Failed to parse WebSocket URL");
-            match connect(WEBSOCKET_URL) {
-                Ok((mut socket, _)) => {
+            match connect(&config, tls.as_ref()) {
+                Ok(mut socket) => {
                     println!("Connected to WebSocket server");
                     app_clone.lock().unwrap().update_connection_status(true);
 
+                    // Wake up regularly even when the bridge is silent, so heartbeats
+                    // and UI messages still go out
+                    match socket.get_mut() {
+                        MaybeTlsStream::Plain(stream) => {
+                            let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+                        }
+                        MaybeTlsStream::Rustls(stream) => {
+                            let _ = stream.sock.set_read_timeout(Some(READ_TIMEOUT));
+                        }
+                        _ => {}
+                    }
+                    let mut last_heartbeat = Instant::now();
+                    let mut last_ping = Instant::now();
+
                     loop {
                         match socket.read() {
-                            Ok(msg) => {
-                                let msg_str = msg.to_string();
-                                let json_value: Value =
-                                    serde_json::from_str(&msg_str).expect("Error parsing JSON");
-
-                                // Update the last received time
-                                app_clone.lock().unwrap().update_last_received_time();
-
-                                if let Some(aruco_ids) = json_value["aruco_ids"].as_array() {
-                                    let deserialized_ids: Vec<u32> = aruco_ids
-                                        .iter()
-                                        .map(|id| id.as_u64().unwrap() as u32)
-                                        .collect();
-
-                                    let mut data = received_data_clone.lock().unwrap();
-                                    data.aruco_ids = deserialized_ids;
+                            Ok(msg) => match Telemetry::decode(&msg.to_string()) {
+                                Ok(telemetry) => handle_telemetry(
+                                    telemetry,
+                                    &app_clone,
+                                    &received_data_clone,
+                                    &drone_to_ui_sender,
+                                ),
+                                Err(e) => {
+                                    println!("Error parsing message from the bridge: {}", e);
+                                    received_data_clone.lock().unwrap().link.parse_error();
                                 }
-
-                                if let Some(serial_data_str) = json_value["serial_data"].as_str() {
-                                    if let Ok(serial_data) =
-                                        serde_json::from_str::<SerialData>(serial_data_str)
-                                    {
-                                        let mut data = received_data_clone.lock().unwrap();
-                                        data.serial_data = serial_data;
-                                    } else {
-                                        // If it's not SerialData, treat it as a chat message, if it does not have `":`
-
-                                        if !serial_data_str.contains(":") {
-                                            drone_to_ui_sender
-                                                .send(serial_data_str.to_string())
-                                                .expect("Failed to send chat message");
-                                        }
-                                    }
-                                }
-
-                                if let Ok(ui_message) = ui_to_drone_receiver.try_recv() {
-                                    if socket.can_write() {
-                                        match socket.send(Message::Text(ui_message)) {
-                                            Ok(_) => {}
-                                            Err(e) => println!("Failed to send message: {:?}", e),
-                                        }
-                                    } else {
-                                        println!("Socket is not ready for writing");
-                                    }
-                                }
-                            }
+                            },
+                            Err(tungstenite::Error::Io(e))
+                                if matches!(
+                                    e.kind(),
+                                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                                ) => {}
                             Err(e) => {
                                 println!("Error reading from WebSocket: {:?}", e);
                                 break;
                             }
                         }
+
+                        // Keep the bridge failsafe from triggering while we hold control
+                        let is_pilot = {
+                            let mut data = received_data_clone.lock().unwrap();
+                            data.deliveries.expire(Instant::now());
+                            data.control == Some(ControlStatus::Pilot)
+                        };
+                        if last_ping.elapsed() > PING_INTERVAL {
+                            last_ping = Instant::now();
+                            let ping = received_data_clone.lock().unwrap().link.ping(last_ping);
+                            if let Err(e) = socket.send(Message::Text(ping.encode())) {
+                                println!("Failed to send ping: {:?}", e);
+                            }
+                        }
+                        if is_pilot && last_heartbeat.elapsed() > HEARTBEAT_INTERVAL {
+                            last_heartbeat = Instant::now();
+                            if let Err(e) =
+                                socket.send(Message::Text(ControlRequest::Heartbeat.encode()))
+                            {
+                                println!("Failed to send heartbeat: {:?}", e);
+                            }
+                        }
+
+                        if let Ok(ui_message) = ui_to_drone_receiver.try_recv() {
+                            if socket.can_write() {
+                                match socket.send(Message::Text(ui_message)) {
+                                    Ok(_) => {}
+                                    Err(e) => println!("Failed to send message: {:?}", e),
+                                }
+                            } else {
+                                println!("Socket is not ready for writing");
+                            }
+                        }
                     }
                 }
                 Err(e) => {
@@ -111,6 +277,11 @@ fn main() -> Result<(), eframe::Error> {
                 }
             }
 
+            received_data_clone
+                .lock()
+                .unwrap()
+                .deliveries
+                .fail_open("connection to the bridge lost");
             app_clone.lock().unwrap().update_connection_status(false);
             app_clone.lock().unwrap().increment_connection_attempts();
             println!(
@@ -6,7 +6,6 @@ use std::time::{Duration, Instant};
 
 #[derive(Clone)]
 pub struct NoteEditorView {
-    open: bool,
     content: String,
     file_path: PathBuf,
     status_message: String,
@@ -20,9 +19,10 @@ pub struct NoteEditorView {
 impl NoteEditorView {
     pub fn new(file_path: PathBuf) -> Self {
         let content = fs::read_to_string(&file_path).unwrap_or_else(|_| String::new());
-        let last_modified = fs::metadata(&file_path).ok().and_then(|m| m.modified().ok());
+        let last_modified = fs::metadata(&file_path)
+            .ok()
+            .and_then(|m| m.modified().ok());
         Self {
-            open: false,
             content,
             file_path,
             status_message: String::new(),
@@ -34,18 +34,8 @@ impl NoteEditorView {
         }
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
-        if ui
-            .button(if self.open { "Close Note Editor" } else { "Open Note Editor" })
-            .clicked()
-        {
-            self.open = !self.open;
-        }
-    }
-
     pub fn window(&mut self, ctx: &egui::Context) {
         egui::Window::new("Note Editor")
-            // .open(&mut self.open)
             .resizable(true)
             .max_size([600.0, 400.0])
             .show(ctx, |ui| {
@@ -58,7 +48,8 @@ impl NoteEditorView {
                 );
 
                 // Check if content has changed
-                if ui.input(|i| i.key_pressed(egui::Key::Enter) || i.key_pressed(egui::Key::Space)) {
+                if ui.input(|i| i.key_pressed(egui::Key::Enter) || i.key_pressed(egui::Key::Space))
+                {
                     content_changed = true;
                 }
 
@@ -96,7 +87,9 @@ impl NoteEditorView {
                     self.status_message = format!("Failed to write to file: {}", e);
                 } else {
                     self.status_message = "File saved successfully".to_string();
-                    self.last_modified = fs::metadata(&self.file_path).ok().and_then(|m| m.modified().ok());
+                    self.last_modified = fs::metadata(&self.file_path)
+                        .ok()
+                        .and_then(|m| m.modified().ok());
                 }
             }
             Err(e) => {
@@ -110,7 +103,9 @@ impl NoteEditorView {
             Ok(content) => {
                 self.content = content;
                 self.status_message = "File reloaded successfully".to_string();
-                self.last_modified = fs::metadata(&self.file_path).ok().and_then(|m| m.modified().ok());
+                self.last_modified = fs::metadata(&self.file_path)
+                    .ok()
+                    .and_then(|m| m.modified().ok());
             }
             Err(e) => {
                 self.status_message = format!("Failed to read file: {}", e);
@@ -123,9 +118,10 @@ impl NoteEditorView {
             if let Ok(modified) = metadata.modified() {
                 if self.last_modified.map_or(true, |last| modified > last) {
                     self.reload_file();
-                    self.status_message = "File automatically reloaded due to external changes".to_string();
+                    self.status_message =
+                        "File automatically reloaded due to external changes".to_string();
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
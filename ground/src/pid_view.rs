@@ -1,4 +1,5 @@
 use eframe::egui;
+use protocol::Command;
 use std::sync::{Arc, Mutex};
 
 use crate::data::ReceivedData;
@@ -9,13 +10,11 @@ pub struct PIDControlView {
     last_sent_time: std::time::Instant,
     enabled_transmit: bool,
     ui_to_drone_tx: crossbeam_channel::Sender<String>,
-    yaw_pid: PIDValues,
-    pitch_pid: PIDValues,
     roll_pid: PIDValues,
 }
 
 #[derive(Clone)]
-struct PIDValues {
+pub struct PIDValues {
     p: f32,
     i: f32,
     d: f32,
@@ -28,16 +27,6 @@ impl PIDControlView {
             last_sent_time: std::time::Instant::now(),
             enabled_transmit: false,
             ui_to_drone_tx,
-            yaw_pid: PIDValues {
-                p: 0.0,
-                i: 0.0,
-                d: 0.0,
-            },
-            pitch_pid: PIDValues {
-                p: 0.0,
-                i: 0.0,
-                d: 0.0,
-            },
             roll_pid: PIDValues {
                 p: 4.6,
                 i: 0.1,
@@ -66,7 +55,7 @@ impl PIDControlView {
             // .open(&mut self.open)
             .resizable(true)
             .show(ctx, |ui| {
-                let data = received_data.lock().unwrap();
+                let mut data = received_data.lock().unwrap();
 
                 if self.last_sent_time.elapsed() > std::time::Duration::from_millis(200)
                     && self.enabled_transmit
@@ -75,11 +64,14 @@ impl PIDControlView {
 
                     // convert all ranges from 1000 to 2000
 
+                    let command = Command::Pid {
+                        p: self.roll_pid.p,
+                        i: self.roll_pid.i,
+                        d: self.roll_pid.d,
+                    };
+                    let message = data.deliveries.track(command);
                     self.ui_to_drone_tx
-                        .send(format!(
-                            "pid->{},{},{}\n",
-                            self.roll_pid.p, self.roll_pid.i, self.roll_pid.d
-                        ))
+                        .send(message)
                         .expect("Failed to send RC control values");
                 }
 
@@ -87,6 +79,15 @@ impl PIDControlView {
                 ui.add_space(10.0);
 
                 ui.checkbox(&mut self.enabled_transmit, "Enable Transmit");
+                if let Some(delivery) = data
+                    .deliveries
+                    .latest(|command| matches!(command, Command::Pid { .. }))
+                {
+                    ui.horizontal(|ui| {
+                        ui.label("Last update:");
+                        delivery.label(ui);
+                    });
+                }
 
                 ui.group(|ui| {
                     // ui.label(format!(
@@ -135,38 +136,6 @@ impl PIDControlView {
                         );
                     });
                 });
-                // self.pid_ui(ui, "Yaw", &mut self.yaw_pid);
-                // ui.add_space(10.0);
-                // self.pid_ui(ui, "Pitch", &mut self.pitch_pid);
-                // ui.add_space(10.0);
-                // self.pid_ui(ui, "Roll", &mut self.roll_pid);
             });
     }
-
-    fn pid_ui(&mut self, ui: &mut egui::Ui, label: &str, pid: &mut PIDValues) {
-        ui.group(|ui| {
-            ui.label(label);
-            ui.horizontal(|ui| {
-                ui.label("P:");
-                ui.add(egui::DragValue::new(&mut pid.p).speed(0.1));
-            });
-            ui.horizontal(|ui| {
-                ui.label("I:");
-                ui.add(egui::DragValue::new(&mut pid.i).speed(0.1));
-            });
-            ui.horizontal(|ui| {
-                ui.label("D:");
-                ui.add(egui::DragValue::new(&mut pid.d).speed(0.1));
-            });
-        });
-    }
-
-    // You might want to add a method to get the current PID values
-    pub fn get_pid_values(&self) -> (PIDValues, PIDValues, PIDValues) {
-        (
-            self.yaw_pid.clone(),
-            self.pitch_pid.clone(),
-            self.roll_pid.clone(),
-        )
-    }
 }
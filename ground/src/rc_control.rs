@@ -1,6 +1,6 @@
 use crossbeam_channel::Sender;
 use eframe::egui;
-use std::sync::{Arc, Mutex};
+use protocol::Command;
 
 const KEYBOARD_CONTROL_SPEED: f32 = 0.01; //0.05;
 
@@ -8,7 +8,7 @@ const KEYBOARD_CONTROL_SPEED: f32 = 0.01; //0.05;
 pub struct RCControl {
     // open: bool,
     pub ui_to_drone_tx: Sender<String>,
-    enabled_transmit : bool,
+    enabled_transmit: bool,
     throttle: f32,
     yaw: f32,
     pitch: f32,
@@ -52,19 +52,21 @@ impl RCControl {
     pub fn window(&mut self, ctx: &egui::Context) {
         // transmit the RC control values to the drone every 50ms
 
-        if self.last_sent_time.elapsed() > std::time::Duration::from_millis(200) && self.enabled_transmit {
+        if self.last_sent_time.elapsed() > std::time::Duration::from_millis(200)
+            && self.enabled_transmit
+        {
             self.last_sent_time = std::time::Instant::now();
 
             // convert all ranges from 1000 to 2000
 
+            let command = Command::Rc {
+                throttle: (self.throttle * 500.0 + 1500.0).round() as i32,
+                yaw: (self.yaw * 500.0 + 1500.0).round() as i32,
+                pitch: (self.pitch * 500.0 + 1500.0).round() as i32,
+                roll: (self.roll * 500.0 + 1500.0).round() as i32,
+            };
             self.ui_to_drone_tx
-                .send(format!(
-                    "rc->{},{},{},{}\n",
-                    (self.throttle * 500.0 + 1500.0).round(),
-                    (self.yaw * 500.0 + 1500.0).round(),
-                    (self.pitch * 500.0 + 1500.0).round(),
-                    (self.roll * 500.0 + 1500.0).round()
-                ))
+                .send(command.encode())
                 .expect("Failed to send RC control values");
         }
 
@@ -73,7 +75,6 @@ impl RCControl {
             .resizable(true)
             .max_size([500.0, 300.0])
             .show(ctx, |ui| {
-
                 ui.checkbox(&mut self.enabled_transmit, "Enable Transmit");
 
                 let available_size = ui.available_size();
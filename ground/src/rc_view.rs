@@ -2,34 +2,12 @@ use crate::data::ReceivedData;
 use eframe::egui;
 use std::sync::{Arc, Mutex};
 
-#[derive(Clone)]
-pub struct RCView {
-    open: bool,
-}
-
-impl Default for RCView {
-    fn default() -> Self {
-        Self { open: false }
-    }
-}
+#[derive(Clone, Default)]
+pub struct RCView;
 
 impl RCView {
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
-        if ui
-            .button(if self.open {
-                "Close RC View"
-            } else {
-                "Open RC View"
-            })
-            .clicked()
-        {
-            self.open = !self.open;
-        }
-    }
-
     pub fn window(&mut self, ctx: &egui::Context, received_data: &Arc<Mutex<ReceivedData>>) {
         egui::Window::new("RC Remote View")
-            // .open(&mut self.open)
             .resizable(true)
             .default_size([500.0, 300.0]) // More rectangular aspect ratio
             .show(ctx, |ui| {
@@ -123,7 +101,7 @@ fn draw_rc_remote(
     painter.circle_filled(right_stick_pos, stick_size / 2.0, egui::Color32::DARK_GRAY);
 
     // Labels
-    let font_id = egui::FontId::proportional(14.0);
+    let _font_id = egui::FontId::proportional(14.0);
     let small_font_id = egui::FontId::proportional(12.0);
 
     // Left stick labels
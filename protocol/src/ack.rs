@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+
+// A command the sender wants delivery reports for. The bridge strips the
+// sequence number and writes `line` to the flight controller as usual.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequencedCommand {
+    pub seq: u32,
+    pub line: String,
+}
+
+impl SequencedCommand {
+    pub fn encode(&self) -> String {
+        format!("seq->{}:{}", self.seq, self.line)
+    }
+
+    // Returns `None` for anything that is not a sequenced command
+    pub fn decode(text: &str) -> Option<SequencedCommand> {
+        let (seq, line) = text.strip_prefix("seq->")?.split_once(':')?;
+        Some(SequencedCommand {
+            seq: seq.parse().ok()?,
+            line: line.to_string(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandStatus {
+    // Queued on the bridge, again on every retry
+    Sent,
+    // Written to the flight controller
+    Delivered,
+    // The flight controller answered, e.g. `Armed...` for `command->arm`
+    Acknowledged,
+    Failed,
+}
+
+// Delivery report for a `SequencedCommand`, sent only to the client that sent it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandAck {
+    pub seq: u32,
+    pub status: CommandStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_wire_strings() {
+        let command = SequencedCommand {
+            seq: 42,
+            line: "pid->3,0.1,0\n".to_string(),
+        };
+        assert_eq!(command.encode(), "seq->42:pid->3,0.1,0\n");
+        assert_eq!(SequencedCommand::decode(&command.encode()), Some(command));
+        assert_eq!(SequencedCommand::decode("command->arm"), None);
+        assert_eq!(SequencedCommand::decode("seq->x:command->arm"), None);
+
+        let ack = CommandAck {
+            seq: 42,
+            status: CommandStatus::Failed,
+            reason: Some("no acknowledgement".to_string()),
+        };
+        let json = serde_json::to_string(&ack).unwrap();
+        assert_eq!(
+            json,
+            r#"{"seq":42,"status":"failed","reason":"no acknowledgement"}"#
+        );
+        assert_eq!(serde_json::from_str::<CommandAck>(&json).unwrap(), ack);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::error::DecodeError;
+
+// Commands sent from the ground, through the bridge, to the flight controller.
+// The wire strings must match what `firmware/src/main.cpp` and
+// `firmware/src/fake_receiver.h` compare against. The firmware reads up to
+// `\n`, so every encoded command ends with one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Arm,
+    Abort,
+    Reboot,
+    EnableMotors,
+    // RC stick values, each in range [1000, 2000]
+    Rc {
+        throttle: i32,
+        yaw: i32,
+        pitch: i32,
+        roll: i32,
+    },
+    // Roll PID constants
+    Pid {
+        p: f32,
+        i: f32,
+        d: f32,
+    },
+    // Free text typed in the chat window, forwarded untouched
+    Text(String),
+}
+
+impl Command {
+    pub fn encode(&self) -> String {
+        match self {
+            Command::Arm => "command->arm\n".to_string(),
+            Command::Abort => "command->abort\n".to_string(),
+            Command::Reboot => "command->reboot\n".to_string(),
+            Command::EnableMotors => "command->enable_motors\n".to_string(),
+            Command::Rc {
+                throttle,
+                yaw,
+                pitch,
+                roll,
+            } => format!("rc->{},{},{},{}\n", throttle, yaw, pitch, roll),
+            Command::Pid { p, i, d } => format!("pid->{},{},{}\n", p, i, d),
+            Command::Text(text) => format!("{}\n", text),
+        }
+    }
+
+    pub fn decode(line: &str) -> Result<Command, DecodeError> {
+        let trimmed = line.trim_end_matches(['\r', '\n']);
+
+        if let Some(name) = trimmed.strip_prefix("command->") {
+            return match name {
+                "arm" => Ok(Command::Arm),
+                "abort" => Ok(Command::Abort),
+                "reboot" => Ok(Command::Reboot),
+                "enable_motors" => Ok(Command::EnableMotors),
+                _ => Err(DecodeError::UnknownCommand(line.to_string())),
+            };
+        }
+
+        if let Some(values) = trimmed.strip_prefix("rc->") {
+            let values = parse_values::<i32>(values, 4)
+                .ok_or_else(|| DecodeError::InvalidArguments(line.to_string()))?;
+            return Ok(Command::Rc {
+                throttle: values[0],
+                yaw: values[1],
+                pitch: values[2],
+                roll: values[3],
+            });
+        }
+
+        if let Some(values) = trimmed.strip_prefix("pid->") {
+            let values = parse_values::<f32>(values, 3)
+                .ok_or_else(|| DecodeError::InvalidArguments(line.to_string()))?;
+            return Ok(Command::Pid {
+                p: values[0],
+                i: values[1],
+                d: values[2],
+            });
+        }
+
+        Ok(Command::Text(trimmed.to_string()))
+    }
+}
+
+fn parse_values<T: std::str::FromStr>(values: &str, count: usize) -> Option<Vec<T>> {
+    let values = values
+        .split(',')
+        .map(|value| value.trim().parse::<T>().ok())
+        .collect::<Option<Vec<T>>>()?;
+
+    if values.len() == count {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_wire_strings() {
+        assert_eq!(Command::Arm.encode(), "command->arm\n");
+        assert_eq!(Command::Abort.encode(), "command->abort\n");
+        assert_eq!(Command::Reboot.encode(), "command->reboot\n");
+        assert_eq!(Command::EnableMotors.encode(), "command->enable_motors\n");
+        assert_eq!(
+            Command::Rc {
+                throttle: 1000,
+                yaw: 1500,
+                pitch: 1500,
+                roll: 2000
+            }
+            .encode(),
+            "rc->1000,1500,1500,2000\n"
+        );
+        assert_eq!(
+            Command::Pid {
+                p: 4.6,
+                i: 0.1,
+                d: 0.0
+            }
+            .encode(),
+            "pid->4.6,0.1,0\n"
+        );
+        assert_eq!(Command::Text("hello".to_string()).encode(), "hello\n");
+    }
+
+    #[test]
+    fn round_trip() {
+        let commands = [
+            Command::Arm,
+            Command::Abort,
+            Command::Reboot,
+            Command::EnableMotors,
+            Command::Rc {
+                throttle: 1234,
+                yaw: 1500,
+                pitch: 1001,
+                roll: 1999,
+            },
+            Command::Pid {
+                p: 3.0,
+                i: 0.125,
+                d: 12.5,
+            },
+            Command::Text("hello drone".to_string()),
+        ];
+
+        for command in commands {
+            assert_eq!(Command::decode(&command.encode()).unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        assert!(matches!(
+            Command::decode("command->fly"),
+            Err(DecodeError::UnknownCommand(_))
+        ));
+        assert!(matches!(
+            Command::decode("rc->1000,1500,1500\n"),
+            Err(DecodeError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            Command::decode("pid->a,b,c\n"),
+            Err(DecodeError::InvalidArguments(_))
+        ));
+    }
+}
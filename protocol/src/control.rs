@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+// Requests handled by the bridge itself, never forwarded to the flight controller.
+// Only the client holding control (the pilot) may send commands to the drone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlRequest {
+    Take,
+    Release,
+    // Sent periodically by the pilot so the bridge can detect a lost link
+    Heartbeat,
+}
+
+impl ControlRequest {
+    pub fn encode(&self) -> String {
+        match self {
+            ControlRequest::Take => "control->take".to_string(),
+            ControlRequest::Release => "control->release".to_string(),
+            ControlRequest::Heartbeat => "control->heartbeat".to_string(),
+        }
+    }
+
+    // Returns `None` for anything that is not a control request
+    pub fn decode(text: &str) -> Option<ControlRequest> {
+        match text.trim_end_matches(['\r', '\n']) {
+            "control->take" => Some(ControlRequest::Take),
+            "control->release" => Some(ControlRequest::Release),
+            "control->heartbeat" => Some(ControlRequest::Heartbeat),
+            _ => None,
+        }
+    }
+}
+
+// Command authority of the client a message is sent to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlStatus {
+    // Nobody holds control
+    Available,
+    // This client holds control
+    Pilot,
+    // Another client holds control, this one is read-only
+    Observer,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_wire_strings() {
+        assert_eq!(ControlRequest::Take.encode(), "control->take");
+        assert_eq!(ControlRequest::Release.encode(), "control->release");
+        assert_eq!(ControlRequest::Heartbeat.encode(), "control->heartbeat");
+        assert_eq!(
+            serde_json::to_string(&ControlStatus::Observer).unwrap(),
+            r#""observer""#
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for request in [
+            ControlRequest::Take,
+            ControlRequest::Release,
+            ControlRequest::Heartbeat,
+        ] {
+            assert_eq!(ControlRequest::decode(&request.encode()), Some(request));
+        }
+        assert_eq!(ControlRequest::decode("command->arm"), None);
+    }
+}
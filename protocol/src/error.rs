@@ -0,0 +1,28 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum DecodeError {
+    UnknownCommand(String),
+    InvalidArguments(String),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownCommand(line) => write!(f, "unknown command: {:?}", line),
+            DecodeError::InvalidArguments(line) => {
+                write!(f, "invalid command arguments: {:?}", line)
+            }
+            DecodeError::Json(e) => write!(f, "invalid telemetry JSON: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<serde_json::Error> for DecodeError {
+    fn from(e: serde_json::Error) -> Self {
+        DecodeError::Json(e)
+    }
+}
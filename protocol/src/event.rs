@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+// Events raised by the bridge itself, sent to clients alongside telemetry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BridgeEvent {
+    // The pilot went silent and the bridge sent the safe sequence to the drone
+    Failsafe {
+        // Unix time in milliseconds
+        timestamp: u64,
+        reason: String,
+        // Lines written to serial
+        sent: Vec<String>,
+    },
+    // The serial port failed or could not be opened, commands are dropped
+    // until the bridge has reopened it
+    SerialDown {
+        timestamp: u64,
+        reason: String,
+    },
+    SerialUp {
+        timestamp: u64,
+    },
+    // The bridge is stopping, clients are closed right after this
+    Shutdown {
+        timestamp: u64,
+        reason: String,
+        // Lines queued for the flight controller before exiting
+        sent: Vec<String>,
+    },
+}
+
+impl BridgeEvent {
+    pub fn describe(&self) -> String {
+        match self {
+            BridgeEvent::Failsafe { reason, sent, .. } => {
+                format!("Failsafe triggered ({}), sent {}", reason, sent.join(", "))
+            }
+            BridgeEvent::SerialDown { reason, .. } => format!("Serial link down ({})", reason),
+            BridgeEvent::SerialUp { .. } => "Serial link up".to_string(),
+            BridgeEvent::Shutdown { reason, sent, .. } => {
+                format!(
+                    "Bridge shutting down ({}), sent {}",
+                    reason,
+                    sent.join(", ")
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_failsafe_event() {
+        let event = BridgeEvent::Failsafe {
+            timestamp: 1_700_000_000_000,
+            reason: "no heartbeat for 1000 ms".to_string(),
+            sent: vec!["command->abort".to_string()],
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"kind":"failsafe","timestamp":1700000000000,"reason":"no heartbeat for 1000 ms","sent":["command->abort"]}"#
+        );
+        assert_eq!(serde_json::from_str::<BridgeEvent>(&json).unwrap(), event);
+    }
+
+    #[test]
+    fn golden_serial_link_events() {
+        let down = BridgeEvent::SerialDown {
+            timestamp: 1_700_000_000_000,
+            reason: "No such device".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&down).unwrap(),
+            r#"{"kind":"serial_down","timestamp":1700000000000,"reason":"No such device"}"#
+        );
+        let up = BridgeEvent::SerialUp {
+            timestamp: 1_700_000_001_000,
+        };
+        assert_eq!(
+            serde_json::to_string(&up).unwrap(),
+            r#"{"kind":"serial_up","timestamp":1700000001000}"#
+        );
+        assert_eq!(down.describe(), "Serial link down (No such device)");
+    }
+}
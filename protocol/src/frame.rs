@@ -0,0 +1,264 @@
+use std::fmt;
+
+use crate::telemetry::SerialData;
+
+// Binary framing, used instead of newline JSON when the firmware is built with
+// `TRANSMITTER_BINARY_FRAMES`. Each frame is
+//
+//   COBS( type: u8 | body | crc16(type | body): u16 LE ) 0x00
+//
+// so a zero byte always marks the end of a frame and a corrupted frame can be
+// detected and skipped without losing sync with the stream.
+
+pub const FRAME_DELIMITER: u8 = 0x00;
+
+const TYPE_SERIAL_DATA: u8 = 0x01;
+const TYPE_TEXT: u8 = 0x02;
+
+#[derive(Debug, Clone)]
+pub enum Frame {
+    SerialData(SerialData),
+    // Status text the firmware would otherwise print with `println`
+    Text(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    Cobs,
+    TooShort(usize),
+    Crc { expected: u16, actual: u16 },
+    UnknownType(u8),
+    InvalidBody(u8),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Cobs => write!(f, "invalid COBS encoding"),
+            FrameError::TooShort(len) => write!(f, "frame too short ({} bytes)", len),
+            FrameError::Crc { expected, actual } => write!(
+                f,
+                "CRC mismatch (expected {:#06x}, got {:#06x})",
+                expected, actual
+            ),
+            FrameError::UnknownType(kind) => write!(f, "unknown message type {:#04x}", kind),
+            FrameError::InvalidBody(kind) => {
+                write!(f, "invalid body for message type {:#04x}", kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl Frame {
+    // Encodes the frame including the trailing delimiter
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = match self {
+            Frame::SerialData(data) => {
+                let mut payload = vec![TYPE_SERIAL_DATA];
+                payload.extend(data.to_bytes());
+                payload
+            }
+            Frame::Text(text) => {
+                let mut payload = vec![TYPE_TEXT];
+                payload.extend(text.as_bytes());
+                payload
+            }
+        };
+        let crc = crc16(&payload);
+        payload.extend(crc.to_le_bytes());
+
+        let mut frame = cobs_encode(&payload);
+        frame.push(FRAME_DELIMITER);
+        frame
+    }
+
+    // Decodes a frame, with or without its trailing delimiter
+    pub fn decode(bytes: &[u8]) -> Result<Frame, FrameError> {
+        let bytes = bytes.strip_suffix(&[FRAME_DELIMITER]).unwrap_or(bytes);
+        let payload = cobs_decode(bytes).ok_or(FrameError::Cobs)?;
+        if payload.len() < 3 {
+            return Err(FrameError::TooShort(payload.len()));
+        }
+
+        let (content, crc) = payload.split_at(payload.len() - 2);
+        let expected = u16::from_le_bytes([crc[0], crc[1]]);
+        let actual = crc16(content);
+        if expected != actual {
+            return Err(FrameError::Crc { expected, actual });
+        }
+
+        let (kind, body) = (content[0], &content[1..]);
+        match kind {
+            TYPE_SERIAL_DATA => SerialData::from_bytes(body)
+                .map(Frame::SerialData)
+                .ok_or(FrameError::InvalidBody(kind)),
+            TYPE_TEXT => String::from_utf8(body.to_vec())
+                .map(Frame::Text)
+                .map_err(|_| FrameError::InvalidBody(kind)),
+            _ => Err(FrameError::UnknownType(kind)),
+        }
+    }
+}
+
+// Decodes frames while keeping count of how many were good or rejected
+#[derive(Debug, Default, Clone)]
+pub struct FrameDecoder {
+    pub frames: u64,
+    pub crc_errors: u64,
+    pub malformed: u64,
+}
+
+impl FrameDecoder {
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<Frame, FrameError> {
+        let result = Frame::decode(bytes);
+        match &result {
+            Ok(_) => self.frames += 1,
+            Err(FrameError::Crc { .. }) => self.crc_errors += 1,
+            Err(_) => self.malformed += 1,
+        }
+        result
+    }
+}
+
+// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF)
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn cobs_encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() + data.len() / 254 + 2);
+    let mut code_index = 0;
+    let mut code = 1u8;
+    encoded.push(0);
+
+    for byte in data {
+        if *byte == 0 {
+            encoded[code_index] = code;
+            code_index = encoded.len();
+            encoded.push(0);
+            code = 1;
+        } else {
+            encoded.push(*byte);
+            code += 1;
+            if code == 0xFF {
+                encoded[code_index] = code;
+                code_index = encoded.len();
+                encoded.push(0);
+                code = 1;
+            }
+        }
+    }
+    encoded[code_index] = code;
+    encoded
+}
+
+fn cobs_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut index = 0;
+
+    while index < data.len() {
+        let code = data[index] as usize;
+        let end = index + code;
+        if code == 0 || end > data.len() {
+            return None;
+        }
+        decoded.extend(&data[index + 1..end]);
+        index = end;
+        if code != 0xFF && index < data.len() {
+            decoded.push(0);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> SerialData {
+        SerialData {
+            elapsed_time: 1234.0,
+            acc_z: 9.81,
+            roll: -4,
+            rc_throttle: 1000,
+            front_left: 1100,
+            kp_r: 3.0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn cobs_round_trip() {
+        let cases: Vec<Vec<u8>> = vec![
+            vec![],
+            vec![0],
+            vec![0, 0],
+            vec![1, 2, 0, 3],
+            (1..=254).collect(),
+            (0..=255).collect(),
+            vec![7; 600],
+        ];
+        for case in cases {
+            let encoded = cobs_encode(&case);
+            assert!(!encoded.contains(&0));
+            assert_eq!(cobs_decode(&encoded).unwrap(), case);
+        }
+    }
+
+    #[test]
+    fn serial_data_frame_round_trip() {
+        let frame = Frame::SerialData(sample()).encode();
+        assert_eq!(frame.last(), Some(&FRAME_DELIMITER));
+        assert_eq!(frame.iter().filter(|b| **b == 0).count(), 1);
+
+        match Frame::decode(&frame).unwrap() {
+            Frame::SerialData(data) => assert_eq!(data.encode(), sample().encode()),
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    #[test]
+    fn text_frame_round_trip() {
+        let frame = Frame::Text("Armed...".to_string()).encode();
+        match Frame::decode(&frame).unwrap() {
+            Frame::Text(text) => assert_eq!(text, "Armed..."),
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    #[test]
+    fn counts_corrupted_frames() {
+        let mut decoder = FrameDecoder::default();
+        let frame = Frame::SerialData(sample()).encode();
+        assert!(decoder.decode(&frame).is_ok());
+
+        let corrupted = cobs_encode(&[TYPE_TEXT, b'h', b'i', 0x12, 0x34]);
+        assert!(matches!(
+            decoder.decode(&corrupted),
+            Err(FrameError::Crc { .. })
+        ));
+        assert!(decoder.decode(&[0x05, 0x01]).is_err());
+
+        assert_eq!(decoder.frames, 1);
+        assert_eq!(decoder.crc_errors, 1);
+        assert_eq!(decoder.malformed, 1);
+    }
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+// Latest fix of the GPS receiver on the bridge
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GpsFix {
+    pub fix: FixType,
+    pub source: GpsSource,
+    pub satellites: u8,
+    // WGS84, in degrees
+    pub lat: f64,
+    pub lon: f64,
+    // Above mean sea level, in metres
+    pub alt_m: f32,
+    pub speed_m_s: f32,
+    // Direction of motion, clockwise from true north
+    pub course_deg: f32,
+    // North, east and down, only reported by UBX
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity_ned_m_s: Option<[f32; 3]>,
+    // Estimated accuracies, only reported by UBX
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub h_acc_m: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v_acc_m: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed_acc_m_s: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FixType {
+    #[serde(rename = "none")]
+    NoFix,
+    #[serde(rename = "dead_reckoning")]
+    DeadReckoning,
+    #[serde(rename = "2d")]
+    Fix2d,
+    #[serde(rename = "3d")]
+    Fix3d,
+    // GNSS combined with dead reckoning
+    #[serde(rename = "gnss_dead_reckoning")]
+    GnssDeadReckoning,
+    #[serde(rename = "time_only")]
+    TimeOnly,
+}
+
+// Which receiver output the fix was read from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GpsSource {
+    // UBX NAV-PVT
+    Ubx,
+    // NMEA GGA and RMC, when the receiver is not configured for UBX
+    Nmea,
+}
+
+impl FixType {
+    // Whether the position can be used
+    pub fn has_position(&self) -> bool {
+        matches!(
+            self,
+            FixType::Fix2d | FixType::Fix3d | FixType::GnssDeadReckoning
+        )
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            FixType::NoFix => "no fix",
+            FixType::DeadReckoning => "dead reckoning",
+            FixType::Fix2d => "2D fix",
+            FixType::Fix3d => "3D fix",
+            FixType::GnssDeadReckoning => "3D fix with dead reckoning",
+            FixType::TimeOnly => "time only",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_gps_fix() {
+        let fix = GpsFix {
+            fix: FixType::Fix3d,
+            source: GpsSource::Ubx,
+            satellites: 12,
+            lat: 47.5,
+            lon: 8.25,
+            alt_m: 499.5,
+            speed_m_s: 1.5,
+            course_deg: 90.0,
+            velocity_ned_m_s: Some([0.0, 1.5, -0.25]),
+            h_acc_m: Some(1.5),
+            v_acc_m: None,
+            speed_acc_m_s: None,
+        };
+        let json = serde_json::to_string(&fix).unwrap();
+        assert_eq!(
+            json,
+            r#"{"fix":"3d","source":"ubx","satellites":12,"lat":47.5,"lon":8.25,"alt_m":499.5,"speed_m_s":1.5,"course_deg":90.0,"velocity_ned_m_s":[0.0,1.5,-0.25],"h_acc_m":1.5}"#
+        );
+        assert_eq!(serde_json::from_str::<GpsFix>(&json).unwrap(), fix);
+
+        assert!(fix.fix.has_position());
+        assert!(!FixType::TimeOnly.has_position());
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+// Bits of the Raspberry Pi firmware `get_throttled` value, the same bits
+// shifted by 16 tell whether the condition happened since boot
+const THROTTLE_FLAGS: [(u32, &str); 4] = [
+    (0, "under-voltage"),
+    (1, "frequency capped"),
+    (2, "throttled"),
+    (3, "soft temperature limit"),
+];
+
+// Health of the Raspberry Pi running the bridge, sampled at a low rate. Every
+// value is `None` when the system does not provide it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct CompanionHealth {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_temp_c: Option<f32>,
+    // 1, 5 and 15 minute load averages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load: Option<[f32; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_total_kb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_available_kb: Option<u64>,
+    // Raw `get_throttled` bits, see `throttling`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub throttled: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wifi: Option<WifiSignal>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WifiSignal {
+    pub interface: String,
+    // Out of 70 on the Pi's Broadcom chip
+    pub link_quality: f32,
+    pub signal_dbm: f32,
+}
+
+impl CompanionHealth {
+    // Names of the throttling conditions active now, and of those seen since boot
+    pub fn throttling(&self) -> (Vec<&'static str>, Vec<&'static str>) {
+        let bits = self.throttled.unwrap_or(0);
+        let names = |shift: u32| {
+            THROTTLE_FLAGS
+                .iter()
+                .filter(|(bit, _)| bits & (1 << (bit + shift)) != 0)
+                .map(|(_, name)| *name)
+                .collect()
+        };
+        (names(0), names(16))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_companion_health() {
+        let health = CompanionHealth {
+            cpu_temp_c: Some(48.5),
+            load: Some([0.5, 0.25, 0.125]),
+            throttled: Some(0x50005),
+            wifi: Some(WifiSignal {
+                interface: "wlan0".to_string(),
+                link_quality: 70.0,
+                signal_dbm: -40.0,
+            }),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&health).unwrap();
+        assert_eq!(
+            json,
+            r#"{"cpu_temp_c":48.5,"load":[0.5,0.25,0.125],"throttled":327685,"wifi":{"interface":"wlan0","link_quality":70.0,"signal_dbm":-40.0}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<CompanionHealth>(&json).unwrap(),
+            health
+        );
+
+        assert_eq!(
+            health.throttling(),
+            (
+                vec!["under-voltage", "throttled"],
+                vec!["under-voltage", "throttled"]
+            )
+        );
+        assert_eq!(CompanionHealth::default().throttling(), (vec![], vec![]));
+    }
+}
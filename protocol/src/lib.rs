@@ -0,0 +1,29 @@
+mod ack;
+mod command;
+mod control;
+mod error;
+mod event;
+mod frame;
+mod gps;
+mod health;
+mod line;
+mod log;
+mod marker;
+mod ping;
+mod replay;
+mod telemetry;
+
+pub use ack::{CommandAck, CommandStatus, SequencedCommand};
+pub use command::Command;
+pub use control::{ControlRequest, ControlStatus};
+pub use error::DecodeError;
+pub use event::BridgeEvent;
+pub use frame::{crc16, Frame, FrameDecoder, FrameError, FRAME_DELIMITER};
+pub use gps::{FixType, GpsFix, GpsSource};
+pub use health::{CompanionHealth, WifiSignal};
+pub use line::{LineKind, Severity};
+pub use log::{LogFile, LogReply, LogRequest};
+pub use marker::{Marker, MarkerPose};
+pub use ping::{Ping, Pong};
+pub use replay::ReplayRequest;
+pub use telemetry::{QueueStats, SerialData, Telemetry};
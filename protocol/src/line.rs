@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+// What a line read from the flight controller is, decided by the bridge and
+// sent with it in `Telemetry::line_kind`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LineKind {
+    // `SerialData` JSON
+    Telemetry,
+    // Status text printed by the firmware
+    Log { severity: Severity },
+    // Answer to a command, e.g. `Armed...` for `command->arm`
+    Ack { command: String },
+    // JSON that is not telemetry, or garbage from the UART
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_line_kinds() {
+        let cases = [
+            (LineKind::Telemetry, r#"{"kind":"telemetry"}"#),
+            (
+                LineKind::Log {
+                    severity: Severity::Warning,
+                },
+                r#"{"kind":"log","severity":"warning"}"#,
+            ),
+            (
+                LineKind::Ack {
+                    command: "command->arm".to_string(),
+                },
+                r#"{"kind":"ack","command":"command->arm"}"#,
+            ),
+            (LineKind::Unknown, r#"{"kind":"unknown"}"#),
+        ];
+        for (kind, json) in cases {
+            assert_eq!(serde_json::to_string(&kind).unwrap(), json);
+            assert_eq!(serde_json::from_str::<LineKind>(json).unwrap(), kind);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+// Requests for the flight recorder on the bridge, allowed from any client
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogRequest {
+    List,
+    Get(String),
+}
+
+impl LogRequest {
+    pub fn encode(&self) -> String {
+        match self {
+            LogRequest::List => "log->list".to_string(),
+            LogRequest::Get(name) => format!("log->get:{}", name),
+        }
+    }
+
+    // Returns `None` for anything that is not a log request
+    pub fn decode(text: &str) -> Option<LogRequest> {
+        let text = text.trim_end_matches(['\r', '\n']);
+        if text == "log->list" {
+            return Some(LogRequest::List);
+        }
+        text.strip_prefix("log->get:")
+            .map(|name| LogRequest::Get(name.to_string()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogFile {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LogReply {
+    List {
+        files: Vec<LogFile>,
+    },
+    // Files are sent as a sequence of chunks split on line boundaries
+    Chunk {
+        name: String,
+        offset: u64,
+        data: String,
+        last: bool,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_wire_strings() {
+        assert_eq!(LogRequest::List.encode(), "log->list");
+        assert_eq!(
+            LogRequest::Get("session-20240101-120000-000.jsonl".to_string()).encode(),
+            "log->get:session-20240101-120000-000.jsonl"
+        );
+        assert_eq!(
+            serde_json::to_string(&LogReply::List {
+                files: vec![LogFile {
+                    name: "a.jsonl".to_string(),
+                    size: 12
+                }]
+            })
+            .unwrap(),
+            r#"{"kind":"list","files":[{"name":"a.jsonl","size":12}]}"#
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for request in [LogRequest::List, LogRequest::Get("a.jsonl".to_string())] {
+            assert_eq!(LogRequest::decode(&request.encode()), Some(request));
+        }
+        assert_eq!(LogRequest::decode("command->arm"), None);
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+// ArUco marker seen by the bridge camera
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub id: u32,
+    // Image coordinates in pixels, clockwise from the marker's top-left corner
+    pub corners: [[f32; 2]; 4],
+    // Only set when the bridge has a camera calibration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pose: Option<MarkerPose>,
+}
+
+// Position and orientation of a marker relative to the camera, as returned by
+// OpenCV's `solvePnP`. The camera frame has x right, y down and z forward; the
+// marker frame has its origin at the marker center, x right, y up and z out of
+// the marker towards the camera.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MarkerPose {
+    // Rotation vector (axis times angle in radians) from marker to camera frame
+    pub rotation: [f32; 3],
+    // Marker center in the camera frame, in metres
+    pub translation: [f32; 3],
+}
+
+impl MarkerPose {
+    // Straight-line distance from the camera to the marker center in metres
+    pub fn distance(&self) -> f32 {
+        let [x, y, z] = self.translation;
+        (x * x + y * y + z * z).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_marker() {
+        let marker = Marker {
+            id: 7,
+            corners: [[10.0, 20.0], [30.0, 20.0], [30.0, 40.5], [10.0, 40.5]],
+            pose: None,
+        };
+        assert_eq!(
+            serde_json::to_string(&marker).unwrap(),
+            r#"{"id":7,"corners":[[10.0,20.0],[30.0,20.0],[30.0,40.5],[10.0,40.5]]}"#
+        );
+    }
+
+    #[test]
+    fn golden_marker_with_pose() {
+        let marker = Marker {
+            id: 3,
+            corners: [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            pose: Some(MarkerPose {
+                rotation: [3.0, 0.0, 0.0],
+                translation: [0.0, 0.3, 0.4],
+            }),
+        };
+        let json = r#"{"id":3,"corners":[[0.0,0.0],[1.0,0.0],[1.0,1.0],[0.0,1.0]],"pose":{"rotation":[3.0,0.0,0.0],"translation":[0.0,0.3,0.4]}}"#;
+        assert_eq!(serde_json::to_string(&marker).unwrap(), json);
+        assert_eq!(serde_json::from_str::<Marker>(json).unwrap(), marker);
+        assert_eq!(marker.pose.unwrap().distance(), 0.5);
+    }
+}
@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+// Round-trip probe, answered right away by the bridge with a `Pong`. Allowed
+// from any client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ping {
+    pub seq: u32,
+}
+
+impl Ping {
+    pub fn encode(&self) -> String {
+        format!("ping->{}", self.seq)
+    }
+
+    // Returns `None` for anything that is not a ping
+    pub fn decode(text: &str) -> Option<Ping> {
+        let seq = text.trim_end_matches(['\r', '\n']).strip_prefix("ping->")?;
+        Some(Ping {
+            seq: seq.parse().ok()?,
+        })
+    }
+}
+
+// Answer to a `Ping`, with the counters of the serial side of the bridge
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pong {
+    pub seq: u32,
+    // Lines read from the flight controller since the bridge started
+    pub serial_lines: u64,
+    // Telemetry lines or frames that could not be parsed
+    pub serial_errors: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_wire_strings() {
+        assert_eq!(Ping { seq: 17 }.encode(), "ping->17");
+        assert_eq!(Ping::decode("ping->17\n"), Some(Ping { seq: 17 }));
+        assert_eq!(Ping::decode("ping->"), None);
+        assert_eq!(Ping::decode("command->arm"), None);
+
+        let pong = Pong {
+            seq: 17,
+            serial_lines: 1200,
+            serial_errors: 3,
+        };
+        let json = serde_json::to_string(&pong).unwrap();
+        assert_eq!(json, r#"{"seq":17,"serial_lines":1200,"serial_errors":3}"#);
+        assert_eq!(serde_json::from_str::<Pong>(&json).unwrap(), pong);
+    }
+}
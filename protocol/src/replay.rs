@@ -0,0 +1,64 @@
+// Requests controlling playback when the bridge replays a recorded session,
+// allowed from any client and ignored by a live bridge
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayRequest {
+    Pause,
+    Resume,
+    // Playback speed relative to the original timing
+    Speed(f32),
+}
+
+impl ReplayRequest {
+    // Speeds a client may ask for, the playback timer cannot wait any longer
+    pub const MIN_SPEED: f32 = 0.01;
+    pub const MAX_SPEED: f32 = 100.0;
+
+    pub fn encode(&self) -> String {
+        match self {
+            ReplayRequest::Pause => "replay->pause".to_string(),
+            ReplayRequest::Resume => "replay->resume".to_string(),
+            ReplayRequest::Speed(speed) => format!("replay->speed:{}", speed),
+        }
+    }
+
+    // Returns `None` for anything that is not a valid replay request
+    pub fn decode(text: &str) -> Option<ReplayRequest> {
+        match text.trim_end_matches(['\r', '\n']) {
+            "replay->pause" => Some(ReplayRequest::Pause),
+            "replay->resume" => Some(ReplayRequest::Resume),
+            text => text
+                .strip_prefix("replay->speed:")
+                .and_then(|speed| speed.parse::<f32>().ok())
+                .filter(|speed| (Self::MIN_SPEED..=Self::MAX_SPEED).contains(speed))
+                .map(ReplayRequest::Speed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_wire_strings() {
+        assert_eq!(ReplayRequest::Pause.encode(), "replay->pause");
+        assert_eq!(ReplayRequest::Resume.encode(), "replay->resume");
+        assert_eq!(ReplayRequest::Speed(2.5).encode(), "replay->speed:2.5");
+    }
+
+    #[test]
+    fn round_trip() {
+        for request in [
+            ReplayRequest::Pause,
+            ReplayRequest::Resume,
+            ReplayRequest::Speed(4.0),
+        ] {
+            assert_eq!(ReplayRequest::decode(&request.encode()), Some(request));
+        }
+        assert_eq!(ReplayRequest::decode("replay->speed:0"), None);
+        assert_eq!(ReplayRequest::decode("replay->speed:1e-30"), None);
+        assert_eq!(ReplayRequest::decode("replay->speed:1000"), None);
+        assert_eq!(ReplayRequest::decode("replay->speed:fast"), None);
+        assert_eq!(ReplayRequest::decode("command->arm"), None);
+    }
+}
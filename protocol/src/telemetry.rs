@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ack::CommandAck;
+use crate::control::ControlStatus;
+use crate::error::DecodeError;
+use crate::event::BridgeEvent;
+use crate::gps::GpsFix;
+use crate::health::CompanionHealth;
+use crate::line::LineKind;
+use crate::log::LogReply;
+use crate::marker::Marker;
+use crate::ping::Pong;
+
+// Message sent from the bridge to the ground for every line read from serial.
+// `serial_data` is the raw line, which is either `SerialData` JSON or a text
+// message printed by the firmware, and `line_kind` tells which.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Telemetry {
+    pub aruco_ids: Vec<u32>,
+    // Corners of every marker in `aruco_ids`, in the same order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub markers: Vec<Marker>,
+    pub serial_data: String,
+    // Set whenever `serial_data` is not empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_kind: Option<LineKind>,
+    // Control status of the receiving client, set by the bridge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub control: Option<ControlStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<BridgeEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logs: Option<LogReply>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_queue: Option<QueueStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ack: Option<CommandAck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pong: Option<Pong>,
+    // Latest health sample of the Pi running the bridge
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub companion: Option<CompanionHealth>,
+    // Latest fix of the GPS receiver on the bridge, `None` without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gps: Option<GpsFix>,
+}
+
+// Commands waiting in the bridge for the serial port
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueueStats {
+    pub depth: u32,
+    // Commands replaced by a newer value before they were written
+    pub coalesced: u64,
+    // Commands dropped because the queue was full
+    pub dropped: u64,
+}
+
+impl Telemetry {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("Telemetry is always serializable")
+    }
+
+    pub fn decode(text: &str) -> Result<Telemetry, DecodeError> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub fn serial_data(&self) -> Result<SerialData, DecodeError> {
+        SerialData::decode(&self.serial_data)
+    }
+}
+
+// Mirrors `TransmitterData` in `firmware/src/transmitter.h`
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[repr(C, packed)]
+pub struct SerialData {
+    pub elapsed_time: f32,
+    pub acc_x: f32,
+    pub acc_y: f32,
+    pub acc_z: f32,
+    pub gyro_x: f32,
+    pub gyro_y: f32,
+    pub gyro_z: f32,
+    pub mag_x: f32,
+    pub mag_y: f32,
+    pub mag_z: f32,
+    pub altitude: f32,
+    pub temp: f32,
+    pub yaw: i32,
+    pub pitch: i32,
+    pub roll: i32,
+    pub rc_throttle: i32,
+    pub rc_yaw: i32,
+    pub rc_pitch: i32,
+    pub rc_roll: i32,
+    pub front_right: i32,
+    pub back_right: i32,
+    pub back_left: i32,
+    pub front_left: i32,
+
+    pub kp_r: f32,
+    pub ki_r: f32,
+    pub kd_r: f32,
+    // pub kp_p: f32,
+    // pub ki_p: f32,
+    // pub kd_p: f32,
+}
+
+impl SerialData {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("SerialData is always serializable")
+    }
+
+    pub fn decode(line: &str) -> Result<SerialData, DecodeError> {
+        Ok(serde_json::from_str(line.trim())?)
+    }
+
+    // Size of `TransmitterData` as laid out in memory on the Teensy
+    pub const BINARY_SIZE: usize = 26 * 4;
+
+    // Little-endian, in field order, matching a `memcpy` of `TransmitterData`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let floats = [
+            self.elapsed_time,
+            self.acc_x,
+            self.acc_y,
+            self.acc_z,
+            self.gyro_x,
+            self.gyro_y,
+            self.gyro_z,
+            self.mag_x,
+            self.mag_y,
+            self.mag_z,
+            self.altitude,
+            self.temp,
+        ];
+        let ints = [
+            self.yaw,
+            self.pitch,
+            self.roll,
+            self.rc_throttle,
+            self.rc_yaw,
+            self.rc_pitch,
+            self.rc_roll,
+            self.front_right,
+            self.back_right,
+            self.back_left,
+            self.front_left,
+        ];
+        let pid = [self.kp_r, self.ki_r, self.kd_r];
+
+        let mut bytes = Vec::with_capacity(Self::BINARY_SIZE);
+        floats.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+        ints.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+        pid.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<SerialData> {
+        if bytes.len() != Self::BINARY_SIZE {
+            return None;
+        }
+
+        let mut words = bytes
+            .chunks_exact(4)
+            .map(|chunk| [chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let mut f = || f32::from_le_bytes(words.next().unwrap());
+        let (elapsed_time, acc_x, acc_y, acc_z) = (f(), f(), f(), f());
+        let (gyro_x, gyro_y, gyro_z) = (f(), f(), f());
+        let (mag_x, mag_y, mag_z) = (f(), f(), f());
+        let (altitude, temp) = (f(), f());
+
+        let mut i = || i32::from_le_bytes(words.next().unwrap());
+        let (yaw, pitch, roll) = (i(), i(), i());
+        let (rc_throttle, rc_yaw, rc_pitch, rc_roll) = (i(), i(), i(), i());
+        let (front_right, back_right, back_left, front_left) = (i(), i(), i(), i());
+
+        let mut f = || f32::from_le_bytes(words.next().unwrap());
+        let (kp_r, ki_r, kd_r) = (f(), f(), f());
+
+        Some(SerialData {
+            elapsed_time,
+            acc_x,
+            acc_y,
+            acc_z,
+            gyro_x,
+            gyro_y,
+            gyro_z,
+            mag_x,
+            mag_y,
+            mag_z,
+            altitude,
+            temp,
+            yaw,
+            pitch,
+            roll,
+            rc_throttle,
+            rc_yaw,
+            rc_pitch,
+            rc_roll,
+            front_right,
+            back_right,
+            back_left,
+            front_left,
+            kp_r,
+            ki_r,
+            kd_r,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A line as printed by `TransmitterController::sendData`
+    const FIRMWARE_LINE: &str = r#"{"elapsed_time":1234,"acc_x":0.1,"acc_y":-0.2,"acc_z":9.81,"gyro_x":1.5,"gyro_y":-1.5,"gyro_z":0,"mag_x":20,"mag_y":-5,"mag_z":40,"altitude":102.5,"temp":24.5,"yaw":10,"pitch":-3,"roll":4,"rc_throttle":1000,"rc_yaw":1500,"rc_pitch":1500,"rc_roll":1500,"front_right":1000,"back_right":1000,"back_left":1000,"front_left":1000,"kp_r":3,"ki_r":0.1,"kd_r":0}"#;
+
+    #[test]
+    fn decodes_firmware_line() {
+        let data = SerialData::decode(&format!("{}\r\n", FIRMWARE_LINE)).unwrap();
+        let (elapsed_time, acc_z, roll, rc_throttle, kp_r) = (
+            data.elapsed_time,
+            data.acc_z,
+            data.roll,
+            data.rc_throttle,
+            data.kp_r,
+        );
+        assert_eq!(elapsed_time, 1234.0);
+        assert_eq!(acc_z, 9.81);
+        assert_eq!(roll, 4);
+        assert_eq!(rc_throttle, 1000);
+        assert_eq!(kp_r, 3.0);
+    }
+
+    #[test]
+    fn serial_data_round_trip() {
+        let data = SerialData::decode(FIRMWARE_LINE).unwrap();
+        let decoded = SerialData::decode(&data.encode()).unwrap();
+        assert_eq!(decoded.encode(), data.encode());
+    }
+
+    #[test]
+    fn binary_layout_matches_transmitter_data() {
+        let data = SerialData::decode(FIRMWARE_LINE).unwrap();
+        let bytes = data.to_bytes();
+        assert_eq!(bytes.len(), SerialData::BINARY_SIZE);
+        assert_eq!(bytes.len(), std::mem::size_of::<SerialData>());
+        // elapsed_time first, rc_throttle is the 16th word
+        assert_eq!(&bytes[0..4], &1234.0f32.to_le_bytes());
+        assert_eq!(&bytes[60..64], &1000i32.to_le_bytes());
+
+        let decoded = SerialData::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.encode(), data.encode());
+        assert!(SerialData::from_bytes(&bytes[1..]).is_none());
+    }
+
+    #[test]
+    fn golden_telemetry_envelope() {
+        let telemetry = Telemetry {
+            aruco_ids: vec![1, 2],
+            markers: vec![],
+            serial_data: "Armed...".to_string(),
+            line_kind: Some(LineKind::Ack {
+                command: "command->arm".to_string(),
+            }),
+            control: None,
+            event: None,
+            logs: None,
+            command_queue: None,
+            ack: None,
+            pong: None,
+            companion: None,
+            gps: None,
+        };
+        assert_eq!(
+            telemetry.encode(),
+            r#"{"aruco_ids":[1,2],"serial_data":"Armed...","line_kind":{"kind":"ack","command":"command->arm"}}"#
+        );
+        assert_eq!(Telemetry::decode(&telemetry.encode()).unwrap(), telemetry);
+        assert!(telemetry.serial_data().is_err());
+    }
+
+    #[test]
+    fn telemetry_with_serial_data() {
+        let telemetry = Telemetry {
+            aruco_ids: vec![],
+            markers: vec![],
+            serial_data: FIRMWARE_LINE.to_string(),
+            line_kind: Some(LineKind::Telemetry),
+            control: Some(ControlStatus::Pilot),
+            event: None,
+            logs: None,
+            command_queue: Some(QueueStats {
+                depth: 2,
+                coalesced: 40,
+                dropped: 0,
+            }),
+            ack: None,
+            pong: None,
+            companion: None,
+            gps: None,
+        };
+        let decoded = Telemetry::decode(&telemetry.encode()).unwrap();
+        let yaw = decoded.serial_data().unwrap().yaw;
+        assert_eq!(yaw, 10);
+        assert_eq!(decoded.line_kind, Some(LineKind::Telemetry));
+        assert_eq!(decoded.control, Some(ControlStatus::Pilot));
+        assert_eq!(decoded.command_queue, telemetry.command_queue);
+    }
+}
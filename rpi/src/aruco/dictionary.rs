@@ -0,0 +1,238 @@
+use std::fmt;
+use std::path::Path;
+
+// Codewords of `DICT_ARUCO_ORIGINAL`, every row of a marker is one of them
+const ORIGINAL_WORDS: [[u8; 5]; 4] = [
+    [1, 0, 0, 0, 0],
+    [1, 0, 1, 1, 1],
+    [0, 1, 0, 0, 1],
+    [0, 1, 1, 1, 0],
+];
+
+#[derive(Debug)]
+pub enum DictionaryError {
+    Read(std::io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for DictionaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DictionaryError::Read(e) => write!(f, "failed to read dictionary: {}", e),
+            DictionaryError::Invalid(message) => write!(f, "invalid dictionary: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for DictionaryError {}
+
+// Marker bits are stored row-major with the top-left bit first (most significant),
+// 1 is a white cell
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    pub name: String,
+    // Cells per side, without the black border
+    pub size: usize,
+    pub max_correction: u32,
+    // Every marker in its 4 orientations, each rotated clockwise from the previous one
+    codes: Vec<[u64; 4]>,
+}
+
+impl Dictionary {
+    fn new(name: &str, size: usize, max_correction: u32, codes: Vec<u64>) -> Dictionary {
+        Dictionary {
+            name: name.to_string(),
+            size,
+            max_correction,
+            codes: codes
+                .into_iter()
+                .map(|code| {
+                    let mut rotations = [code; 4];
+                    for i in 1..4 {
+                        rotations[i] = rotate(rotations[i - 1], size);
+                    }
+                    rotations
+                })
+                .collect(),
+        }
+    }
+
+    // The 1024 5x5 markers of the original ArUco library, `DICT_ARUCO_ORIGINAL` in OpenCV.
+    // Each row encodes two bits of the id, most significant row first.
+    pub fn aruco_original() -> Dictionary {
+        let codes = (0..1024u64)
+            .map(|id| {
+                (0..5).fold(0, |code, row| {
+                    let word = ORIGINAL_WORDS[(id >> (2 * (4 - row)) & 3) as usize];
+                    word.iter()
+                        .fold(code, |code, &bit| (code << 1) | bit as u64)
+                })
+            })
+            .collect();
+        Dictionary::new("aruco_original", 5, 1, codes)
+    }
+
+    // Reads a dictionary written by OpenCV's `Dictionary::writeDictionary`, e.g.
+    // `cv2.aruco.getPredefinedDictionary(cv2.aruco.DICT_4X4_50).writeDictionary(fs)`
+    pub fn load(path: &Path) -> Result<Dictionary, DictionaryError> {
+        let text = std::fs::read_to_string(path).map_err(DictionaryError::Read)?;
+        let name = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or("custom");
+        Dictionary::parse(name, &text)
+    }
+
+    pub fn parse(name: &str, text: &str) -> Result<Dictionary, DictionaryError> {
+        let mut count = None;
+        let mut size = None;
+        let mut max_correction = 0;
+        let mut markers = Vec::new();
+
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| DictionaryError::Invalid(format!("{} is not a number", key)))
+            };
+            match key {
+                "nmarkers" => count = Some(number()?),
+                "markersize" => size = Some(number()?),
+                "maxCorrectionBits" => max_correction = number()? as u32,
+                _ => {
+                    if let Some(index) = key.strip_prefix("marker_") {
+                        let index = index.parse::<usize>().map_err(|_| {
+                            DictionaryError::Invalid(format!("bad marker key {}", key))
+                        })?;
+                        markers.push((index, value.to_string()));
+                    }
+                }
+            }
+        }
+
+        let size = size.ok_or_else(|| DictionaryError::Invalid("missing markersize".into()))?;
+        if !(3..=8).contains(&size) {
+            return Err(DictionaryError::Invalid(format!(
+                "markersize must be between 3 and 8, got {}",
+                size
+            )));
+        }
+        markers.sort();
+        let codes = markers
+            .iter()
+            .enumerate()
+            .map(|(expected, (index, bits))| {
+                if *index != expected || bits.len() != size * size {
+                    return Err(DictionaryError::Invalid(format!("bad marker_{}", index)));
+                }
+                bits.chars().try_fold(0u64, |code, bit| match bit {
+                    '0' => Ok(code << 1),
+                    '1' => Ok((code << 1) | 1),
+                    _ => Err(DictionaryError::Invalid(format!("bad marker_{}", index))),
+                })
+            })
+            .collect::<Result<Vec<u64>, DictionaryError>>()?;
+
+        if codes.is_empty() || count.is_some_and(|count| count != codes.len()) {
+            return Err(DictionaryError::Invalid(format!(
+                "expected {} markers, found {}",
+                count.unwrap_or(1),
+                codes.len()
+            )));
+        }
+        Ok(Dictionary::new(name, size, max_correction, codes))
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    // Bits of marker `id` in its upright orientation
+    #[cfg(test)]
+    pub fn code(&self, id: u32) -> u64 {
+        self.codes[id as usize][0]
+    }
+
+    // Finds the marker closest to `bits`, returns its id and how many times the
+    // marker was rotated clockwise to appear as `bits`
+    pub fn identify(&self, bits: u64) -> Option<(u32, usize)> {
+        let mut best: Option<(u32, u32, usize)> = None;
+        for (id, rotations) in self.codes.iter().enumerate() {
+            for (rotation, code) in rotations.iter().enumerate() {
+                let distance = (code ^ bits).count_ones();
+                if distance <= self.max_correction
+                    && best.is_none_or(|(_, best, _)| distance < best)
+                {
+                    best = Some((id as u32, distance, rotation));
+                }
+            }
+        }
+        best.map(|(id, _, rotation)| (id, rotation))
+    }
+}
+
+// Rotates a `size` x `size` bit matrix clockwise
+fn rotate(code: u64, size: usize) -> u64 {
+    let bit = |row: usize, col: usize| (code >> (size * size - 1 - (row * size + col))) & 1;
+    let mut rotated = 0;
+    for row in 0..size {
+        for col in 0..size {
+            rotated = (rotated << 1) | bit(size - 1 - col, row);
+        }
+    }
+    rotated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn original_dictionary() {
+        let dictionary = Dictionary::aruco_original();
+        assert_eq!(dictionary.len(), 1024);
+        // Marker 0 is five rows of 10000, marker 1023 five rows of 01110
+        assert_eq!(dictionary.code(0), 0b10000_10000_10000_10000_10000);
+        assert_eq!(dictionary.code(1023), 0b01110_01110_01110_01110_01110);
+        assert_eq!(dictionary.identify(dictionary.code(300)), Some((300, 0)));
+    }
+
+    #[test]
+    fn identifies_rotations() {
+        let dictionary = Dictionary::aruco_original();
+        let code = dictionary.code(123);
+        assert_eq!(rotate(rotate(rotate(rotate(code, 5), 5), 5), 5), code);
+        assert_eq!(dictionary.identify(rotate(code, 5)), Some((123, 1)));
+        assert_eq!(
+            dictionary.identify(rotate(rotate(code, 5), 5)),
+            Some((123, 2))
+        );
+        // One flipped bit is corrected, two are not
+        assert_eq!(dictionary.identify(code ^ 1), Some((123, 0)));
+        assert_eq!(dictionary.identify(0), None);
+    }
+
+    #[test]
+    fn parses_opencv_yaml() {
+        let dictionary = Dictionary::parse(
+            "4x4_test",
+            "%YAML:1.0\n---\nnmarkers: 2\nmarkersize: 4\nmaxCorrectionBits: 1\n\
+             marker_0: \"1011010100110010\"\nmarker_1: \"0000111110011010\"\n",
+        )
+        .unwrap();
+        assert_eq!(dictionary.size, 4);
+        assert_eq!(dictionary.len(), 2);
+        assert_eq!(dictionary.code(0), 0b1011_0101_0011_0010);
+
+        assert!(Dictionary::parse("bad", "markersize: 4\nmarker_0: \"101\"\n").is_err());
+        assert!(Dictionary::parse(
+            "bad",
+            "nmarkers: 3\nmarkersize: 3\nmarker_0: \"101010101\"\n"
+        )
+        .is_err());
+    }
+}
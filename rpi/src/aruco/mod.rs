@@ -0,0 +1,316 @@
+mod dictionary;
+mod pose;
+pub mod quad;
+
+pub use dictionary::Dictionary;
+pub use pose::PoseEstimator;
+
+use protocol::Marker;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bridge::Bridge;
+use crate::camera::{Frame, FrameSource};
+use quad::{adaptive_threshold, convex_hull, fit_quad, outlines, Homography, Point};
+
+// Threshold windows as a fraction of the shorter image side, so that both small
+// and large markers keep a solid black border
+const WINDOW_DIVISORS: [usize; 3] = [16, 8, 4];
+// How much darker than its surroundings a pixel must be to count as black
+const THRESHOLD_OFFSET: u32 = 7;
+// Minimum difference between the brightest and darkest cell of a marker
+const MIN_CONTRAST: f32 = 30.0;
+// Smallest marker side, in pixels per cell
+const MIN_CELL_PIXELS: usize = 2;
+// Share of the convex outline a marker quadrilateral must cover
+const MIN_QUAD_FILL: f32 = 0.9;
+// Share of the border cells allowed to read white
+const MAX_BORDER_ERRORS: f32 = 0.2;
+
+pub struct Detector {
+    dictionary: Dictionary,
+    poses: Option<PoseEstimator>,
+}
+
+impl Detector {
+    pub fn new(dictionary: Dictionary) -> Detector {
+        Detector {
+            dictionary,
+            poses: None,
+        }
+    }
+
+    // Also estimate the pose of every detected marker
+    pub fn with_poses(self, poses: PoseEstimator) -> Detector {
+        Detector {
+            poses: Some(poses),
+            ..self
+        }
+    }
+
+    #[cfg(test)]
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dictionary
+    }
+
+    // Markers in `frame`, sorted by id
+    pub fn detect(&self, frame: &Frame) -> Vec<Marker> {
+        let cells = self.dictionary.size + 2;
+        let min_side = (cells * MIN_CELL_PIXELS) as f32;
+        let shortest = frame.width.min(frame.height);
+        let mut markers: Vec<Marker> = Vec::new();
+
+        for divisor in WINDOW_DIVISORS {
+            let window = (shortest / divisor).max(3) | 1;
+            let dark = adaptive_threshold(frame, window, THRESHOLD_OFFSET);
+            let min_pixels = cells * MIN_CELL_PIXELS * 4;
+
+            for outline in outlines(&dark, frame.width, frame.height, min_pixels) {
+                let Some(quad) = fit_quad(&convex_hull(outline), min_side, MIN_QUAD_FILL) else {
+                    continue;
+                };
+                let Some(marker) = self.decode(frame, &quad) else {
+                    continue;
+                };
+                // The same marker is usually found with several windows
+                let duplicate = markers.iter().any(|other| {
+                    other.id == marker.id
+                        && distance(center(&other.corners), center(&marker.corners)) < min_side
+                });
+                if !duplicate {
+                    markers.push(marker);
+                }
+            }
+        }
+
+        if let Some(poses) = &self.poses {
+            for marker in &mut markers {
+                marker.pose = poses.estimate(&marker.corners);
+            }
+        }
+        markers.sort_by_key(|marker| marker.id);
+        markers
+    }
+
+    // Reads the cell grid inside `quad` and looks it up in the dictionary
+    fn decode(&self, frame: &Frame, quad: &[Point; 4]) -> Option<Marker> {
+        let size = self.dictionary.size;
+        let cells = size + 2;
+        let homography = Homography::from_unit_square(quad)?;
+
+        // Mean of a few samples around the middle of every cell
+        let mut means = Vec::with_capacity(cells * cells);
+        for row in 0..cells {
+            for col in 0..cells {
+                let mut sum = 0.0;
+                for (du, dv) in [
+                    (0.35, 0.35),
+                    (0.65, 0.35),
+                    (0.5, 0.5),
+                    (0.35, 0.65),
+                    (0.65, 0.65),
+                ] {
+                    let u = (col as f32 + du) / cells as f32;
+                    let v = (row as f32 + dv) / cells as f32;
+                    let [x, y] = homography.map(u, v);
+                    sum += frame.sample(x, y)?;
+                }
+                means.push(sum / 5.0);
+            }
+        }
+
+        let min = means.iter().cloned().fold(f32::MAX, f32::min);
+        let max = means.iter().cloned().fold(f32::MIN, f32::max);
+        if max - min < MIN_CONTRAST {
+            return None;
+        }
+        let threshold = (min + max) / 2.0;
+
+        let mut border_errors = 0;
+        let mut bits = 0u64;
+        for row in 0..cells {
+            for col in 0..cells {
+                let white = means[row * cells + col] > threshold;
+                if row == 0 || col == 0 || row == cells - 1 || col == cells - 1 {
+                    border_errors += white as usize;
+                } else {
+                    bits = (bits << 1) | white as u64;
+                }
+            }
+        }
+        if border_errors as f32 > MAX_BORDER_ERRORS * (4 * (cells - 1)) as f32 {
+            return None;
+        }
+
+        // A marker rotated clockwise `rotation` times has its top-left corner there
+        let (id, rotation) = self.dictionary.identify(bits)?;
+        let corners = [0, 1, 2, 3].map(|i| quad[(i + rotation) % 4]);
+        Some(Marker {
+            id,
+            corners,
+            pose: None,
+        })
+    }
+}
+
+fn center(corners: &[Point; 4]) -> Point {
+    [
+        corners.iter().map(|p| p[0]).sum::<f32>() / 4.0,
+        corners.iter().map(|p| p[1]).sum::<f32>() / 4.0,
+    ]
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+// Detects markers on frames from `source` at `rate_hz` and publishes them to clients.
+// Runs on its own thread since detection is CPU bound.
+pub fn run(
+    bridge: Arc<Bridge>,
+    detector: Detector,
+    mut source: Box<dyn FrameSource>,
+    rate_hz: f32,
+) {
+    let interval = Duration::from_secs_f32(1.0 / rate_hz);
+    thread::spawn(move || loop {
+        let started = Instant::now();
+        match source.next_frame() {
+            Ok(Some(frame)) => *bridge.markers.lock().unwrap() = detector.detect(&frame),
+            Ok(None) => {
+                println!("Camera stopped, no more ArUco detection");
+                bridge.markers.lock().unwrap().clear();
+                return;
+            }
+            Err(e) => eprintln!("Failed to read camera frame: {}", e),
+        }
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::ImageFiles;
+
+    // Paints marker `id` into the quadrilateral `corners`, top-left corner first
+    fn draw(frame: &mut Frame, dictionary: &Dictionary, id: u32, corners: [Point; 4]) {
+        let size = dictionary.size;
+        let cells = size + 2;
+        let code = dictionary.code(id);
+        let homography = Homography::from_unit_square(&corners).unwrap();
+        let steps = 800;
+        for i in 0..steps {
+            for j in 0..steps {
+                let (u, v) = (
+                    (i as f32 + 0.5) / steps as f32,
+                    (j as f32 + 0.5) / steps as f32,
+                );
+                let (col, row) = ((u * cells as f32) as usize, (v * cells as f32) as usize);
+                let inner = (1..=size).contains(&row) && (1..=size).contains(&col);
+                let white = inner && {
+                    let bit = (row - 1) * size + (col - 1);
+                    (code >> (size * size - 1 - bit)) & 1 == 1
+                };
+                let [x, y] = homography.map(u, v);
+                frame.set(x as usize, y as usize, if white { 255 } else { 0 });
+            }
+        }
+    }
+
+    fn assert_corners(marker: &Marker, expected: [Point; 4], tolerance: f32) {
+        for (corner, expected) in marker.corners.iter().zip(expected) {
+            assert!(
+                distance(*corner, expected) <= tolerance,
+                "marker {} corners {:?}, expected {:?}",
+                marker.id,
+                marker.corners,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn detects_rotated_markers() {
+        let detector = Detector::new(Dictionary::aruco_original());
+        let square = [[20.0, 20.0], [90.0, 20.0], [90.0, 90.0], [20.0, 90.0]];
+
+        for rotation in 0..4 {
+            let mut frame = Frame::new(110, 110, 255);
+            // Rotating the marker clockwise moves its top-left corner clockwise
+            let corners = [0, 1, 2, 3].map(|i| square[(i + rotation) % 4]);
+            draw(&mut frame, detector.dictionary(), 417, corners);
+
+            let markers = detector.detect(&frame);
+            assert_eq!(markers.len(), 1, "rotation {}", rotation);
+            assert_eq!(markers[0].id, 417);
+            assert_corners(&markers[0], corners, 1.0);
+        }
+    }
+
+    #[test]
+    fn detects_markers_in_perspective_from_image_file() {
+        let detector = Detector::new(Dictionary::aruco_original());
+        let mut frame = Frame::new(320, 240, 200);
+        let tilted = [[40.0, 50.0], [140.0, 35.0], [150.0, 160.0], [30.0, 140.0]];
+        let small = [
+            [200.0, 100.0],
+            [260.0, 105.0],
+            [255.0, 165.0],
+            [195.0, 160.0],
+        ];
+        draw(&mut frame, detector.dictionary(), 5, tilted);
+        draw(&mut frame, detector.dictionary(), 1000, small);
+
+        let path = std::env::temp_dir().join(format!("rpi-aruco-{}.png", std::process::id()));
+        frame.save(&path).unwrap();
+        let mut source = ImageFiles::open(&path).unwrap();
+        let loaded = source.next_frame().unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let markers = detector.detect(&loaded);
+        assert_eq!(
+            markers.iter().map(|m| m.id).collect::<Vec<_>>(),
+            vec![5, 1000]
+        );
+        assert_corners(&markers[0], tilted, 2.0);
+        assert_corners(&markers[1], small, 2.0);
+    }
+
+    #[test]
+    fn estimates_poses_with_calibration() {
+        let calibration = crate::calibration::Calibration {
+            fx: 600.0,
+            fy: 600.0,
+            cx: 55.0,
+            cy: 55.0,
+            distortion: [0.0; 5],
+        };
+        let detector = Detector::new(Dictionary::aruco_original())
+            .with_poses(PoseEstimator::new(calibration, 0.1));
+        let mut frame = Frame::new(110, 110, 255);
+        let square = [[20.0, 20.0], [90.0, 20.0], [90.0, 90.0], [20.0, 90.0]];
+        draw(&mut frame, detector.dictionary(), 12, square);
+
+        let markers = detector.detect(&frame);
+        let pose = markers[0].pose.expect("pose with a calibration");
+        // 70 pixels wide at a focal length of 600 pixels
+        assert!((pose.translation[2] - 0.1 * 600.0 / 70.0).abs() < 0.02);
+        assert!(pose.translation[0].abs() < 0.005 && pose.translation[1].abs() < 0.005);
+        // Facing the camera, the marker's y axis points up the image
+        assert!((pose.rotation[0].abs() - std::f32::consts::PI).abs() < 0.1);
+    }
+
+    #[test]
+    fn ignores_plain_squares() {
+        let detector = Detector::new(Dictionary::aruco_original());
+        let mut frame = Frame::new(100, 100, 255);
+        for y in 20..80 {
+            for x in 20..80 {
+                frame.set(x, y, 0);
+            }
+        }
+        assert!(detector.detect(&frame).is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+use protocol::MarkerPose;
+
+use super::quad::{Homography, Point};
+use crate::calibration::Calibration;
+use crate::geometry::{
+    add, apply, least_squares, multiply, pose_from_homography, rotation_matrix, Vector,
+};
+
+// Refinement steps of the homography pose
+const MAX_ITERATIONS: usize = 30;
+
+// Estimates marker poses from their corners, like `solvePnP` with
+// `SOLVEPNP_IPPE_SQUARE` in OpenCV
+pub struct PoseEstimator {
+    calibration: Calibration,
+    // Corners of the marker in its own frame, in the same order as detected corners
+    object: [Vector; 4],
+}
+
+impl PoseEstimator {
+    pub fn new(calibration: Calibration, marker_size: f32) -> PoseEstimator {
+        let half = marker_size as f64 / 2.0;
+        PoseEstimator {
+            calibration,
+            object: [
+                [-half, half, 0.0],
+                [half, half, 0.0],
+                [half, -half, 0.0],
+                [-half, -half, 0.0],
+            ],
+        }
+    }
+
+    // Pose of the marker with `corners`, clockwise from its top-left one.
+    // The homography between the marker plane and the undistorted corners gives
+    // a first pose, which is then refined against the reprojection error.
+    pub fn estimate(&self, corners: &[Point; 4]) -> Option<MarkerPose> {
+        let corners = corners.map(|[u, v]| [u as f64, v as f64]);
+        let (rotation, translation) = self.initial_pose(&corners)?;
+        let params = least_squares(
+            [rotation, translation].concat(),
+            |params| self.residuals(&corners, params),
+            MAX_ITERATIONS,
+        );
+        if params[5] <= 0.0 {
+            return None;
+        }
+        Some(MarkerPose {
+            rotation: [params[0] as f32, params[1] as f32, params[2] as f32],
+            translation: [params[3] as f32, params[4] as f32, params[5] as f32],
+        })
+    }
+
+    fn initial_pose(&self, corners: &[[f64; 2]; 4]) -> Option<(Vector, Vector)> {
+        let normalized = corners.map(|corner| {
+            let [x, y] = self.calibration.undistort(corner);
+            [x as f32, y as f32]
+        });
+        let unit = Homography::from_unit_square(&normalized)?.matrix();
+
+        // Marker point (x, y) sits at (x / size + 1/2, 1/2 - y / size) on the unit square
+        let size = self.object[1][0] - self.object[0][0];
+        let to_unit = [
+            [1.0 / size, 0.0, 0.5],
+            [0.0, -1.0 / size, 0.5],
+            [0.0, 0.0, 1.0],
+        ];
+        Some(pose_from_homography(&multiply(&unit, &to_unit)))
+    }
+
+    // Pixel offsets between the projected and detected corners, `params` is the
+    // rotation vector followed by the translation
+    fn residuals(&self, corners: &[[f64; 2]; 4], params: &[f64]) -> Vec<f64> {
+        let rotation = rotation_matrix([params[0], params[1], params[2]]);
+        let translation = [params[3], params[4], params[5]];
+        let mut residuals = Vec::with_capacity(8);
+        for (point, corner) in self.object.iter().zip(corners) {
+            let camera = add(apply(&rotation, *point), translation);
+            let [u, v] = self.calibration.project(camera);
+            residuals.extend([u - corner[0], v - corner[1]]);
+        }
+        residuals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Calibration {
+        Calibration {
+            fx: 600.0,
+            fy: 600.0,
+            cx: 320.0,
+            cy: 240.0,
+            distortion: [0.05, -0.1, 0.0, 0.0, 0.0],
+        }
+    }
+
+    #[test]
+    fn recovers_projected_pose() {
+        let camera = camera();
+        let estimator = PoseEstimator::new(camera.clone(), 0.2);
+        for (rotation, translation) in [
+            // Straight below a camera looking down
+            ([0.0, 0.0, 0.0], [0.0, 0.0, 1.5]),
+            ([0.4, -0.3, 0.2], [0.25, -0.1, 1.2]),
+            ([-0.6, 0.1, 2.5], [-0.3, 0.2, 2.0]),
+        ] {
+            let matrix = rotation_matrix(rotation);
+            let corners = estimator.object.map(|point| {
+                let [u, v] = camera.project(add(apply(&matrix, point), translation));
+                [u as f32, v as f32]
+            });
+
+            let pose = estimator.estimate(&corners).unwrap();
+            for i in 0..3 {
+                assert!(
+                    (pose.rotation[i] as f64 - rotation[i]).abs() < 1e-3,
+                    "rotation {:?}, expected {:?}",
+                    pose.rotation,
+                    rotation
+                );
+                assert!(
+                    (pose.translation[i] as f64 - translation[i]).abs() < 1e-3,
+                    "translation {:?}, expected {:?}",
+                    pose.translation,
+                    translation
+                );
+            }
+        }
+    }
+}
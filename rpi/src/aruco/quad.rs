@@ -0,0 +1,284 @@
+use crate::camera::Frame;
+use crate::geometry::solve;
+
+pub type Point = [f32; 2];
+
+// Dark pixels compared to the mean of the surrounding `window` x `window` pixels
+pub fn adaptive_threshold(frame: &Frame, window: usize, offset: u32) -> Vec<bool> {
+    let (width, height) = (frame.width, frame.height);
+
+    // Summed-area table, one row and column larger than the image
+    let mut integral = vec![0u32; (width + 1) * (height + 1)];
+    for y in 0..height {
+        let mut row = 0;
+        for x in 0..width {
+            row += frame.get(x, y) as u32;
+            integral[(y + 1) * (width + 1) + x + 1] = integral[y * (width + 1) + x + 1] + row;
+        }
+    }
+
+    let half = window / 2;
+    let mut dark = vec![false; width * height];
+    for y in 0..height {
+        let (y0, y1) = (y.saturating_sub(half), (y + half + 1).min(height));
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(half), (x + half + 1).min(width));
+            let sum = integral[y1 * (width + 1) + x1] + integral[y0 * (width + 1) + x0]
+                - integral[y0 * (width + 1) + x1]
+                - integral[y1 * (width + 1) + x0];
+            let area = ((x1 - x0) * (y1 - y0)) as u32;
+            dark[y * width + x] = (frame.get(x, y) as u32 + offset) * area < sum;
+        }
+    }
+    dark
+}
+
+// Outlines of the 8-connected dark regions that do not touch the image edge.
+// Every outline is the list of pixel corners on the region boundary.
+pub fn outlines(dark: &[bool], width: usize, height: usize, min_pixels: usize) -> Vec<Vec<Point>> {
+    let mut visited = vec![false; dark.len()];
+    let mut outlines = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..dark.len() {
+        if !dark[start] || visited[start] {
+            continue;
+        }
+
+        let mut pixels = 0;
+        let mut touches_edge = false;
+        let mut boundary = Vec::new();
+        visited[start] = true;
+        stack.push(start);
+
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            pixels += 1;
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                touches_edge = true;
+                continue;
+            }
+
+            let mut on_boundary = false;
+            for (dx, dy) in [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ] {
+                let neighbour = (y as isize + dy) as usize * width + (x as isize + dx) as usize;
+                if !dark[neighbour] {
+                    on_boundary = true;
+                } else if !visited[neighbour] {
+                    visited[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+            if on_boundary {
+                let (x, y) = (x as f32, y as f32);
+                boundary.extend([[x, y], [x + 1.0, y], [x + 1.0, y + 1.0], [x, y + 1.0]]);
+            }
+        }
+
+        if !touches_edge && pixels >= min_pixels {
+            outlines.push(boundary);
+        }
+    }
+    outlines
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f32 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+fn area(polygon: &[Point]) -> f32 {
+    let mut sum = 0.0;
+    for i in 0..polygon.len() {
+        sum += cross([0.0, 0.0], polygon[i], polygon[(i + 1) % polygon.len()]);
+    }
+    sum.abs() / 2.0
+}
+
+// Convex hull by the monotone chain algorithm
+pub fn convex_hull(mut points: Vec<Point>) -> Vec<Point> {
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut hull: Vec<Point> = Vec::with_capacity(points.len() * 2);
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &Point>> = if pass == 0 {
+            Box::new(points.iter())
+        } else {
+            Box::new(points.iter().rev())
+        };
+        for &point in iter {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        hull.pop();
+    }
+    hull
+}
+
+// Fits a quadrilateral to a convex hull, returns its corners clockwise in image
+// coordinates starting from the top-left one. `None` if the quadrilateral covers
+// less than `min_fill` of the hull or has a side shorter than `min_side` pixels.
+pub fn fit_quad(hull: &[Point], min_side: f32, min_fill: f32) -> Option<[Point; 4]> {
+    if hull.len() < 4 {
+        return None;
+    }
+    let count = hull.len() as f32;
+    let center = hull.iter().fold([0.0, 0.0], |sum, p| {
+        [sum[0] + p[0] / count, sum[1] + p[1] / count]
+    });
+    let farthest = |from: Point| {
+        *hull
+            .iter()
+            .max_by(|a, b| {
+                distance(**a, from)
+                    .partial_cmp(&distance(**b, from))
+                    .unwrap()
+            })
+            .unwrap()
+    };
+
+    // Two opposite corners, then the points farthest from their diagonal on each side
+    let a = farthest(center);
+    let c = farthest(a);
+    let side = |p: &&Point| cross(a, c, **p);
+    let b = *hull
+        .iter()
+        .max_by(|p, q| side(p).partial_cmp(&side(q)).unwrap())?;
+    let d = *hull
+        .iter()
+        .min_by(|p, q| side(p).partial_cmp(&side(q)).unwrap())?;
+    if cross(a, c, b) <= 0.0 || cross(a, c, d) >= 0.0 {
+        return None;
+    }
+
+    let mut quad = [a, b, c, d];
+    if quad
+        .iter()
+        .enumerate()
+        .any(|(i, p)| distance(*p, quad[(i + 1) % 4]) < min_side)
+    {
+        return None;
+    }
+    if area(&quad) < min_fill * area(hull) {
+        return None;
+    }
+
+    // Clockwise on screen is increasing angle with y pointing down
+    let center = [
+        quad.iter().map(|p| p[0]).sum::<f32>() / 4.0,
+        quad.iter().map(|p| p[1]).sum::<f32>() / 4.0,
+    ];
+    let angle = |p: &Point| (p[1] - center[1]).atan2(p[0] - center[0]);
+    quad.sort_by(|p, q| angle(p).partial_cmp(&angle(q)).unwrap());
+    let top_left = (0..4)
+        .min_by(|&i, &j| {
+            (quad[i][0] + quad[i][1])
+                .partial_cmp(&(quad[j][0] + quad[j][1]))
+                .unwrap()
+        })
+        .unwrap();
+    quad.rotate_left(top_left);
+    Some(quad)
+}
+
+// Projective transform from the unit square to an image quadrilateral
+pub struct Homography([f32; 8]);
+
+impl Homography {
+    // Maps (0, 0), (1, 0), (1, 1), (0, 1) to the corners in order
+    pub fn from_unit_square(corners: &[Point; 4]) -> Option<Homography> {
+        let square = [[0.0f64, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let mut a = [[0.0; 8]; 8];
+        let mut b = [0.0; 8];
+        for i in 0..4 {
+            let [u, v] = square[i];
+            let (x, y) = (corners[i][0] as f64, corners[i][1] as f64);
+            a[2 * i] = [u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x];
+            a[2 * i + 1] = [0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y];
+            b[2 * i] = x;
+            b[2 * i + 1] = y;
+        }
+        let h = solve(a, b)?;
+        Some(Homography(h.map(|value| value as f32)))
+    }
+
+    // Row-major 3x3 matrix acting on homogeneous coordinates
+    pub fn matrix(&self) -> [[f64; 3]; 3] {
+        let h = self.0.map(|value| value as f64);
+        [[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], 1.0]]
+    }
+
+    pub fn map(&self, u: f32, v: f32) -> Point {
+        let h = &self.0;
+        let w = h[6] * u + h[7] * v + 1.0;
+        [
+            (h[0] * u + h[1] * v + h[2]) / w,
+            (h[3] * u + h[4] * v + h[5]) / w,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_square() {
+        let mut points = Vec::new();
+        for i in 0..=20 {
+            let t = i as f32;
+            points.extend([
+                [10.0 + t, 10.0],
+                [30.0, 10.0 + t],
+                [10.0 + t, 30.0],
+                [10.0, 10.0 + t],
+            ]);
+        }
+        let hull = convex_hull(points);
+        assert_eq!(hull.len(), 4);
+        assert_eq!(
+            fit_quad(&hull, 5.0, 0.9),
+            Some([[10.0, 10.0], [30.0, 10.0], [30.0, 30.0], [10.0, 30.0]])
+        );
+        assert_eq!(fit_quad(&hull, 25.0, 0.9), None);
+    }
+
+    #[test]
+    fn homography_maps_corners() {
+        let corners = [[10.0, 12.0], [50.0, 8.0], [60.0, 70.0], [5.0, 55.0]];
+        let homography = Homography::from_unit_square(&corners).unwrap();
+        for (corner, (u, v)) in corners
+            .iter()
+            .zip([(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)])
+        {
+            let mapped = homography.map(u, v);
+            assert!(
+                distance(mapped, *corner) < 1e-3,
+                "{:?} != {:?}",
+                mapped,
+                corner
+            );
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use tokio_tungstenite::tungstenite::handshake::server::{
+    Callback, ErrorResponse, Request, Response,
+};
+use tokio_tungstenite::tungstenite::http::{header, HeaderValue, StatusCode};
+
+use crate::config::AuthConfig;
+
+// What a client may do, decided on the WebSocket handshake
+#[derive(Debug, Clone, PartialEq)]
+pub enum Access {
+    // No token is configured
+    Open,
+    // Sent `auth.token`
+    Shared,
+    // Sent the key of this client in `auth.clients`
+    Client(String),
+    // No valid token, may watch with `auth.allow_observers`
+    Observer,
+    Denied,
+}
+
+impl Access {
+    // Whether the client may send commands and requests
+    pub fn authenticated(&self) -> bool {
+        matches!(self, Access::Open | Access::Shared | Access::Client(_))
+    }
+}
+
+// Checks the `Authorization: Bearer <token>` header of a handshake
+pub fn authorize(config: &AuthConfig, authorization: Option<&str>) -> Access {
+    if !config.enabled() {
+        return Access::Open;
+    }
+    let token = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    if let Some(token) = token {
+        if config.token.as_deref().is_some_and(|key| same(key, token)) {
+            return Access::Shared;
+        }
+        // Every key is compared, the time taken does not tell which one matched
+        let mut client = None;
+        for (name, key) in &config.clients {
+            if same(key, token) {
+                client = Some(name);
+            }
+        }
+        if let Some(name) = client {
+            return Access::Client(name.clone());
+        }
+    }
+    match config.allow_observers {
+        true => Access::Observer,
+        false => Access::Denied,
+    }
+}
+
+// Constant time for tokens of the key's length
+fn same(key: &str, token: &str) -> bool {
+    key.len() == token.len()
+        && key
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// Decides the access of a client on its handshake, denied clients get 401
+pub struct Handshake<'a> {
+    pub config: &'a AuthConfig,
+    pub access: &'a mut Access,
+}
+
+impl Callback for Handshake<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let authorization = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        *self.access = authorize(self.config, authorization);
+        if *self.access != Access::Denied {
+            return Ok(response);
+        }
+        let mut error = ErrorResponse::new(Some("missing or invalid token".to_string()));
+        *error.status_mut() = StatusCode::UNAUTHORIZED;
+        error
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_tokens() {
+        let mut config = AuthConfig::default();
+        assert_eq!(authorize(&config, None), Access::Open);
+
+        config.token = Some("shared-secret".to_string());
+        config
+            .clients
+            .insert("laptop".to_string(), "laptop-key".to_string());
+        assert_eq!(
+            authorize(&config, Some("Bearer shared-secret")),
+            Access::Shared
+        );
+        assert_eq!(
+            authorize(&config, Some("Bearer laptop-key")),
+            Access::Client("laptop".to_string())
+        );
+        assert_eq!(
+            authorize(&config, Some("Bearer laptop-kez")),
+            Access::Denied
+        );
+        assert_eq!(authorize(&config, Some("laptop-key")), Access::Denied);
+        assert_eq!(authorize(&config, None), Access::Denied);
+
+        config.allow_observers = true;
+        assert_eq!(authorize(&config, Some("Bearer shared")), Access::Observer);
+        assert!(!Access::Observer.authenticated());
+        assert!(Access::Client("laptop".to_string()).authenticated());
+    }
+}
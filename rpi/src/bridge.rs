@@ -0,0 +1,75 @@
+use protocol::{BridgeEvent, CompanionHealth, GpsFix, Marker};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::{broadcast, watch};
+
+use crate::config::Config;
+use crate::control::Control;
+use crate::delivery::{Tracker, Update};
+use crate::failsafe::Watchdog;
+use crate::recorder::Recorder;
+use crate::replay::Playback;
+use crate::serial::SerialLink;
+
+// State shared by every client connection
+pub struct Bridge {
+    pub config: Config,
+    pub serial: SerialLink,
+    pub control: Control,
+    pub watchdog: Watchdog,
+    pub recorder: Recorder,
+    // Set when serving a recorded session instead of the serial port
+    pub replay: Option<watch::Sender<Playback>>,
+    pub events: broadcast::Sender<BridgeEvent>,
+    // Delivery of sequenced commands, reported to the client that sent them
+    pub deliveries: Tracker,
+    pub acks: broadcast::Sender<Update>,
+    // Reported to every client that connects afterwards
+    pub last_failsafe: Mutex<Option<BridgeEvent>>,
+    // Set while the serial port is down, for clients that connect meanwhile
+    pub link_down: Mutex<Option<BridgeEvent>>,
+    // Latest ArUco detection, empty without a camera
+    pub markers: Mutex<Vec<Marker>>,
+    // Latest health sample of the Pi, `None` when disabled
+    pub companion: Mutex<Option<CompanionHealth>>,
+    // Latest GPS fix, `None` while the receiver is silent
+    pub gps: Mutex<Option<GpsFix>>,
+    // Connected WebSocket clients
+    pub clients: AtomicUsize,
+    pub started: Instant,
+    next_client_id: AtomicU64,
+}
+
+impl Bridge {
+    pub fn new(
+        config: Config,
+        serial: SerialLink,
+        recorder: Recorder,
+        replay: Option<watch::Sender<Playback>>,
+    ) -> Self {
+        Self {
+            config,
+            serial,
+            control: Control::default(),
+            watchdog: Watchdog::default(),
+            recorder,
+            replay,
+            events: broadcast::channel(16).0,
+            deliveries: Tracker::default(),
+            acks: broadcast::channel(100).0,
+            last_failsafe: Mutex::new(None),
+            link_down: Mutex::new(None),
+            markers: Mutex::new(Vec::new()),
+            companion: Mutex::new(None),
+            gps: Mutex::new(None),
+            clients: AtomicUsize::new(0),
+            started: Instant::now(),
+            next_client_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn next_client_id(&self) -> u64 {
+        self.next_client_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::aruco::quad::{adaptive_threshold, convex_hull, fit_quad, outlines, Point};
+use crate::camera::Frame;
+use crate::geometry::solve;
+
+// Threshold windows as a fraction of the shorter image side
+const WINDOW_DIVISORS: [usize; 3] = [4, 8, 16];
+const THRESHOLD_OFFSET: u32 = 5;
+// The dark squares touch at their corners, shrinking them by a few pixels separates them
+const EROSIONS: [usize; 3] = [1, 2, 3];
+// Smallest square side in pixels
+const MIN_SQUARE_SIDE: f32 = 6.0;
+// Small squares lose their corners to thresholding and erosion, so their
+// outlines are only roughly square
+const MIN_SQUARE_FILL: f32 = 0.75;
+// Corners of two squares closer than this share of the square side are the same
+// board corner
+const MAX_LINK_DISTANCE: f32 = 0.5;
+const SUBPIXEL_ITERATIONS: usize = 20;
+
+// Board corner each corner of a dark square touches, clockwise from the top-left
+// one, as offsets of the diagonal neighbour square
+const NEIGHBOURS: [(i32, i32); 4] = [(-1, -1), (1, -1), (1, 1), (-1, 1)];
+const LATTICE: [(i32, i32); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+
+// Grid position of a dark square and the rotation of its corner labels
+type Cell = ((i32, i32), usize);
+
+// Finds the `columns` x `rows` inner corners of a checkerboard, like OpenCV's
+// `findChessboardCorners` with `patternSize = (columns, rows)`. The corners are
+// returned row by row, `None` unless the whole board is visible.
+pub fn find_corners(frame: &Frame, columns: usize, rows: usize) -> Option<Vec<[f64; 2]>> {
+    let shortest = frame.width.min(frame.height);
+    for divisor in WINDOW_DIVISORS {
+        let window = (shortest / divisor).max(3) | 1;
+        let dark = adaptive_threshold(frame, window, THRESHOLD_OFFSET);
+        let mut eroded = dark;
+        let mut eroded_by = 0;
+        for erosion in EROSIONS {
+            while eroded_by < erosion {
+                eroded = erode(&eroded, frame.width, frame.height);
+                eroded_by += 1;
+            }
+            let squares = find_squares(&eroded, frame.width, frame.height, erosion);
+            if let Some(corners) = assemble(&squares, columns, rows) {
+                return Some(
+                    corners
+                        .into_iter()
+                        .map(|(corner, side)| refine(frame, corner, side))
+                        .collect(),
+                );
+            }
+        }
+    }
+    None
+}
+
+// Keeps the dark pixels whose 8 neighbours are all dark
+fn erode(dark: &[bool], width: usize, height: usize) -> Vec<bool> {
+    let mut eroded = vec![false; dark.len()];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width - 1 {
+            eroded[y * width + x] = (y - 1..=y + 1).all(|y| {
+                dark[y * width + x - 1..=y * width + x + 1]
+                    .iter()
+                    .all(|d| *d)
+            });
+        }
+    }
+    eroded
+}
+
+// Dark squares in the eroded image, grown back by the `erosion` pixels they lost
+fn find_squares(dark: &[bool], width: usize, height: usize, erosion: usize) -> Vec<[Point; 4]> {
+    let min_pixels = (MIN_SQUARE_SIDE * MIN_SQUARE_SIDE) as usize;
+    outlines(dark, width, height, min_pixels)
+        .into_iter()
+        .filter_map(|outline| fit_quad(&convex_hull(outline), MIN_SQUARE_SIDE, MIN_SQUARE_FILL))
+        .map(|square| {
+            let center = [
+                square.iter().map(|p| p[0]).sum::<f32>() / 4.0,
+                square.iter().map(|p| p[1]).sum::<f32>() / 4.0,
+            ];
+            let scale = 1.0 + 2.0 * erosion as f32 / side(&square);
+            square.map(|p| {
+                [
+                    center[0] + (p[0] - center[0]) * scale,
+                    center[1] + (p[1] - center[1]) * scale,
+                ]
+            })
+        })
+        .collect()
+}
+
+fn distance(a: Point, b: Point) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+fn side(square: &[Point; 4]) -> f32 {
+    (0..4)
+        .map(|i| distance(square[i], square[(i + 1) % 4]))
+        .sum::<f32>()
+        / 4.0
+}
+
+// Puts the squares on a grid through their shared corners. Returns every inner
+// corner with the side of its squares, row by row.
+fn assemble(squares: &[[Point; 4]], columns: usize, rows: usize) -> Option<Vec<(Point, f32)>> {
+    if squares.len() < 2 {
+        return None;
+    }
+
+    // Pairs up the corners of diagonal neighbours, each corner with its nearest one
+    let nearest = |a: usize, k: usize| {
+        let limit = MAX_LINK_DISTANCE * side(&squares[a]);
+        let mut best: Option<(usize, usize, f32)> = None;
+        for (b, square) in squares.iter().enumerate() {
+            if b == a {
+                continue;
+            }
+            for (m, corner) in square.iter().enumerate() {
+                let d = distance(squares[a][k], *corner);
+                if d < limit && best.is_none_or(|(_, _, best)| d < best) {
+                    best = Some((b, m, d));
+                }
+            }
+        }
+        best.map(|(b, m, _)| (b, m))
+    };
+    let links: Vec<[Option<(usize, usize)>; 4]> = (0..squares.len())
+        .map(|a| {
+            std::array::from_fn(|k| nearest(a, k).filter(|&(b, m)| nearest(b, m) == Some((a, k))))
+        })
+        .collect();
+
+    // Every square gets a cell and a rotation of its corners so that its labelled
+    // corner `l` is `square[(l + rotation) % 4]`. Squares seen at an angle do not
+    // agree on their top-left corner, so the rotation follows the neighbours.
+    let mut best: Option<HashMap<usize, Cell>> = None;
+    let mut seen = vec![false; squares.len()];
+    for start in 0..squares.len() {
+        if seen[start] {
+            continue;
+        }
+        let mut cells = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        cells.insert(start, ((0, 0), 0));
+        seen[start] = true;
+        while let Some(a) = queue.pop_front() {
+            let ((x, y), rotation) = cells[&a];
+            for (label, (dx, dy)) in NEIGHBOURS.iter().enumerate() {
+                let Some((b, m)) = links[a][(label + rotation) % 4] else {
+                    continue;
+                };
+                // The neighbour touches with its opposite corner
+                let placed = ((x + dx, y + dy), (m + 4 - (label + 2) % 4) % 4);
+                match cells.get(&b) {
+                    Some(existing) if *existing != placed => return None,
+                    Some(_) => {}
+                    None => {
+                        cells.insert(b, placed);
+                        seen[b] = true;
+                        queue.push_back(b);
+                    }
+                }
+            }
+        }
+        if best.as_ref().is_none_or(|best| cells.len() > best.len()) {
+            best = Some(cells);
+        }
+    }
+    let cells = best?;
+
+    // Inner corners are the ones shared by two squares
+    let mut corners: HashMap<(i32, i32), (Point, f32)> = HashMap::new();
+    for (&a, &((x, y), rotation)) in &cells {
+        for (label, (dx, dy)) in LATTICE.iter().enumerate() {
+            let k = (label + rotation) % 4;
+            if let Some((b, m)) = links[a][k] {
+                let (p, q) = (squares[a][k], squares[b][m]);
+                let middle = [(p[0] + q[0]) / 2.0, (p[1] + q[1]) / 2.0];
+                let size = (side(&squares[a]) + side(&squares[b])) / 2.0;
+                corners.insert((x + dx, y + dy), (middle, size));
+            }
+        }
+    }
+    if corners.len() != columns * rows {
+        return None;
+    }
+
+    let min_x = corners.keys().map(|(x, _)| *x).min()?;
+    let min_y = corners.keys().map(|(_, y)| *y).min()?;
+    let width = (corners.keys().map(|(x, _)| *x).max()? - min_x + 1) as usize;
+    let height = (corners.keys().map(|(_, y)| *y).max()? - min_y + 1) as usize;
+    // A board turned by a quarter has its rows along the other axis
+    let position = |col: usize, row: usize| {
+        let (col, row) = (col as i32, row as i32);
+        if (width, height) == (columns, rows) {
+            Some((min_x + col, min_y + row))
+        } else if (width, height) == (rows, columns) {
+            Some((min_x + row, min_y + columns as i32 - 1 - col))
+        } else {
+            None
+        }
+    };
+    let mut ordered = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for col in 0..columns {
+            ordered.push(*corners.get(&position(col, row)?)?);
+        }
+    }
+    Some(ordered)
+}
+
+// Moves `corner` onto the saddle point of the image, like OpenCV's `cornerSubPix`.
+// Image gradients around a checkerboard corner are all orthogonal to the line
+// to the corner, which gives a 2x2 linear system for its position.
+fn refine(frame: &Frame, corner: Point, side: f32) -> [f64; 2] {
+    // Outlines use pixel corners, pixel centers are whole numbers here
+    let mut position = [corner[0] as f64 - 0.5, corner[1] as f64 - 0.5];
+    let half = (side / 4.0).clamp(2.0, 8.0) as i64;
+
+    for _ in 0..SUBPIXEL_ITERATIONS {
+        let (cx, cy) = (position[0].round() as i64, position[1].round() as i64);
+        let mut a = [[0.0; 2]; 2];
+        let mut b = [0.0; 2];
+        for y in cy - half..=cy + half {
+            for x in cx - half..=cx + half {
+                if x < 1 || y < 1 || x >= frame.width as i64 - 1 || y >= frame.height as i64 - 1 {
+                    continue;
+                }
+                let (ux, uy) = (x as usize, y as usize);
+                let gx = (frame.get(ux + 1, uy) as f64 - frame.get(ux - 1, uy) as f64) / 2.0;
+                let gy = (frame.get(ux, uy + 1) as f64 - frame.get(ux, uy - 1) as f64) / 2.0;
+                let (gxx, gxy, gyy) = (gx * gx, gx * gy, gy * gy);
+                a[0][0] += gxx;
+                a[0][1] += gxy;
+                a[1][0] += gxy;
+                a[1][1] += gyy;
+                b[0] += gxx * x as f64 + gxy * y as f64;
+                b[1] += gxy * x as f64 + gyy * y as f64;
+            }
+        }
+        let Some(next) = solve(a, b) else {
+            break;
+        };
+        let moved = (next[0] - position[0]).hypot(next[1] - position[1]);
+        // Stay near the detected corner on flat or noisy patches
+        if (next[0] - corner[0] as f64).hypot(next[1] - corner[1] as f64) > side as f64 / 4.0 {
+            break;
+        }
+        position = next;
+        if moved < 0.01 {
+            break;
+        }
+    }
+    position
+}
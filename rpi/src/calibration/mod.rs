@@ -0,0 +1,602 @@
+mod checkerboard;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::camera::{Frame, ImageFiles};
+use crate::config::CalibrateArgs;
+use crate::geometry::{
+    add, apply, least_squares, multiply, pose_from_homography, rotation_matrix, solve,
+    solve_in_place, Matrix, Vector,
+};
+
+// How many fixed-point steps `undistort` takes, plenty for lens distortion
+const UNDISTORT_ITERATIONS: usize = 20;
+// Board views needed to calibrate, more in different tilts give better results
+const MIN_VIEWS: usize = 3;
+const MAX_ITERATIONS: usize = 100;
+// fx, fy, cx, cy, k1, k2, p1, p2 are fitted. k3 stays 0 like with OpenCV's
+// `CALIB_FIX_K3`, it only matters for wide angle lenses and overfits otherwise.
+const INTRINSICS: usize = 8;
+
+// Pinhole camera intrinsics with OpenCV's distortion model, read from a TOML file:
+//
+//     fx = 610.2
+//     fy = 609.8
+//     cx = 319.5
+//     cy = 241.1
+//     distortion = [0.12, -0.25, 0.001, -0.002, 0.1]
+//
+// The calibration only holds for the image resolution it was computed at.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Calibration {
+    // Focal lengths and principal point in pixels
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    // k1, k2, p1, p2, k3 in OpenCV's order
+    #[serde(default)]
+    pub distortion: [f64; 5],
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+    Image(PathBuf, std::io::Error),
+    Write(PathBuf, std::io::Error),
+    Failed(String),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::Read(path, e) => {
+                write!(f, "failed to read calibration {}: {}", path.display(), e)
+            }
+            CalibrationError::Parse(path, e) => {
+                write!(f, "failed to parse calibration {}: {}", path.display(), e)
+            }
+            CalibrationError::Invalid(message) => write!(f, "invalid calibration: {}", message),
+            CalibrationError::Image(path, e) => {
+                write!(f, "failed to read image {}: {}", path.display(), e)
+            }
+            CalibrationError::Write(path, e) => {
+                write!(f, "failed to write calibration {}: {}", path.display(), e)
+            }
+            CalibrationError::Failed(message) => write!(f, "calibration failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+impl Calibration {
+    pub fn load(path: &Path) -> Result<Calibration, CalibrationError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CalibrationError::Read(path.to_owned(), e))?;
+        let calibration: Calibration =
+            toml::from_str(&text).map_err(|e| CalibrationError::Parse(path.to_owned(), e))?;
+        calibration.validate()?;
+        Ok(calibration)
+    }
+
+    pub fn validate(&self) -> Result<(), CalibrationError> {
+        if !(self.fx.is_finite() && self.fy.is_finite() && self.fx > 0.0 && self.fy > 0.0) {
+            return Err(CalibrationError::Invalid(format!(
+                "fx and fy must be positive numbers, got {} and {}",
+                self.fx, self.fy
+            )));
+        }
+        if !(self.cx.is_finite() && self.cy.is_finite())
+            || self.distortion.iter().any(|k| !k.is_finite())
+        {
+            return Err(CalibrationError::Invalid(
+                "cx, cy and distortion must be finite".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Applies lens distortion to a point on the normalized image plane (z = 1)
+    pub fn distort(&self, [x, y]: [f64; 2]) -> [f64; 2] {
+        let [k1, k2, p1, p2, k3] = self.distortion;
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+        [
+            x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+            y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y,
+        ]
+    }
+
+    // Pixel a point in the camera frame is seen at
+    pub fn project(&self, [x, y, z]: [f64; 3]) -> [f64; 2] {
+        let [x, y] = self.distort([x / z, y / z]);
+        [self.fx * x + self.cx, self.fy * y + self.cy]
+    }
+
+    // Inverse of `project` up to depth, returns the point on the normalized image plane
+    pub fn undistort(&self, [u, v]: [f64; 2]) -> [f64; 2] {
+        let distorted = [(u - self.cx) / self.fx, (v - self.cy) / self.fy];
+        let mut point = distorted;
+        for _ in 0..UNDISTORT_ITERATIONS {
+            let error = self.distort(point);
+            point = [
+                point[0] + distorted[0] - error[0],
+                point[1] + distorted[1] - error[1],
+            ];
+        }
+        point
+    }
+}
+
+// Checkerboard used for calibration
+#[derive(Debug, Clone, Copy)]
+pub struct Board {
+    // Inner corners per row and per column
+    pub columns: usize,
+    pub rows: usize,
+    // Side of one square in metres
+    pub square: f64,
+}
+
+impl Board {
+    // Inner corners on the board plane, row by row like `checkerboard::find_corners`
+    fn points(&self) -> Vec<[f64; 2]> {
+        (0..self.rows)
+            .flat_map(|row| {
+                (0..self.columns)
+                    .map(move |col| [col as f64 * self.square, row as f64 * self.square])
+            })
+            .collect()
+    }
+}
+
+pub struct Report {
+    pub calibration: Calibration,
+    // Root mean square distance in pixels between the detected corners and the
+    // corners projected with the calibration, over all views and for each view
+    pub error: f64,
+    pub view_errors: Vec<f64>,
+}
+
+// Zhang's method as in OpenCV's `calibrateCamera`: a first guess of the focal
+// lengths from the homography of every view, with the principal point at the
+// image center and no distortion, then a least squares fit of the intrinsics
+// and every board pose to the detected corners
+pub fn calibrate(
+    views: &[Vec<[f64; 2]>],
+    board: &Board,
+    width: usize,
+    height: usize,
+) -> Result<Report, CalibrationError> {
+    if views.len() < MIN_VIEWS {
+        return Err(CalibrationError::Failed(format!(
+            "the board was found in {} images, at least {} are needed",
+            views.len(),
+            MIN_VIEWS
+        )));
+    }
+    let points = board.points();
+    let homographies = views
+        .iter()
+        .map(|corners| fit_homography(&points, corners))
+        .collect::<Option<Vec<Matrix>>>()
+        .ok_or_else(|| CalibrationError::Failed("degenerate board view".into()))?;
+
+    let (cx, cy) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0);
+    let (fx, fy) = initial_focal_lengths(&homographies, cx, cy).ok_or_else(|| {
+        CalibrationError::Failed("the board needs to be tilted differently in the images".into())
+    })?;
+
+    // Intrinsics followed by the rotation vector and translation of every view
+    let inverse = [
+        [1.0 / fx, 0.0, -cx / fx],
+        [0.0, 1.0 / fy, -cy / fy],
+        [0.0, 0.0, 1.0],
+    ];
+    let mut params = vec![fx, fy, cx, cy, 0.0, 0.0, 0.0, 0.0];
+    for h in &homographies {
+        let (rotation, translation) = pose_from_homography(&multiply(&inverse, h));
+        params.extend(rotation);
+        params.extend(translation);
+    }
+
+    let residuals = |params: &[f64]| {
+        let calibration = Calibration::from_params(params);
+        let mut residuals = Vec::new();
+        for (view, corners) in views.iter().enumerate() {
+            let pose = &params[INTRINSICS + 6 * view..INTRINSICS + 6 * (view + 1)];
+            residuals.extend(reprojection(&calibration, pose, &points, corners));
+        }
+        residuals
+    };
+    let params = least_squares(params, residuals, MAX_ITERATIONS);
+
+    let calibration = Calibration::from_params(&params);
+    calibration.validate()?;
+    let rms = |residuals: &[f64]| {
+        (residuals.iter().map(|r| r * r).sum::<f64>() / (residuals.len() / 2) as f64).sqrt()
+    };
+    let view_errors = views
+        .iter()
+        .enumerate()
+        .map(|(view, corners)| {
+            let pose = &params[INTRINSICS + 6 * view..INTRINSICS + 6 * (view + 1)];
+            rms(&reprojection(&calibration, pose, &points, corners))
+        })
+        .collect();
+    Ok(Report {
+        error: rms(&residuals(&params)),
+        calibration,
+        view_errors,
+    })
+}
+
+impl Calibration {
+    fn from_params(params: &[f64]) -> Calibration {
+        Calibration {
+            fx: params[0],
+            fy: params[1],
+            cx: params[2],
+            cy: params[3],
+            distortion: [params[4], params[5], params[6], params[7], 0.0],
+        }
+    }
+}
+
+// Pixel offsets between the projected board points and the detected corners
+fn reprojection(
+    calibration: &Calibration,
+    pose: &[f64],
+    points: &[[f64; 2]],
+    corners: &[[f64; 2]],
+) -> Vec<f64> {
+    let rotation = rotation_matrix([pose[0], pose[1], pose[2]]);
+    let translation = [pose[3], pose[4], pose[5]];
+    let mut residuals = Vec::with_capacity(points.len() * 2);
+    for ([x, y], corner) in points.iter().zip(corners) {
+        let camera: Vector = add(apply(&rotation, [*x, *y, 0.0]), translation);
+        let [u, v] = calibration.project(camera);
+        residuals.extend([u - corner[0], v - corner[1]]);
+    }
+    residuals
+}
+
+// Least squares homography from `from` to `to` (DLT), on points shifted and
+// scaled around their centroid to keep the equations well conditioned
+fn fit_homography(from: &[[f64; 2]], to: &[[f64; 2]]) -> Option<Matrix> {
+    let normalizer = |points: &[[f64; 2]]| {
+        let count = points.len() as f64;
+        let (mx, my) = points
+            .iter()
+            .fold((0.0, 0.0), |(x, y), p| (x + p[0] / count, y + p[1] / count));
+        let spread = points
+            .iter()
+            .map(|p| (p[0] - mx).hypot(p[1] - my))
+            .sum::<f64>()
+            / count;
+        let scale = std::f64::consts::SQRT_2 / spread;
+        [
+            [scale, 0.0, -scale * mx],
+            [0.0, scale, -scale * my],
+            [0.0, 0.0, 1.0],
+        ]
+    };
+    let (from_norm, to_norm) = (normalizer(from), normalizer(to));
+
+    let mut normal = [[0.0; 8]; 8];
+    let mut rhs = [0.0; 8];
+    for (p, q) in from.iter().zip(to) {
+        let [x, y, _] = apply(&from_norm, [p[0], p[1], 1.0]);
+        let [u, v, _] = apply(&to_norm, [q[0], q[1], 1.0]);
+        for (row, value) in [
+            ([x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u], u),
+            ([0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v], v),
+        ] {
+            for j in 0..8 {
+                rhs[j] += row[j] * value;
+                for k in 0..8 {
+                    normal[j][k] += row[j] * row[k];
+                }
+            }
+        }
+    }
+    let h = solve(normal, rhs)?;
+    let h = [[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], 1.0]];
+
+    // Undo the normalization, H = T_to^-1 h T_from
+    let [[s, _, tx], [_, _, ty], _] = to_norm;
+    let to_inverse = [
+        [1.0 / s, 0.0, -tx / s],
+        [0.0, 1.0 / s, -ty / s],
+        [0.0, 0.0, 1.0],
+    ];
+    Some(multiply(&to_inverse, &multiply(&h, &from_norm)))
+}
+
+// Focal lengths from the orthogonality of the board axes, as in OpenCV's
+// `initIntrinsicParams2D`. With the principal point known and no skew every
+// homography gives two linear equations in 1 / fx^2 and 1 / fy^2.
+fn initial_focal_lengths(homographies: &[Matrix], cx: f64, cy: f64) -> Option<(f64, f64)> {
+    let shift = [[1.0, 0.0, -cx], [0.0, 1.0, -cy], [0.0, 0.0, 1.0]];
+    let mut equations: Vec<[f64; 2]> = Vec::new();
+    let mut values = Vec::new();
+    for h in homographies {
+        let h = multiply(&shift, h);
+        let (a, b) = ([h[0][0], h[1][0], h[2][0]], [h[0][1], h[1][1], h[2][1]]);
+        // a^T B b = 0 and a^T B a = b^T B b with B = diag(1 / fx^2, 1 / fy^2, 1)
+        equations.push([a[0] * b[0], a[1] * b[1]]);
+        values.push(-a[2] * b[2]);
+        equations.push([a[0] * a[0] - b[0] * b[0], a[1] * a[1] - b[1] * b[1]]);
+        values.push(b[2] * b[2] - a[2] * a[2]);
+    }
+
+    let mut normal = [[0.0; 2]; 2];
+    let mut rhs = [0.0; 2];
+    for (row, value) in equations.iter().zip(&values) {
+        for j in 0..2 {
+            rhs[j] += row[j] * value;
+            for k in 0..2 {
+                normal[j][k] += row[j] * row[k];
+            }
+        }
+    }
+    if !solve_in_place(&mut normal, &mut rhs) || rhs[0] <= 0.0 || rhs[1] <= 0.0 {
+        return None;
+    }
+    Some((1.0 / rhs[0].sqrt(), 1.0 / rhs[1].sqrt()))
+}
+
+// `rpi calibrate`: finds the board in every image, calibrates and writes the result
+pub fn run(args: &CalibrateArgs) -> Result<(), CalibrationError> {
+    let (columns, rows) = args.board;
+    let board = Board {
+        columns,
+        rows,
+        square: args.square,
+    };
+    let images = ImageFiles::open(&args.images)
+        .map_err(|e| CalibrationError::Image(args.images.clone(), e))?;
+
+    let mut size = None;
+    let mut names = Vec::new();
+    let mut views = Vec::new();
+    for path in images.paths() {
+        let frame = Frame::load(path).map_err(|e| CalibrationError::Image(path.clone(), e))?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match size {
+            None => size = Some((frame.width, frame.height)),
+            Some((width, height)) if (width, height) != (frame.width, frame.height) => {
+                return Err(CalibrationError::Failed(format!(
+                    "{} is {}x{}, the other images are {}x{}",
+                    name, frame.width, frame.height, width, height
+                )));
+            }
+            Some(_) => {}
+        }
+        match checkerboard::find_corners(&frame, columns, rows) {
+            Some(corners) => {
+                names.push(name.to_string());
+                views.push(corners);
+            }
+            None => println!("{}: board not found, skipped", name),
+        }
+    }
+
+    let (width, height) = size.unwrap_or_default();
+    let report = calibrate(&views, &board, width, height)?;
+    for (name, error) in names.iter().zip(&report.view_errors) {
+        println!("{}: reprojection error {:.3} px", name, error);
+    }
+    println!(
+        "Calibrated {}x{} from {} images, reprojection error {:.3} px",
+        width,
+        height,
+        views.len(),
+        report.error
+    );
+
+    let text = format!(
+        "# Written by `rpi calibrate` from {} images at {}x{}\n\
+         # RMS reprojection error {:.3} px\n{}",
+        views.len(),
+        width,
+        height,
+        report.error,
+        toml::to_string(&report.calibration).expect("Calibration is always serializable")
+    );
+    std::fs::write(&args.output, text)
+        .map_err(|e| CalibrationError::Write(args.output.clone(), e))?;
+    println!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Calibration {
+        Calibration {
+            fx: 600.0,
+            fy: 590.0,
+            cx: 320.0,
+            cy: 240.0,
+            distortion: [0.1, -0.2, 0.001, -0.002, 0.05],
+        }
+    }
+
+    #[test]
+    fn parses_file() {
+        let calibration: Calibration = toml::from_str(
+            "fx = 600.0\nfy = 590.0\ncx = 320.0\ncy = 240.0\n\
+             distortion = [0.1, -0.2, 0.001, -0.002, 0.05]\n",
+        )
+        .unwrap();
+        assert_eq!(calibration, camera());
+        assert!(calibration.validate().is_ok());
+
+        let pinhole: Calibration = toml::from_str("fx = 1\nfy = 1\ncx = 0\ncy = 0\n").unwrap();
+        assert_eq!(pinhole.distortion, [0.0; 5]);
+        assert!(toml::from_str::<Calibration>("fx = 1\nfy = 1\ncx = 0\n").is_err());
+
+        let mut invalid = camera();
+        invalid.fy = 0.0;
+        assert!(matches!(
+            invalid.validate(),
+            Err(CalibrationError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn undistort_inverts_project() {
+        let camera = camera();
+        for point in [[0.0, 0.0, 1.0], [0.3, -0.2, 1.5], [-0.5, 0.35, 1.2]] {
+            let [x, y] = camera.undistort(camera.project(point));
+            assert!((x - point[0] / point[2]).abs() < 1e-9, "{:?}", point);
+            assert!((y - point[1] / point[2]).abs() < 1e-9, "{:?}", point);
+        }
+    }
+
+    const BOARD: Board = Board {
+        columns: 7,
+        rows: 5,
+        square: 0.03,
+    };
+
+    fn lens() -> Calibration {
+        Calibration {
+            fx: 300.0,
+            fy: 305.0,
+            cx: 162.0,
+            cy: 117.0,
+            distortion: [-0.12, 0.05, 0.0, 0.0, 0.0],
+        }
+    }
+
+    // Rotation vectors and board center positions in the camera frame
+    const VIEWS: [(Vector, Vector); 5] = [
+        ([0.35, 0.0, 0.05], [0.01, 0.0, 0.5]),
+        ([-0.3, 0.25, 0.1], [-0.02, 0.01, 0.55]),
+        ([0.05, 0.4, -0.1], [0.0, -0.02, 0.5]),
+        ([0.25, -0.3, 0.35], [0.02, 0.01, 0.6]),
+        ([-0.1, -0.35, 1.7], [0.0, 0.0, 0.55]),
+    ];
+
+    // Camera image of the board, with one white square around the dark ones.
+    // Every pixel averages `SAMPLES` x `SAMPLES` rays like a real sensor.
+    const SAMPLES: usize = 4;
+
+    fn render(camera: &Calibration, (rotation, center): (Vector, Vector)) -> Frame {
+        let rotation = rotation_matrix(rotation);
+        let middle = [
+            (BOARD.columns - 1) as f64 * BOARD.square / 2.0,
+            (BOARD.rows - 1) as f64 * BOARD.square / 2.0,
+            0.0,
+        ];
+        let translation = crate::geometry::subtract(center, apply(&rotation, middle));
+        let transpose: Matrix = std::array::from_fn(|i| std::array::from_fn(|j| rotation[j][i]));
+        let origin = apply(&transpose, translation);
+
+        let mut frame = Frame::new(320, 240, 128);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let mut sum = 0.0;
+                for sample in 0..SAMPLES * SAMPLES {
+                    let dx = ((sample % SAMPLES) as f64 + 0.5) / SAMPLES as f64 - 0.5;
+                    let dy = ((sample / SAMPLES) as f64 + 0.5) / SAMPLES as f64 - 0.5;
+                    let [u, v] = camera.undistort([x as f64 + dx, y as f64 + dy]);
+                    // Where the ray through the pixel meets the board plane
+                    let ray = apply(&transpose, [u, v, 1.0]);
+                    let distance = origin[2] / ray[2];
+                    let bx = ray[0] * distance - origin[0];
+                    let by = ray[1] * distance - origin[1];
+                    let (col, row) = (
+                        (bx / BOARD.square).floor() as i64 + 1,
+                        (by / BOARD.square).floor() as i64 + 1,
+                    );
+                    let (columns, rows) = (BOARD.columns as i64, BOARD.rows as i64);
+                    sum += if col < -1 || row < -1 || col > columns + 1 || row > rows + 1 {
+                        128.0
+                    } else if (0..=columns).contains(&col)
+                        && (0..=rows).contains(&row)
+                        && (col + row) % 2 == 0
+                    {
+                        20.0
+                    } else {
+                        235.0
+                    };
+                }
+                frame.set(x, y, (sum / (SAMPLES * SAMPLES) as f64).round() as u8);
+            }
+        }
+        frame
+    }
+
+    fn projected_corners(
+        camera: &Calibration,
+        (rotation, center): (Vector, Vector),
+    ) -> Vec<[f64; 2]> {
+        let matrix = rotation_matrix(rotation);
+        let middle = [
+            (BOARD.columns - 1) as f64 * BOARD.square / 2.0,
+            (BOARD.rows - 1) as f64 * BOARD.square / 2.0,
+            0.0,
+        ];
+        let translation = crate::geometry::subtract(center, apply(&matrix, middle));
+        BOARD
+            .points()
+            .iter()
+            .map(|[x, y]| camera.project(add(apply(&matrix, [*x, *y, 0.0]), translation)))
+            .collect()
+    }
+
+    #[test]
+    fn finds_checkerboard_corners() {
+        let camera = lens();
+        for view in VIEWS {
+            let frame = render(&camera, view);
+            let corners = checkerboard::find_corners(&frame, BOARD.columns, BOARD.rows)
+                .unwrap_or_else(|| panic!("no board in view {:?}", view));
+            // The board is symmetric, so the first corner may be any of the four
+            let expected = projected_corners(&camera, view);
+            for corner in &corners {
+                let closest = expected
+                    .iter()
+                    .map(|p| (p[0] - corner[0]).hypot(p[1] - corner[1]))
+                    .fold(f64::MAX, f64::min);
+                assert!(closest < 0.3, "corner {:?} is {} px off", corner, closest);
+            }
+        }
+        assert!(checkerboard::find_corners(&Frame::new(320, 240, 200), 7, 5).is_none());
+    }
+
+    #[test]
+    fn calibrates_from_rendered_views() {
+        let camera = lens();
+        let views: Vec<_> = VIEWS
+            .iter()
+            .map(|view| checkerboard::find_corners(&render(&camera, *view), 7, 5).unwrap())
+            .collect();
+
+        let report = calibrate(&views, &BOARD, 320, 240).unwrap();
+        let result = &report.calibration;
+        assert!(report.error < 0.2, "reprojection error {}", report.error);
+        assert!((result.fx - camera.fx).abs() < 3.0, "{:?}", result);
+        assert!((result.fy - camera.fy).abs() < 3.0, "{:?}", result);
+        assert!((result.cx - camera.cx).abs() < 2.0, "{:?}", result);
+        assert!((result.cy - camera.cy).abs() < 2.0, "{:?}", result);
+        assert!(
+            (result.distortion[0] - camera.distortion[0]).abs() < 0.03,
+            "{:?}",
+            result
+        );
+
+        let err = calibrate(&views[..2], &BOARD, 320, 240).err().unwrap();
+        assert!(matches!(err, CalibrationError::Failed(_)));
+    }
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// 8-bit grayscale image, row-major
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    #[cfg(test)]
+    pub fn new(width: usize, height: usize, value: u8) -> Frame {
+        Frame {
+            width,
+            height,
+            pixels: vec![value; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    #[cfg(test)]
+    pub fn set(&mut self, x: usize, y: usize, value: u8) {
+        self.pixels[y * self.width + x] = value;
+    }
+
+    // Bilinear interpolation, `None` outside the image
+    pub fn sample(&self, x: f32, y: f32) -> Option<f32> {
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (x0, y0) = (x as usize, y as usize);
+        if x0 + 1 >= self.width || y0 + 1 >= self.height {
+            return None;
+        }
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let top = self.get(x0, y0) as f32 * (1.0 - fx) + self.get(x0 + 1, y0) as f32 * fx;
+        let bottom =
+            self.get(x0, y0 + 1) as f32 * (1.0 - fx) + self.get(x0 + 1, y0 + 1) as f32 * fx;
+        Some(top * (1.0 - fy) + bottom * fy)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Frame> {
+        let image = image::open(path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .to_luma8();
+        Ok(Frame {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels: image.into_raw(),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        image::save_buffer(
+            path,
+            &self.pixels,
+            self.width as u32,
+            self.height as u32,
+            image::ColorType::L8,
+        )
+        .map_err(io::Error::other)
+    }
+}
+
+// Where the bridge gets camera images from
+pub trait FrameSource: Send {
+    // Returns `None` once the source has no more frames
+    fn next_frame(&mut self) -> io::Result<Option<Frame>>;
+}
+
+// Serves image files in name order, looping over them, instead of a camera
+pub struct ImageFiles {
+    paths: Vec<PathBuf>,
+    next: usize,
+}
+
+impl ImageFiles {
+    // `path` is either a single image or a directory of PNG images
+    pub fn open(path: &Path) -> io::Result<ImageFiles> {
+        let mut paths = Vec::new();
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) == Some("png") {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+        } else {
+            paths.push(path.to_owned());
+        }
+
+        if paths.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no PNG images in {}", path.display()),
+            ));
+        }
+        Ok(ImageFiles { paths, next: 0 })
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+}
+
+impl FrameSource for ImageFiles {
+    fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        let path = &self.paths[self.next];
+        self.next = (self.next + 1) % self.paths.len();
+        Frame::load(path).map(Some)
+    }
+}
@@ -0,0 +1,100 @@
+use protocol::{Command, LineKind, SerialData, Severity};
+
+// Lines the firmware prints in answer to a command, see `firmware/src/main.cpp`
+const ACKS: [(&str, Command); 3] = [
+    ("Armed...", Command::Arm),
+    ("Aborting...", Command::Abort),
+    ("Starting Drone...", Command::Reboot),
+];
+
+// Checked in order on the lowercased line, the first match wins
+const SEVERITIES: [(&str, Severity); 6] = [
+    // A retry is not a failure yet
+    ("retrying", Severity::Warning),
+    ("fail", Severity::Error),
+    ("error", Severity::Error),
+    ("!!!", Severity::Error),
+    ("disabling motors", Severity::Warning),
+    ("warn", Severity::Warning),
+];
+
+// Decides what a line read from the flight controller is
+pub fn classify(line: &str) -> LineKind {
+    let line = line.trim();
+    if line.starts_with('{') {
+        return match SerialData::decode(line) {
+            Ok(_) => LineKind::Telemetry,
+            Err(_) => LineKind::Unknown,
+        };
+    }
+    // Noise on the UART, e.g. while the flight controller boots
+    if line.is_empty()
+        || line
+            .chars()
+            .any(|c| c == char::REPLACEMENT_CHARACTER || (c.is_control() && c != '\t'))
+    {
+        return LineKind::Unknown;
+    }
+    if let Some((_, command)) = ACKS.iter().find(|(ack, _)| *ack == line) {
+        return LineKind::Ack {
+            command: command.encode().trim_end().to_string(),
+        };
+    }
+    let lower = line.to_lowercase();
+    let severity = SEVERITIES
+        .iter()
+        .find(|(word, _)| lower.contains(word))
+        .map_or(Severity::Info, |(_, severity)| *severity);
+    LineKind::Log { severity }
+}
+
+// Whether the flight controller reports being armed (true) or disarmed (false),
+// `None` for lines that say neither
+pub fn arming(line: &str) -> Option<bool> {
+    let LineKind::Ack { command } = classify(line) else {
+        return None;
+    };
+    match Command::decode(&command) {
+        Ok(Command::Arm) => Some(true),
+        Ok(Command::Abort | Command::Reboot) => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(severity: Severity) -> LineKind {
+        LineKind::Log { severity }
+    }
+
+    #[test]
+    fn firmware_lines() {
+        let telemetry = SerialData::default().encode();
+        assert_eq!(classify(&telemetry), LineKind::Telemetry);
+        assert_eq!(classify(r#"{"elapsed_time":1.0}"#), LineKind::Unknown);
+        assert_eq!(
+            classify("Armed...\r\n"),
+            LineKind::Ack {
+                command: "command->arm".to_string()
+            }
+        );
+        assert_eq!(
+            classify("Waiting for command to arm..."),
+            log(Severity::Info)
+        );
+        assert_eq!(
+            classify("Last enable motor check: disabling motors..."),
+            log(Severity::Warning)
+        );
+        assert_eq!(
+            classify("Set sampling mode fail, retrying...."),
+            log(Severity::Warning)
+        );
+        assert_eq!(classify("Failed to find LSM6DS chip"), log(Severity::Error));
+        assert_eq!(classify("Data bus error!!!"), log(Severity::Error));
+        assert_eq!(classify("\u{1}\u{fffd}x"), LineKind::Unknown);
+        assert_eq!(classify("\r\n"), LineKind::Unknown);
+    }
+}
@@ -0,0 +1,542 @@
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use protocol::{
+    BridgeEvent, Command, CommandAck, CommandStatus, ControlRequest, LineKind, LogReply,
+    LogRequest, Ping, Pong, ReplayRequest, SequencedCommand, Telemetry,
+};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{accept_hdr_async, WebSocketStream};
+
+use crate::auth::{Access, Handshake};
+use crate::bridge::Bridge;
+use crate::classify::classify;
+use crate::delivery;
+use crate::recorder;
+
+// Flight logs are downloaded in chunks of about this size, split on line boundaries
+const LOG_CHUNK_SIZE: usize = 256 * 1024;
+
+// A client connection, with or without TLS
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Stream for S {}
+
+type WsSender = SplitSink<WebSocketStream<Box<dyn Stream>>, Message>;
+
+pub async fn handle_connection(stream: TcpStream, bridge: Arc<Bridge>, tls: Option<TlsAcceptor>) {
+    let stream: Box<dyn Stream> = match tls {
+        Some(tls) => match tls.accept(stream).await {
+            Ok(stream) => Box::new(stream),
+            Err(e) => {
+                eprintln!("Failed TLS handshake: {}", e);
+                return;
+            }
+        },
+        None => Box::new(stream),
+    };
+    let mut access = Access::Denied;
+    let handshake = Handshake {
+        config: &bridge.config.auth,
+        access: &mut access,
+    };
+    let ws_stream = match accept_hdr_async(stream, handshake).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            match access {
+                Access::Denied => eprintln!("Rejected WebSocket client without a valid token"),
+                _ => eprintln!("Failed to accept WebSocket connection: {}", e),
+            }
+            return;
+        }
+    };
+
+    let client = bridge.next_client_id();
+    bridge.clients.fetch_add(1, Ordering::Relaxed);
+    match &access {
+        Access::Client(name) => println!("Client {} ({}) connected", client, name),
+        Access::Observer => println!("Client {} connected without a token, watching only", client),
+        Access::Open | Access::Shared | Access::Denied => println!("Client {} connected", client),
+    }
+
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    let mut lines = bridge.serial.lines.subscribe();
+    let mut events = bridge.events.subscribe();
+    let mut acks = bridge.acks.subscribe();
+
+    let last_failsafe = bridge.last_failsafe.lock().unwrap().clone();
+    let link_down = bridge.link_down.lock().unwrap().clone();
+    for event in [last_failsafe, link_down].into_iter().flatten() {
+        if let Err(e) = send_telemetry(&mut ws_sender, &bridge, client, None, Some(event)).await {
+            eprintln!("WebSocket send error: {}", e);
+            bridge.clients.fetch_sub(1, Ordering::Relaxed);
+            return;
+        }
+    }
+
+    let telemetry_interval = match bridge.config.telemetry.rate_hz {
+        rate if rate > 0.0 => Duration::from_secs_f32(1.0 / rate),
+        _ => Duration::ZERO,
+    };
+    let mut last_telemetry: Option<Instant> = None;
+
+    loop {
+        tokio::select! {
+            line = lines.recv() => {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("Client {} lagging, skipped {} lines", client, skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                // Text messages always go through, telemetry is limited to the configured rate
+                let kind = classify(&line);
+                if kind == LineKind::Telemetry {
+                    if last_telemetry.is_some_and(|at| at.elapsed() < telemetry_interval) {
+                        continue;
+                    }
+                    last_telemetry = Some(Instant::now());
+                }
+
+                let line = Some((line.trim(), kind));
+                if let Err(e) = send_telemetry(&mut ws_sender, &bridge, client, line, None).await {
+                    eprintln!("WebSocket send error: {}", e);
+                    break;
+                }
+            }
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                let closing = matches!(event, BridgeEvent::Shutdown { .. });
+                if let Err(e) = send_telemetry(&mut ws_sender, &bridge, client, None, Some(event)).await {
+                    eprintln!("WebSocket send error: {}", e);
+                    break;
+                }
+                if closing {
+                    let close = CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "bridge shutting down".into(),
+                    };
+                    if let Err(e) = ws_sender.send(Message::Close(Some(close))).await {
+                        eprintln!("WebSocket send error: {}", e);
+                    }
+                    break;
+                }
+            }
+            ack = acks.recv() => {
+                let ack = match ack {
+                    Ok((recipient, ack)) if recipient == client => ack,
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                if let Err(e) = send_ack(&mut ws_sender, &bridge, client, ack).await {
+                    eprintln!("WebSocket send error: {}", e);
+                    break;
+                }
+            }
+            msg = ws_receiver.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        // Answered first, the round trip should not include any other work
+                        if let Some(ping) = Ping::decode(&text) {
+                            let pong = Pong {
+                                seq: ping.seq,
+                                serial_lines: bridge.serial.stats.lines(),
+                                serial_errors: bridge.serial.stats.errors(),
+                            };
+                            let telemetry = Telemetry {
+                                pong: Some(pong),
+                                ..status(&bridge, client)
+                            };
+                            if let Err(e) = ws_sender.send(Message::Text(telemetry.encode())).await {
+                                eprintln!("WebSocket send error: {}", e);
+                                break;
+                            }
+                            continue;
+                        }
+
+                        if !access.authenticated() {
+                            eprintln!("Ignoring message from unauthenticated client {}: {}", client, text);
+                            if let Some(command) = SequencedCommand::decode(&text) {
+                                let ack = CommandAck {
+                                    seq: command.seq,
+                                    status: CommandStatus::Failed,
+                                    reason: Some("not authenticated".to_string()),
+                                };
+                                if let Err(e) = send_ack(&mut ws_sender, &bridge, client, ack).await {
+                                    eprintln!("WebSocket send error: {}", e);
+                                    break;
+                                }
+                            }
+                            continue;
+                        }
+
+                        if let Some(request) = ControlRequest::decode(&text) {
+                            handle_control_request(&bridge, client, request);
+                            if request == ControlRequest::Heartbeat {
+                                continue;
+                            }
+                            // Let the client know the outcome even if serial is silent
+                            if let Err(e) = send_telemetry(&mut ws_sender, &bridge, client, None, None).await {
+                                eprintln!("WebSocket send error: {}", e);
+                                break;
+                            }
+                            continue;
+                        }
+
+                        // Any client may read the flight logs
+                        if let Some(request) = LogRequest::decode(&text) {
+                            if let Err(e) = handle_log_request(&mut ws_sender, &bridge, client, request).await {
+                                eprintln!("WebSocket send error: {}", e);
+                                break;
+                            }
+                            continue;
+                        }
+
+                        if let Some(request) = ReplayRequest::decode(&text) {
+                            match &bridge.replay {
+                                Some(playback) => {
+                                    playback.send_modify(|state| state.apply(request));
+                                    println!("Client {} set replay to {:?}", client, *playback.borrow());
+                                }
+                                None => eprintln!("Ignoring {:?}, not replaying a log", request),
+                            }
+                            continue;
+                        }
+
+                        // Sequenced commands get delivery reports, the rest are fire and forget
+                        let (seq, text) = match SequencedCommand::decode(&text) {
+                            Some(command) => (Some(command.seq), command.line),
+                            None => (None, text),
+                        };
+
+                        if !bridge.control.is_pilot(client) {
+                            eprintln!("Ignoring command from observer {}: {}", client, text);
+                            if let Some(seq) = seq {
+                                let ack = CommandAck {
+                                    seq,
+                                    status: CommandStatus::Failed,
+                                    reason: Some("not the pilot".to_string()),
+                                };
+                                if let Err(e) = send_ack(&mut ws_sender, &bridge, client, ack).await {
+                                    eprintln!("WebSocket send error: {}", e);
+                                    break;
+                                }
+                            }
+                            continue;
+                        }
+                        bridge.watchdog.feed(Instant::now());
+                        if bridge.serial.commands.blocked(&text) {
+                            eprintln!("Ignoring command from {}, kill switch engaged: {}", client, text);
+                            if let Some(seq) = seq {
+                                let ack = CommandAck {
+                                    seq,
+                                    status: CommandStatus::Failed,
+                                    reason: Some("kill switch engaged".to_string()),
+                                };
+                                if let Err(e) = send_ack(&mut ws_sender, &bridge, client, ack).await {
+                                    eprintln!("WebSocket send error: {}", e);
+                                    break;
+                                }
+                            }
+                            continue;
+                        }
+                        if let Err(e) = Command::decode(&text) {
+                            eprintln!("Forwarding malformed command: {}", e);
+                        }
+                        if let Some(seq) = seq {
+                            let ack = bridge.deliveries.sent(client, seq, &text, Instant::now());
+                            if let Err(e) = send_ack(&mut ws_sender, &bridge, client, ack).await {
+                                eprintln!("WebSocket send error: {}", e);
+                                break;
+                            }
+                        }
+                        bridge.serial.commands.push(text);
+                    }
+                    Some(Ok(Message::Close(_))) | None => {
+                        println!("WebSocket connection closed");
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        eprintln!("WebSocket receive error: {}", e);
+                        break;
+                    }
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+
+    // A pilot that disappears keeps the watchdog armed so the failsafe can trigger
+    bridge.control.release(client);
+    delivery::cancel(&bridge, Some(client), "client disconnected");
+    bridge.clients.fetch_sub(1, Ordering::Relaxed);
+    println!("Client {} disconnected", client);
+}
+
+fn handle_control_request(bridge: &Bridge, client: u64, request: ControlRequest) {
+    match request {
+        ControlRequest::Take => {
+            if bridge.control.take(client) {
+                bridge.watchdog.feed(Instant::now());
+                println!("Client {} has control", client);
+            } else {
+                println!(
+                    "Client {} denied control, another client is the pilot",
+                    client
+                );
+            }
+        }
+        ControlRequest::Release => {
+            if bridge.control.is_pilot(client) {
+                bridge.control.release(client);
+                bridge.watchdog.disarm();
+                delivery::cancel(bridge, Some(client), "control released");
+                println!("Client {} released control", client);
+            }
+        }
+        ControlRequest::Heartbeat => {
+            if bridge.control.is_pilot(client) {
+                bridge.watchdog.feed(Instant::now());
+            }
+        }
+    }
+}
+
+async fn handle_log_request(
+    ws_sender: &mut WsSender,
+    bridge: &Bridge,
+    client: u64,
+    request: LogRequest,
+) -> Result<(), tungstenite::Error> {
+    let dir = &bridge.config.log.dir;
+    let reply = match request {
+        LogRequest::List => match recorder::list_files(dir) {
+            Ok(files) => LogReply::List { files },
+            Err(e) => LogReply::Error {
+                message: format!("failed to list logs: {}", e),
+            },
+        },
+        LogRequest::Get(name) => match recorder::resolve(dir, &name) {
+            Some(path) => match File::open(&path).await {
+                Ok(file) => return send_log_file(ws_sender, bridge, client, &name, file).await,
+                Err(e) => LogReply::Error {
+                    message: format!("failed to open {}: {}", name, e),
+                },
+            },
+            None => LogReply::Error {
+                message: format!("no log named {:?}", name),
+            },
+        },
+    };
+    send_log_reply(ws_sender, bridge, client, reply).await
+}
+
+async fn send_log_file(
+    ws_sender: &mut WsSender,
+    bridge: &Bridge,
+    client: u64,
+    name: &str,
+    file: File,
+) -> Result<(), tungstenite::Error> {
+    let mut reader = BufReader::new(file);
+    let mut offset = 0;
+    let mut chunk = String::new();
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let read = match reader.read_line(&mut line).await {
+            Ok(read) => read,
+            Err(e) => {
+                let message = format!("failed to read {}: {}", name, e);
+                return send_log_reply(ws_sender, bridge, client, LogReply::Error { message })
+                    .await;
+            }
+        };
+
+        if read == 0 || (!chunk.is_empty() && chunk.len() + line.len() > LOG_CHUNK_SIZE) {
+            let data = std::mem::take(&mut chunk);
+            let length = data.len() as u64;
+            let reply = LogReply::Chunk {
+                name: name.to_string(),
+                offset,
+                data,
+                last: read == 0,
+            };
+            send_log_reply(ws_sender, bridge, client, reply).await?;
+            offset += length;
+        }
+        if read == 0 {
+            return Ok(());
+        }
+        chunk.push_str(&line);
+    }
+}
+
+async fn send_log_reply(
+    ws_sender: &mut WsSender,
+    bridge: &Bridge,
+    client: u64,
+    reply: LogReply,
+) -> Result<(), tungstenite::Error> {
+    let telemetry = Telemetry {
+        logs: Some(reply),
+        ..status(bridge, client)
+    };
+    ws_sender.send(Message::Text(telemetry.encode())).await
+}
+
+async fn send_ack(
+    ws_sender: &mut WsSender,
+    bridge: &Bridge,
+    client: u64,
+    ack: CommandAck,
+) -> Result<(), tungstenite::Error> {
+    let telemetry = Telemetry {
+        ack: Some(ack),
+        ..status(bridge, client)
+    };
+    ws_sender.send(Message::Text(telemetry.encode())).await
+}
+
+async fn send_telemetry(
+    ws_sender: &mut WsSender,
+    bridge: &Bridge,
+    client: u64,
+    line: Option<(&str, LineKind)>,
+    event: Option<BridgeEvent>,
+) -> Result<(), tungstenite::Error> {
+    let (serial_data, line_kind) = match line {
+        Some((line, kind)) => (line.to_string(), Some(kind)),
+        None => (String::new(), None),
+    };
+    let telemetry = Telemetry {
+        serial_data,
+        line_kind,
+        event,
+        ..status(bridge, client)
+    };
+    ws_sender.send(Message::Text(telemetry.encode())).await
+}
+
+// Status-only message for `client`, without serial data
+fn status(bridge: &Bridge, client: u64) -> Telemetry {
+    let markers = bridge.markers.lock().unwrap().clone();
+    Telemetry {
+        aruco_ids: markers.iter().map(|marker| marker.id).collect(),
+        markers,
+        control: Some(bridge.control.status(client)),
+        command_queue: Some(bridge.serial.commands.stats()),
+        companion: bridge.companion.lock().unwrap().clone(),
+        gps: bridge.gps.lock().unwrap().clone(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::emulator;
+    use crate::recorder::Recorder;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::connect_async;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::http::StatusCode;
+
+    // Failure reason of the delivery report for `seq`
+    async fn failed<S>(ws: &mut WebSocketStream<S>, seq: u32) -> Option<String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let command = SequencedCommand {
+            seq,
+            line: "command->reboot".to_string(),
+        };
+        ws.send(Message::Text(command.encode())).await.unwrap();
+        let ack = tokio::time::timeout(Duration::from_secs(3), async {
+            loop {
+                let Message::Text(text) = ws.next().await.unwrap().unwrap() else {
+                    continue;
+                };
+                if let Some(ack) = Telemetry::decode(&text).unwrap().ack {
+                    return ack;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!((ack.seq, ack.status), (seq, CommandStatus::Failed));
+        ack.reason
+    }
+
+    // Serves a bridge on the emulator, returns its URL
+    async fn serve(mut config: Config) -> String {
+        config.emulator.boot_ms = 0;
+        let recorder = Recorder::disabled();
+        let serial = emulator::open(&config.emulator, recorder.clone());
+        let bridge = Arc::new(Bridge::new(config, serial, recorder, None));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(handle_connection(stream, Arc::clone(&bridge), None));
+            }
+        });
+        format!("ws://{}", address)
+    }
+
+    #[tokio::test]
+    async fn commands_need_a_token() {
+        let mut config = Config::default();
+        config.auth.token = Some("secret".to_string());
+        let url = serve(config.clone()).await;
+
+        let Err(tungstenite::Error::Http(response)) = connect_async(url.as_str()).await else {
+            panic!("expected the handshake to be refused");
+        };
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let mut request = url.as_str().into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Authorization", "Bearer secret".parse().unwrap());
+        let (mut pilot, _) = connect_async(request).await.unwrap();
+        // Past authentication, held back for not being the pilot
+        assert_eq!(
+            failed(&mut pilot, 1).await.as_deref(),
+            Some("not the pilot")
+        );
+
+        // Observers may connect and watch, nothing they send is acted on
+        config.auth.allow_observers = true;
+        let url = serve(config).await;
+        let (mut observer, _) = connect_async(url.as_str()).await.unwrap();
+        observer
+            .send(Message::Text(ControlRequest::Take.encode()))
+            .await
+            .unwrap();
+        assert_eq!(
+            failed(&mut observer, 2).await.as_deref(),
+            Some("not authenticated")
+        );
+    }
+}
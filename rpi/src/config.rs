@@ -0,0 +1,957 @@
+use clap::{Args, Parser, Subcommand};
+use protocol::ReplayRequest;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_CONFIG_PATH: &str = "rpi.toml";
+// Rate limits, telemetry also takes 0 to send every line
+const MIN_RATE_HZ: f32 = 0.01;
+const MAX_RATE_HZ: f32 = 1000.0;
+
+#[derive(Parser, Debug, Default)]
+#[command(about = "Bridge between the flight controller UART and the ground station")]
+pub struct Cli {
+    /// TOML config file, `rpi.toml` is used if it exists
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Serial device connected to the flight controller
+    #[arg(long)]
+    pub serial: Option<String>,
+
+    /// Serial baud rate
+    #[arg(long)]
+    pub baud: Option<u32>,
+
+    /// Address the WebSocket server binds to
+    #[arg(long)]
+    pub bind: Option<String>,
+
+    /// WebSocket server port
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Port of the HTTP status and metrics endpoint
+    #[arg(long)]
+    pub metrics_port: Option<u16>,
+
+    /// Directory for flight logs
+    #[arg(long)]
+    pub log_dir: Option<PathBuf>,
+
+    /// Maximum telemetry rate sent to clients in Hz, 0 forwards every line
+    #[arg(long)]
+    pub telemetry_rate: Option<f32>,
+
+    /// Replay a recorded flight log instead of opening the serial port
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+
+    /// Replay speed relative to the original timing
+    #[arg(long)]
+    pub replay_speed: Option<f32>,
+
+    /// Start the replay again when the end of the log is reached
+    #[arg(long)]
+    pub replay_loop: bool,
+
+    /// Use the built-in flight controller emulator instead of the serial port
+    #[arg(long)]
+    pub emulator: bool,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// Compute the camera calibration used for marker poses from checkerboard images
+    Calibrate(CalibrateArgs),
+    /// Convert flight logs to MCAP, the parts of one session in order
+    Export(ExportArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// Flight log files recorded by the bridge
+    #[arg(required = true)]
+    pub logs: Vec<PathBuf>,
+
+    /// MCAP file to write, the first log with an `.mcap` extension by default
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct CalibrateArgs {
+    /// PNG image or directory of images of the board, in different positions and tilts
+    pub images: PathBuf,
+
+    /// Inner corners of the board as COLUMNSxROWS, a board of 10x7 squares has 9x6
+    #[arg(long, default_value = "9x6", value_parser = parse_board)]
+    pub board: (usize, usize),
+
+    /// Side of one board square in metres
+    #[arg(long, default_value_t = 0.025)]
+    pub square: f64,
+
+    /// Calibration file to write
+    #[arg(short, long, default_value = "camera.toml")]
+    pub output: PathBuf,
+}
+
+fn bounded_rate(rate_hz: f32) -> bool {
+    (MIN_RATE_HZ..=MAX_RATE_HZ).contains(&rate_hz)
+}
+
+fn valid_rate(rate_hz: f32) -> bool {
+    rate_hz == 0.0 || bounded_rate(rate_hz)
+}
+
+fn parse_board(text: &str) -> Result<(usize, usize), String> {
+    let (columns, rows) = text
+        .split_once('x')
+        .ok_or_else(|| format!("expected COLUMNSxROWS, got {}", text))?;
+    let columns: usize = columns
+        .parse()
+        .map_err(|_| format!("bad column count {}", columns))?;
+    let rows: usize = rows
+        .parse()
+        .map_err(|_| format!("bad row count {}", rows))?;
+    if columns < 2 || rows < 2 {
+        return Err("a board needs at least 2x2 inner corners".into());
+    }
+    Ok((columns, rows))
+}
+
+// How the firmware delimits telemetry on the UART, see `protocol::Frame`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    // One JSON `SerialData` or text message per line
+    Lines,
+    // COBS frames with a CRC, firmware built with `TRANSMITTER_BINARY_FRAMES`
+    Cobs,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub serial: SerialConfig,
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub metrics: MetricsConfig,
+    pub mavlink: MavlinkConfig,
+    pub foxglove: FoxgloveConfig,
+    pub telemetry: TelemetryConfig,
+    pub log: LogConfig,
+    pub failsafe: FailsafeConfig,
+    pub shutdown: ShutdownConfig,
+    pub replay: ReplayConfig,
+    pub emulator: EmulatorConfig,
+    pub aruco: ArucoConfig,
+    pub health: HealthConfig,
+    pub gpio: GpioConfig,
+    pub gps: GpsConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SerialConfig {
+    pub path: String,
+    pub baud: u32,
+    pub framing: Framing,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub port: u16,
+    // PEM certificate chain and private key, clients connect with `wss://` when set
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+}
+
+// Keys WebSocket clients send as `Authorization: Bearer <token>` on the
+// handshake, anyone can connect while none is set
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // Shared by every client
+    pub token: Option<String>,
+    // Per-client keys by client name, shown in the log and revoked one at a time
+    pub clients: BTreeMap<String, String>,
+    // Clients without a valid token may connect and watch, but not send anything
+    pub allow_observers: bool,
+}
+
+impl AuthConfig {
+    pub fn enabled(&self) -> bool {
+        self.token.is_some() || !self.clients.is_empty()
+    }
+}
+
+// HTTP status and Prometheus metrics, served on `server.bind`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+// MAVLink v2 over UDP for standard ground stations, served on `server.bind`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MavlinkConfig {
+    pub enabled: bool,
+    pub port: u16,
+    // Where telemetry is sent, the last ground station heard from if unset.
+    // When set, packets from anywhere else are ignored.
+    pub peer: Option<SocketAddr>,
+    // Let the ground station at `peer` take control and send commands
+    pub control: bool,
+    pub system_id: u8,
+    // Telemetry messages per second, 0 sends every telemetry line
+    pub rate_hz: f32,
+}
+
+// Foxglove WebSocket protocol server for Foxglove Studio, served on `server.bind`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FoxgloveConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    pub rate_hz: f32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub dir: PathBuf,
+    // Record every serial line and command to `dir`
+    pub enabled: bool,
+    // A new file is started once the current one reaches this size
+    pub max_file_mb: u64,
+    // The oldest files are deleted to stay below this size
+    pub max_total_mb: u64,
+    // Also write every session to `<session>.mcap`
+    pub mcap: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FailsafeConfig {
+    pub enabled: bool,
+    // Time without a heartbeat from the pilot before the safe sequence is sent
+    pub timeout_ms: u64,
+    // Sent after throttle is set low
+    pub action: FailsafeAction,
+}
+
+// Safe sequence written when the bridge is stopped with SIGINT or SIGTERM
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    // Sent after throttle is set low
+    pub action: FailsafeAction,
+    // The bridge exits after this even if the sequence was not written
+    pub timeout_ms: u64,
+}
+
+// Serves a recorded session instead of the serial port when `file` is set
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayConfig {
+    pub file: Option<PathBuf>,
+    pub speed: f32,
+    // Start again from the beginning once the log ends
+    pub repeat: bool,
+}
+
+// Simulated flight controller used instead of the serial port when enabled
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmulatorConfig {
+    pub enabled: bool,
+    // Delay before "Waiting for command to arm...", 5000 ms on the real firmware
+    pub boot_ms: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArucoConfig {
+    pub enabled: bool,
+    // PNG image or directory of images used as camera frames
+    pub images: PathBuf,
+    pub rate_hz: f32,
+    // Dictionary written by OpenCV's `writeDictionary`, `DICT_ARUCO_ORIGINAL` if unset
+    pub dictionary: Option<PathBuf>,
+    // Camera calibration file, marker poses are only estimated when it is set
+    pub calibration: Option<PathBuf>,
+    // Side of the black marker square in metres
+    pub marker_size_m: f32,
+}
+
+// Health of the Pi itself, sent to clients as `companion`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    pub enabled: bool,
+    pub rate_hz: f32,
+    // Directory `/proc` and `/sys` are read under, to try fixtures off the Pi
+    pub root: PathBuf,
+}
+
+// Status LED and kill switch on the Pi's GPIO header, pins are BCM numbers
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpioConfig {
+    pub enabled: bool,
+    pub led_pin: u8,
+    pub kill_switch_pin: u8,
+    // Switch wired to ground with the internal pull-up, otherwise to 3.3 V with the pull-down
+    pub kill_switch_active_low: bool,
+}
+
+// u-blox receiver on a second UART, its fix is sent to clients as `gps`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpsConfig {
+    pub enabled: bool,
+    pub path: String,
+    pub baud: u32,
+    // Send the UBX configuration at startup: NAV-PVT output at `rate_hz`, airborne model
+    pub configure: bool,
+    pub rate_hz: f32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailsafeAction {
+    Abort,
+    Reboot,
+    // Only set throttle low
+    None,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            path: "/dev/ttyS0".to_string(),
+            baud: 1_000_000,
+            framing: Framing::Lines,
+        }
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 8765,
+            cert: None,
+            key: None,
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 9100,
+        }
+    }
+}
+
+impl Default for MavlinkConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 14555,
+            peer: None,
+            control: false,
+            system_id: 1,
+            rate_hz: 10.0,
+        }
+    }
+}
+
+impl Default for FoxgloveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8766,
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("logs"),
+            enabled: true,
+            max_file_mb: 50,
+            max_total_mb: 1000,
+            mcap: false,
+        }
+    }
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            action: FailsafeAction::Abort,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            speed: 1.0,
+            repeat: false,
+        }
+    }
+}
+
+impl Default for EmulatorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            boot_ms: 5000,
+        }
+    }
+}
+
+impl Default for ArucoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            images: PathBuf::from("images"),
+            rate_hz: 5.0,
+            dictionary: None,
+            calibration: None,
+            marker_size_m: 0.1,
+        }
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rate_hz: 0.5,
+            root: PathBuf::from("/"),
+        }
+    }
+}
+
+impl Default for GpioConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            led_pin: 17,
+            kill_switch_pin: 27,
+            kill_switch_active_low: true,
+        }
+    }
+}
+
+impl Default for GpsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "/dev/ttyAMA1".to_string(),
+            // The M9N's UART1 default
+            baud: 38_400,
+            configure: false,
+            rate_hz: 5.0,
+        }
+    }
+}
+
+impl Default for FailsafeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_ms: 1000,
+            action: FailsafeAction::Abort,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => {
+                write!(f, "failed to read config {}: {}", path.display(), e)
+            }
+            ConfigError::Parse(path, e) => {
+                write!(f, "failed to parse config {}: {}", path.display(), e)
+            }
+            ConfigError::Invalid(message) => write!(f, "invalid config: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    // Loads the config file (if any), applies CLI overrides and validates the result
+    pub fn load(cli: &Cli) -> Result<Config, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Config::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_owned(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_owned(), e))
+    }
+
+    fn apply(&mut self, cli: &Cli) {
+        if let Some(path) = &cli.serial {
+            self.serial.path = path.clone();
+        }
+        if let Some(baud) = cli.baud {
+            self.serial.baud = baud;
+        }
+        if let Some(bind) = &cli.bind {
+            self.server.bind = bind.clone();
+        }
+        if let Some(port) = cli.port {
+            self.server.port = port;
+        }
+        if let Some(port) = cli.metrics_port {
+            self.metrics.port = port;
+        }
+        if let Some(dir) = &cli.log_dir {
+            self.log.dir = dir.clone();
+        }
+        if let Some(rate) = cli.telemetry_rate {
+            self.telemetry.rate_hz = rate;
+        }
+        if let Some(file) = &cli.replay {
+            self.replay.file = Some(file.clone());
+        }
+        if let Some(speed) = cli.replay_speed {
+            self.replay.speed = speed;
+        }
+        if cli.replay_loop {
+            self.replay.repeat = true;
+        }
+        if cli.emulator {
+            self.emulator.enabled = true;
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.serial.path.trim().is_empty() {
+            return Err(ConfigError::Invalid("serial.path must not be empty".into()));
+        }
+        if self.serial.baud == 0 {
+            return Err(ConfigError::Invalid("serial.baud must be positive".into()));
+        }
+        if self.server.bind.parse::<IpAddr>().is_err() {
+            return Err(ConfigError::Invalid(format!(
+                "server.bind {:?} is not an IP address",
+                self.server.bind
+            )));
+        }
+        if self.server.port == 0 {
+            return Err(ConfigError::Invalid("server.port must not be 0".into()));
+        }
+        if self.server.cert.is_some() != self.server.key.is_some() {
+            return Err(ConfigError::Invalid(
+                "server.cert and server.key must be set together".into(),
+            ));
+        }
+        let keys: Vec<&String> = self
+            .auth
+            .token
+            .iter()
+            .chain(self.auth.clients.values())
+            .collect();
+        if keys.iter().any(|key| key.is_empty()) {
+            return Err(ConfigError::Invalid("auth tokens must not be empty".into()));
+        }
+        if keys.iter().collect::<BTreeSet<_>>().len() != keys.len() {
+            return Err(ConfigError::Invalid(
+                "auth tokens must be different for every client".into(),
+            ));
+        }
+        if self.metrics.enabled && (self.metrics.port == 0 || self.metrics.port == self.server.port)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "metrics.port must not be 0 or the server port, got {}",
+                self.metrics.port
+            )));
+        }
+        if self.foxglove.enabled {
+            let taken = [self.server.port, self.metrics.port];
+            if self.foxglove.port == 0 || taken.contains(&self.foxglove.port) {
+                return Err(ConfigError::Invalid(format!(
+                    "foxglove.port must not be 0, the server or the metrics port, got {}",
+                    self.foxglove.port
+                )));
+            }
+            // Foxglove Studio cannot send a token, anyone could watch the flight
+            if self.auth.enabled() {
+                return Err(ConfigError::Invalid(
+                    "foxglove.enabled must be false when auth is configured, it takes no tokens"
+                        .into(),
+                ));
+            }
+        }
+        if self.mavlink.enabled {
+            let taken = [self.server.port, self.metrics.port];
+            if self.mavlink.port == 0 || taken.contains(&self.mavlink.port) {
+                return Err(ConfigError::Invalid(format!(
+                    "mavlink.port must not be 0, the server or the metrics port, got {}",
+                    self.mavlink.port
+                )));
+            }
+            // 0 addresses every system
+            if self.mavlink.system_id == 0 {
+                return Err(ConfigError::Invalid(
+                    "mavlink.system_id must not be 0".into(),
+                ));
+            }
+            if self.auth.enabled() && self.mavlink.peer.is_none() {
+                return Err(ConfigError::Invalid(
+                    "mavlink.peer must be set when auth is configured, the gateway takes no tokens"
+                        .into(),
+                ));
+            }
+            if self.mavlink.control && self.mavlink.peer.is_none() {
+                return Err(ConfigError::Invalid(
+                    "mavlink.control needs mavlink.peer, commands are only taken from it".into(),
+                ));
+            }
+            if !valid_rate(self.mavlink.rate_hz) {
+                return Err(ConfigError::Invalid(format!(
+                    "mavlink.rate_hz must be 0 or between {} and {}, got {}",
+                    MIN_RATE_HZ, MAX_RATE_HZ, self.mavlink.rate_hz
+                )));
+            }
+        }
+        if !valid_rate(self.telemetry.rate_hz) {
+            return Err(ConfigError::Invalid(format!(
+                "telemetry.rate_hz must be 0 or between {} and {}, got {}",
+                MIN_RATE_HZ, MAX_RATE_HZ, self.telemetry.rate_hz
+            )));
+        }
+        if self.failsafe.timeout_ms < 100 {
+            return Err(ConfigError::Invalid(format!(
+                "failsafe.timeout_ms must be at least 100, got {}",
+                self.failsafe.timeout_ms
+            )));
+        }
+        if !(100..=30_000).contains(&self.shutdown.timeout_ms) {
+            return Err(ConfigError::Invalid(format!(
+                "shutdown.timeout_ms must be between 100 and 30000, got {}",
+                self.shutdown.timeout_ms
+            )));
+        }
+        if self.log.dir.as_os_str().is_empty() {
+            return Err(ConfigError::Invalid("log.dir must not be empty".into()));
+        }
+        if self.log.max_file_mb == 0 || self.log.max_file_mb > self.log.max_total_mb {
+            return Err(ConfigError::Invalid(format!(
+                "log.max_file_mb must be between 1 and log.max_total_mb ({}), got {}",
+                self.log.max_total_mb, self.log.max_file_mb
+            )));
+        }
+        if self.replay.file.is_some() && self.emulator.enabled {
+            return Err(ConfigError::Invalid(
+                "replay.file and emulator.enabled cannot be used together".into(),
+            ));
+        }
+        if !(ReplayRequest::MIN_SPEED..=ReplayRequest::MAX_SPEED).contains(&self.replay.speed) {
+            return Err(ConfigError::Invalid(format!(
+                "replay.speed must be between {} and {}, got {}",
+                ReplayRequest::MIN_SPEED,
+                ReplayRequest::MAX_SPEED,
+                self.replay.speed
+            )));
+        }
+        if self.aruco.enabled && !bounded_rate(self.aruco.rate_hz) {
+            return Err(ConfigError::Invalid(format!(
+                "aruco.rate_hz must be between {} and {}, got {}",
+                MIN_RATE_HZ, MAX_RATE_HZ, self.aruco.rate_hz
+            )));
+        }
+        if self.health.enabled && !bounded_rate(self.health.rate_hz) {
+            return Err(ConfigError::Invalid(format!(
+                "health.rate_hz must be between {} and {}, got {}",
+                MIN_RATE_HZ, MAX_RATE_HZ, self.health.rate_hz
+            )));
+        }
+        if self.gpio.enabled {
+            let pins = [self.gpio.led_pin, self.gpio.kill_switch_pin];
+            if pins.iter().any(|pin| *pin > 27) || pins[0] == pins[1] {
+                return Err(ConfigError::Invalid(format!(
+                    "gpio.led_pin and gpio.kill_switch_pin must be different pins from 0 to 27, got {} and {}",
+                    pins[0], pins[1]
+                )));
+            }
+        }
+        if self.gps.enabled && self.gps.path == self.serial.path {
+            return Err(ConfigError::Invalid(format!(
+                "gps.path must not be the flight controller's serial port {}",
+                self.serial.path
+            )));
+        }
+        if self.gps.enabled && self.gps.baud == 0 {
+            return Err(ConfigError::Invalid("gps.baud must not be 0".into()));
+        }
+        // The M9N measures at up to 25 Hz
+        if self.gps.enabled
+            && self.gps.configure
+            && !(self.gps.rate_hz > 0.0 && self.gps.rate_hz <= 25.0)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "gps.rate_hz must be above 0 and at most 25, got {}",
+                self.gps.rate_hz
+            )));
+        }
+        if self.aruco.enabled
+            && (!self.aruco.marker_size_m.is_finite() || self.aruco.marker_size_m <= 0.0)
+        {
+            return Err(ConfigError::Invalid(format!(
+                "aruco.marker_size_m must be a positive number, got {}",
+                self.aruco.marker_size_m
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.server.bind, self.server.port)
+    }
+
+    pub fn metrics_address(&self) -> String {
+        format!("{}:{}", self.server.bind, self.metrics.port)
+    }
+
+    pub fn foxglove_address(&self) -> String {
+        format!("{}:{}", self.server.bind, self.foxglove.port)
+    }
+
+    pub fn mavlink_address(&self) -> String {
+        format!("{}:{}", self.server.bind, self.mavlink.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_partial_file_with_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [serial]
+            path = "/dev/ttyUSB0"
+            framing = "cobs"
+
+            [failsafe]
+            action = "none"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.serial.path, "/dev/ttyUSB0");
+        assert_eq!(config.serial.framing, Framing::Cobs);
+        assert_eq!(config.serial.baud, 1_000_000);
+        assert_eq!(config.bind_address(), "0.0.0.0:8765");
+        assert_eq!(config.failsafe.action, FailsafeAction::None);
+        assert_eq!(config.failsafe.timeout_ms, 1000);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(toml::from_str::<Config>("[serial]\nbaudrate = 9600\n").is_err());
+    }
+
+    #[test]
+    fn cli_overrides_file() {
+        let mut config = Config::default();
+        config.apply(&Cli {
+            serial: Some("/dev/ttyUSB1".to_string()),
+            port: Some(9000),
+            metrics_port: Some(9200),
+            telemetry_rate: Some(20.0),
+            replay: Some(PathBuf::from("logs/session.jsonl")),
+            replay_loop: true,
+            ..Default::default()
+        });
+
+        assert_eq!(config.serial.path, "/dev/ttyUSB1");
+        assert_eq!(config.server.port, 9000);
+        assert_eq!(config.metrics.port, 9200);
+        assert_eq!(config.telemetry.rate_hz, 20.0);
+        assert_eq!(config.serial.baud, 1_000_000);
+        assert_eq!(
+            config.replay.file,
+            Some(PathBuf::from("logs/session.jsonl"))
+        );
+        assert!(config.replay.repeat);
+        assert_eq!(config.replay.speed, 1.0);
+    }
+
+    #[test]
+    fn parses_calibrate_command() {
+        let cli = Cli::try_parse_from(["rpi", "calibrate", "shots", "--board", "7x5"]).unwrap();
+        let Some(CliCommand::Calibrate(args)) = cli.command else {
+            panic!("expected calibrate, got {:?}", cli.command);
+        };
+        assert_eq!(args.images, PathBuf::from("shots"));
+        assert_eq!(args.board, (7, 5));
+        assert_eq!(args.square, 0.025);
+        assert_eq!(args.output, PathBuf::from("camera.toml"));
+
+        assert!(Cli::try_parse_from(["rpi", "calibrate", "shots", "--board", "7"]).is_err());
+        assert!(Cli::try_parse_from(["rpi", "calibrate", "shots", "--board", "1x5"]).is_err());
+        assert!(Cli::try_parse_from(["rpi"]).unwrap().command.is_none());
+    }
+
+    #[test]
+    fn validation_errors() {
+        let mut config = Config::default();
+        config.server.bind = "pi.local".to_string();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.telemetry.rate_hz = -1.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.telemetry.rate_hz = 1e-30;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.telemetry.rate_hz = 0.0;
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.failsafe.timeout_ms = 10;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.metrics.port = config.server.port;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.metrics.enabled = false;
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.shutdown.timeout_ms = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.serial.baud = 0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.log.max_file_mb = 2000;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.mavlink.enabled = true;
+        config.mavlink.control = true;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.mavlink.peer = Some("192.168.1.10:14550".parse().unwrap());
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.mavlink.enabled = true;
+        config.auth.token = Some("secret".to_string());
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.mavlink.peer = Some("192.168.1.10:14550".parse().unwrap());
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.foxglove.enabled = true;
+        assert!(config.validate().is_ok());
+        config.auth.token = Some("secret".to_string());
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.replay.speed = 0.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.replay.speed = 1e-30;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.replay.file = Some(PathBuf::from("session.jsonl"));
+        config.emulator.enabled = true;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.aruco.enabled = true;
+        config.aruco.marker_size_m = 0.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.aruco.enabled = true;
+        config.aruco.rate_hz = 1e-40;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.aruco.rate_hz = f32::INFINITY;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.aruco.rate_hz = 5.0;
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.health.rate_hz = 1e-40;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.health.rate_hz = 2000.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.health.rate_hz = 0.5;
+        assert!(config.validate().is_ok());
+
+        let mut config = Config::default();
+        config.gps.enabled = true;
+        config.gps.configure = true;
+        config.gps.rate_hz = 50.0;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+        config.gps.rate_hz = 10.0;
+        config.gps.path = config.serial.path.clone();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.server.cert = Some(PathBuf::from("bridge.pem"));
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+
+        let mut config = Config::default();
+        config.auth.token = Some("secret".to_string());
+        config
+            .auth
+            .clients
+            .insert("laptop".to_string(), "secret".to_string());
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+}
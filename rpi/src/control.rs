@@ -0,0 +1,77 @@
+use protocol::ControlStatus;
+use std::sync::Mutex;
+
+// Tracks which client, if any, holds command authority over the drone
+#[derive(Default)]
+pub struct Control {
+    pilot: Mutex<Option<u64>>,
+}
+
+impl Control {
+    // Grants control if nobody holds it, returns whether `client` is now the pilot
+    pub fn take(&self, client: u64) -> bool {
+        let mut pilot = self.pilot.lock().unwrap();
+        match *pilot {
+            Some(id) => id == client,
+            None => {
+                *pilot = Some(client);
+                true
+            }
+        }
+    }
+
+    // Gives up control, does nothing if `client` is not the pilot
+    pub fn release(&self, client: u64) {
+        let mut pilot = self.pilot.lock().unwrap();
+        if *pilot == Some(client) {
+            *pilot = None;
+        }
+    }
+
+    pub fn pilot(&self) -> Option<u64> {
+        *self.pilot.lock().unwrap()
+    }
+
+    pub fn is_pilot(&self, client: u64) -> bool {
+        *self.pilot.lock().unwrap() == Some(client)
+    }
+
+    pub fn status(&self, client: u64) -> ControlStatus {
+        match *self.pilot.lock().unwrap() {
+            None => ControlStatus::Available,
+            Some(id) if id == client => ControlStatus::Pilot,
+            Some(_) => ControlStatus::Observer,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_pilot() {
+        let control = Control::default();
+        assert_eq!(control.status(1), ControlStatus::Available);
+
+        assert!(control.take(1));
+        assert!(control.take(1));
+        assert!(!control.take(2));
+        assert_eq!(control.status(1), ControlStatus::Pilot);
+        assert_eq!(control.status(2), ControlStatus::Observer);
+        assert!(!control.is_pilot(2));
+    }
+
+    #[test]
+    fn release_only_by_pilot() {
+        let control = Control::default();
+        assert!(control.take(1));
+
+        control.release(2);
+        assert!(control.is_pilot(1));
+
+        control.release(1);
+        assert_eq!(control.status(2), ControlStatus::Available);
+        assert!(control.take(2));
+    }
+}
@@ -0,0 +1,375 @@
+use protocol::{Command, CommandAck, CommandStatus, SerialData};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::bridge::Bridge;
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+// Time for a command to be written and answered, per attempt
+const ACK_TIMEOUT: Duration = Duration::from_millis(1000);
+// The flight controller calibrates its sensors again after a reboot
+const REBOOT_TIMEOUT: Duration = Duration::from_secs(10);
+// Attempts for commands that are safe to send twice
+const MAX_ATTEMPTS: u32 = 3;
+
+// Sequenced commands from clients, followed until the flight controller
+// answers them. Each update is reported to the client that sent the command.
+#[derive(Default)]
+pub struct Tracker {
+    pending: Mutex<Vec<Pending>>,
+}
+
+struct Pending {
+    client: u64,
+    seq: u32,
+    line: String,
+    command: Option<Command>,
+    delivered: bool,
+    attempts: u32,
+    deadline: Instant,
+}
+
+// Report for `client` about one of its commands
+pub type Update = (u64, CommandAck);
+
+fn ack(seq: u32, status: CommandStatus, reason: Option<String>) -> CommandAck {
+    CommandAck {
+        seq,
+        status,
+        reason,
+    }
+}
+
+// Commands without an answer are done once written
+fn has_answer(command: &Option<Command>) -> bool {
+    matches!(
+        command,
+        Some(Command::Arm | Command::Abort | Command::Reboot | Command::Pid { .. })
+    )
+}
+
+// Whether serial `line` is the flight controller's answer to `command`
+fn answers(command: &Option<Command>, line: &str) -> bool {
+    let line = line.trim_end();
+    match command {
+        Some(Command::Arm) => line == "Armed...",
+        Some(Command::Abort) => line == "Aborting...",
+        // "Starting Drone..." is only printed by DEBUG_SERIAL builds
+        Some(Command::Reboot) => line == "Waiting for command to arm...",
+        // Telemetry reports the roll constants in use
+        Some(Command::Pid { p, i, d }) => SerialData::decode(line).is_ok_and(|data| {
+            let close = |a: f32, b: f32| (a - b).abs() <= 1e-3 * a.abs().max(1.0);
+            let (kp_r, ki_r, kd_r) = (data.kp_r, data.ki_r, data.kd_r);
+            close(kp_r, *p) && close(ki_r, *i) && close(kd_r, *d)
+        }),
+        _ => false,
+    }
+}
+
+fn timeout(command: &Option<Command>) -> Duration {
+    match command {
+        Some(Command::Reboot) => REBOOT_TIMEOUT,
+        _ => ACK_TIMEOUT,
+    }
+}
+
+// A second reboot would restart the flight controller again
+fn retried(command: &Option<Command>) -> bool {
+    matches!(
+        command,
+        Some(Command::Arm | Command::Abort | Command::Pid { .. })
+    )
+}
+
+impl Tracker {
+    // Starts following `line` from `client`, about to be queued
+    pub fn sent(&self, client: u64, seq: u32, line: &str, now: Instant) -> CommandAck {
+        let command = Command::decode(line).ok();
+        let deadline = now + timeout(&command);
+        self.pending.lock().unwrap().push(Pending {
+            client,
+            seq,
+            line: line.to_string(),
+            command,
+            delivered: false,
+            attempts: 1,
+            deadline,
+        });
+        ack(seq, CommandStatus::Sent, None)
+    }
+
+    // `line` was written to the flight controller, it belongs to the oldest
+    // command waiting with the same line
+    pub fn written(&self, line: &str, now: Instant) -> Vec<Update> {
+        let mut pending = self.pending.lock().unwrap();
+        let Some(index) = pending.iter().position(|p| !p.delivered && p.line == line) else {
+            return Vec::new();
+        };
+        let entry = &mut pending[index];
+        entry.delivered = true;
+        entry.deadline = now + timeout(&entry.command);
+        let update = (entry.client, ack(entry.seq, CommandStatus::Delivered, None));
+        if !has_answer(&entry.command) {
+            pending.remove(index);
+        }
+        vec![update]
+    }
+
+    // Acknowledges every delivered command that serial `line` answers
+    pub fn received(&self, line: &str) -> Vec<Update> {
+        let mut updates = Vec::new();
+        self.pending.lock().unwrap().retain(|p| {
+            let answered = p.delivered && answers(&p.command, line);
+            if answered {
+                updates.push((p.client, ack(p.seq, CommandStatus::Acknowledged, None)));
+            }
+            !answered
+        });
+        updates
+    }
+
+    // Commands past their deadline are sent again through `resend` if that is
+    // safe and their client is still the pilot, otherwise they fail. `resend`
+    // runs under the lock, so a `cancel` either comes first or sees the
+    // command queued again.
+    pub fn expire(
+        &self,
+        now: Instant,
+        is_pilot: impl Fn(u64) -> bool,
+        mut resend: impl FnMut(&str),
+    ) -> Vec<Update> {
+        let mut updates = Vec::new();
+        self.pending.lock().unwrap().retain_mut(|p| {
+            if now < p.deadline {
+                return true;
+            }
+            if !is_pilot(p.client) {
+                let reason = Some("no longer the pilot".to_string());
+                updates.push((p.client, ack(p.seq, CommandStatus::Failed, reason)));
+                return false;
+            }
+            if retried(&p.command) && p.attempts < MAX_ATTEMPTS {
+                p.attempts += 1;
+                p.delivered = false;
+                p.deadline = now + timeout(&p.command);
+                let reason = format!("attempt {} of {}", p.attempts, MAX_ATTEMPTS);
+                updates.push((p.client, ack(p.seq, CommandStatus::Sent, Some(reason))));
+                resend(&p.line);
+                return true;
+            }
+            let reason = if p.delivered {
+                "no answer from the flight controller"
+            } else {
+                "not written to the flight controller"
+            };
+            let reason = format!("{} after {} attempt(s)", reason, p.attempts);
+            updates.push((p.client, ack(p.seq, CommandStatus::Failed, Some(reason))));
+            false
+        });
+        updates
+    }
+
+    // Fails the commands of `client`, or of every client, so none of them is
+    // sent again
+    pub fn cancel(&self, client: Option<u64>, reason: &str) -> Vec<Update> {
+        let mut updates = Vec::new();
+        self.pending.lock().unwrap().retain(|p| {
+            if client.is_some_and(|client| client != p.client) {
+                return true;
+            }
+            let reason = Some(reason.to_string());
+            updates.push((p.client, ack(p.seq, CommandStatus::Failed, reason)));
+            false
+        });
+        updates
+    }
+}
+
+// Cancels the pending commands of `client`, or of every client, and reports it
+// to the clients that sent them. Called when control changes hands and before
+// a safe sequence is queued.
+pub fn cancel(bridge: &Bridge, client: Option<u64>, reason: &str) {
+    for update in bridge.deliveries.cancel(client, reason) {
+        // Sending only fails when no client is connected
+        let _ = bridge.acks.send(update);
+    }
+}
+
+pub async fn run(bridge: Arc<Bridge>) {
+    let mut lines = bridge.serial.lines.subscribe();
+    let mut written = bridge.serial.written.subscribe();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        // A command is written before the flight controller can answer it
+        let updates = tokio::select! {
+            biased;
+            line = written.recv() => match line {
+                Ok(line) => bridge.deliveries.written(&line, Instant::now()),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            line = lines.recv() => match line {
+                Ok(line) => bridge.deliveries.received(&line),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            _ = interval.tick() => bridge.deliveries.expire(
+                Instant::now(),
+                |client| bridge.control.is_pilot(client),
+                |line| bridge.serial.commands.push(line.to_string()),
+            ),
+        };
+        for update in updates {
+            // Sending only fails when no client is connected
+            let _ = bridge.acks.send(update);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn telemetry(kp_r: f32, ki_r: f32, kd_r: f32) -> String {
+        SerialData {
+            kp_r,
+            ki_r,
+            kd_r,
+            ..Default::default()
+        }
+        .encode()
+    }
+
+    // Expires with `pilot` in control, returns the updates and the lines sent again
+    fn expire(tracker: &Tracker, now: Instant, pilot: u64) -> (Vec<Update>, Vec<String>) {
+        let mut resend = Vec::new();
+        let updates = tracker.expire(
+            now,
+            |client| client == pilot,
+            |line| resend.push(line.to_string()),
+        );
+        (updates, resend)
+    }
+
+    fn statuses(updates: Vec<Update>) -> Vec<(u64, u32, CommandStatus)> {
+        updates
+            .into_iter()
+            .map(|(client, ack)| (client, ack.seq, ack.status))
+            .collect()
+    }
+
+    #[test]
+    fn acknowledges_answered_commands() {
+        let tracker = Tracker::default();
+        let now = Instant::now();
+        tracker.sent(1, 7, "command->arm", now);
+        tracker.sent(1, 8, "pid->2.5,0.2,0.01\n", now);
+        tracker.sent(1, 9, "hello", now);
+
+        // Answers only count once the command is written
+        assert!(tracker.received("Armed...").is_empty());
+        assert_eq!(
+            statuses(tracker.written("command->arm", now)),
+            vec![(1, 7, CommandStatus::Delivered)]
+        );
+        assert_eq!(
+            statuses(tracker.received("Armed...\r\n")),
+            vec![(1, 7, CommandStatus::Acknowledged)]
+        );
+
+        tracker.written("pid->2.5,0.2,0.01\n", now);
+        assert!(tracker.received(&telemetry(4.6, 0.1, 0.0)).is_empty());
+        assert_eq!(
+            statuses(tracker.received(&telemetry(2.5, 0.2, 0.01))),
+            vec![(1, 8, CommandStatus::Acknowledged)]
+        );
+
+        // Text has no answer, writing it is the end
+        assert_eq!(
+            statuses(tracker.written("hello", now)),
+            vec![(1, 9, CommandStatus::Delivered)]
+        );
+        assert!(tracker.pending.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn retries_then_fails() {
+        let tracker = Tracker::default();
+        let start = Instant::now();
+        tracker.sent(2, 1, "command->arm", start);
+        tracker.sent(2, 2, "command->reboot", start);
+
+        let mut now = start;
+        for attempt in 2..=MAX_ATTEMPTS {
+            tracker.written("command->arm", now);
+            now += ACK_TIMEOUT;
+            let (updates, resend) = expire(&tracker, now, 2);
+            assert_eq!(statuses(updates), vec![(2, 1, CommandStatus::Sent)]);
+            assert_eq!(resend, vec!["command->arm"]);
+            assert_eq!(tracker.pending.lock().unwrap()[0].attempts, attempt);
+        }
+        now += ACK_TIMEOUT;
+        let (updates, resend) = expire(&tracker, now, 2);
+        assert!(resend.is_empty());
+        assert_eq!(updates[0].1.status, CommandStatus::Failed);
+        assert_eq!(
+            updates[0].1.reason.as_deref(),
+            Some("not written to the flight controller after 3 attempt(s)")
+        );
+
+        // Reboot is never sent twice
+        let (updates, resend) = expire(&tracker, start + REBOOT_TIMEOUT, 2);
+        assert!(resend.is_empty());
+        assert_eq!(statuses(updates), vec![(2, 2, CommandStatus::Failed)]);
+    }
+
+    #[test]
+    fn reboot_needs_the_boot_message() {
+        let tracker = Tracker::default();
+        let now = Instant::now();
+        tracker.sent(1, 1, "command->reboot", now);
+        tracker.written("command->reboot", now);
+
+        assert!(tracker.received("Starting Drone...").is_empty());
+        assert_eq!(
+            statuses(tracker.received("Waiting for command to arm...\r\n")),
+            vec![(1, 1, CommandStatus::Acknowledged)]
+        );
+    }
+
+    #[test]
+    fn only_the_pilot_is_retried() {
+        let tracker = Tracker::default();
+        let start = Instant::now();
+        tracker.sent(1, 1, "command->arm", start);
+        tracker.written("command->arm", start);
+
+        // Control went to client 2 in the meantime
+        let (updates, resend) = expire(&tracker, start + ACK_TIMEOUT, 2);
+        assert!(resend.is_empty());
+        assert_eq!(statuses(updates), vec![(1, 1, CommandStatus::Failed)]);
+        assert!(tracker.pending.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn cancelled_commands_are_not_sent_again() {
+        let tracker = Tracker::default();
+        let start = Instant::now();
+        tracker.sent(1, 1, "command->arm", start);
+        tracker.sent(1, 2, "pid->2.5,0.2,0.01\n", start);
+        tracker.sent(2, 1, "command->abort", start);
+
+        assert_eq!(
+            statuses(tracker.cancel(Some(1), "control released")),
+            vec![(1, 1, CommandStatus::Failed), (1, 2, CommandStatus::Failed)]
+        );
+        assert_eq!(
+            statuses(tracker.cancel(None, "failsafe triggered")),
+            vec![(2, 1, CommandStatus::Failed)]
+        );
+
+        let (updates, resend) = expire(&tracker, start + ACK_TIMEOUT, 1);
+        assert!(updates.is_empty() && resend.is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+use protocol::{Command, SerialData};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+
+use crate::config::EmulatorConfig;
+use crate::queue::CommandQueue;
+use crate::recorder::{Entry, Recorder};
+use crate::serial::{self, LinkState, SerialLink, SerialStats};
+
+// Timings of `firmware/src/main.cpp` and `transmitter.h`
+const LOOP_INTERVAL: Duration = Duration::from_millis(10);
+const TRANSMIT_INTERVAL_MS: u64 = 20;
+const WAIT_FOR_ARM_INTERVAL_MS: u64 = 400;
+const ENABLE_MOTORS_TIMEOUT_MS: u64 = 200;
+const DEFAULT_PID: (f32, f32, f32) = (3.0, 0.1, 0.0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Booting { ready_at: u64 },
+    WaitingForArm,
+    Flying,
+    // The firmware spins forever after an abort, only a power cycle recovers it
+    Aborted,
+}
+
+// Simulated flight controller speaking the same line protocol as the firmware.
+// The sensors read a level, stationary drone, so the roll PID output stays zero
+// and the motor outputs follow the throttle.
+pub struct Emulator {
+    state: State,
+    boot_ms: u64,
+    booted_at: u64,
+    last_transmit: Option<u64>,
+    // Throttle, yaw, pitch, roll
+    rc: (i32, i32, i32, i32),
+    pid: (f32, f32, f32),
+    last_enable_motors: Option<u64>,
+    motors_disabled: bool,
+    // Bytes written since the last `\n`
+    input: String,
+}
+
+impl Emulator {
+    pub fn new(now: u64, boot_ms: u64) -> Self {
+        Self {
+            state: State::Booting {
+                ready_at: now + boot_ms,
+            },
+            boot_ms,
+            booted_at: now,
+            last_transmit: None,
+            rc: (1000, 1500, 1500, 1500),
+            pid: DEFAULT_PID,
+            last_enable_motors: None,
+            motors_disabled: true,
+            input: String::new(),
+        }
+    }
+
+    // Takes bytes written by the bridge, returns the lines sent back. Like the
+    // firmware's `readStringUntil('\n')`, a command is only read once its
+    // newline arrives and must then match exactly.
+    pub fn write(&mut self, bytes: &str, now: u64) -> Vec<String> {
+        self.input.push_str(bytes);
+        let mut output = Vec::new();
+        while let Some(end) = self.input.find('\n') {
+            let line: String = self.input.drain(..=end).collect();
+            output.extend(self.receive(&line[..end], now));
+        }
+        output
+    }
+
+    // Handles one line without its newline like `setup()` and `loop()` do
+    fn receive(&mut self, line: &str, now: u64) -> Vec<String> {
+        match self.state {
+            State::WaitingForArm if line == "command->arm" => {
+                self.state = State::Flying;
+                self.rc = (1000, 1500, 1500, 1500);
+                vec!["Armed...".to_string()]
+            }
+            State::Flying => {
+                if line.starts_with("rc->") {
+                    if let Ok(Command::Rc {
+                        throttle,
+                        yaw,
+                        pitch,
+                        roll,
+                    }) = Command::decode(line)
+                    {
+                        self.rc = (throttle, yaw, pitch, roll);
+                    }
+                }
+                if line.starts_with("pid->") {
+                    if let Ok(Command::Pid { p, i, d }) = Command::decode(line) {
+                        self.pid = (p, i, d);
+                    }
+                }
+                match line {
+                    "command->abort" => {
+                        self.state = State::Aborted;
+                        vec!["Aborting...".to_string()]
+                    }
+                    "command->enable_motors" => {
+                        self.last_enable_motors = Some(now);
+                        Vec::new()
+                    }
+                    "command->reboot" => {
+                        println!("Emulator rebooting");
+                        *self = Emulator::new(now, self.boot_ms);
+                        Vec::new()
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Runs the firmware loop up to `now`, returns the lines sent back
+    pub fn update(&mut self, now: u64) -> Vec<String> {
+        let mut lines = Vec::new();
+        match self.state {
+            State::Booting { ready_at } if now >= ready_at => {
+                self.state = State::WaitingForArm;
+                lines.push("Waiting for command to arm...".to_string());
+                lines.push(SerialData::default().encode());
+                self.last_transmit = Some(now);
+            }
+            State::WaitingForArm if self.transmit_due(now, WAIT_FOR_ARM_INTERVAL_MS) => {
+                self.last_transmit = Some(now);
+                lines.push(SerialData::default().encode());
+            }
+            State::Flying => {
+                self.check_motors(now);
+                if self.transmit_due(now, TRANSMIT_INTERVAL_MS) {
+                    self.last_transmit = Some(now);
+                    lines.push(self.serial_data(now).encode());
+                }
+            }
+            _ => {}
+        }
+        lines
+    }
+
+    fn transmit_due(&self, now: u64, interval: u64) -> bool {
+        !matches!(self.last_transmit, Some(last) if now - last < interval)
+    }
+
+    // Motors are disabled without a recent `command->enable_motors`, and only
+    // enabled again while the throttle is low
+    fn check_motors(&mut self, now: u64) {
+        let keepalive = matches!(
+            self.last_enable_motors,
+            Some(last) if now - last <= ENABLE_MOTORS_TIMEOUT_MS
+        );
+        if !keepalive {
+            if !self.motors_disabled {
+                println!("Emulator: last enable motor check: disabling motors...");
+            }
+            self.motors_disabled = true;
+        } else if self.motors_disabled && (1000..=1150).contains(&self.rc.0) {
+            println!("Emulator: motors enabled");
+            self.motors_disabled = false;
+        }
+    }
+
+    fn serial_data(&self, now: u64) -> SerialData {
+        let (throttle, yaw, pitch, roll) = self.rc;
+        let motor = throttle.clamp(1000, 2000);
+        SerialData {
+            elapsed_time: (now - self.booted_at) as f32,
+            acc_z: 9.81,
+            mag_x: 20.0,
+            mag_z: -40.0,
+            temp: 25.0,
+            rc_throttle: throttle,
+            rc_yaw: yaw,
+            rc_pitch: pitch,
+            rc_roll: roll,
+            front_right: motor,
+            back_right: motor,
+            back_left: motor,
+            front_left: motor,
+            kp_r: self.pid.0,
+            ki_r: self.pid.1,
+            kd_r: self.pid.2,
+            ..Default::default()
+        }
+    }
+}
+
+// Runs the emulator behind the same channels as a real serial port
+pub fn open(config: &EmulatorConfig, recorder: Recorder) -> SerialLink {
+    let (lines, _) = broadcast::channel::<String>(100);
+    let commands = Arc::new(CommandQueue::default());
+    let (written, _) = broadcast::channel::<String>(100);
+    let stats = Arc::new(SerialStats::default());
+    println!(
+        "Emulating the flight controller, boot takes {} ms",
+        config.boot_ms
+    );
+
+    let tx = lines.clone();
+    let boot_ms = config.boot_ms;
+    let queue = commands.clone();
+    let written_tx = written.clone();
+    let line_stats = stats.clone();
+    tokio::spawn(async move {
+        let start = Instant::now();
+        let millis = || start.elapsed().as_millis() as u64;
+        let mut emulator = Emulator::new(millis(), boot_ms);
+        let mut interval = tokio::time::interval(LOOP_INTERVAL);
+
+        loop {
+            let output = tokio::select! {
+                _ = interval.tick() => emulator.update(millis()),
+                command = queue.pop() => {
+                    println!("Processing command: {}", command);
+                    recorder.record(Entry::Command {
+                        line: command.trim_end().to_string(),
+                    });
+                    let line = serial::terminated(&command);
+                    line_stats.wrote(line.len());
+                    let output = emulator.write(&line, millis());
+                    let _ = written_tx.send(command);
+                    output
+                }
+            };
+            for line in output {
+                line_stats.read(line.len());
+                line_stats.line(&line);
+                recorder.record(Entry::Serial { line: line.clone() });
+                // Sending only fails when no client is connected
+                let _ = tx.send(line);
+            }
+        }
+    });
+
+    SerialLink {
+        lines,
+        commands,
+        written,
+        stats,
+        state: watch::channel(LinkState::Up).1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn armed() -> Emulator {
+        let mut emulator = Emulator::new(0, 100);
+        assert!(emulator.update(50).is_empty());
+        assert_eq!(emulator.update(100)[0], "Waiting for command to arm...");
+        assert_eq!(emulator.write("command->arm\n", 110), vec!["Armed..."]);
+        emulator
+    }
+
+    #[test]
+    fn waits_for_arm() {
+        let mut emulator = Emulator::new(0, 100);
+        emulator.update(100);
+        assert!(emulator.write("rc->1200,1500,1500,1500\n", 120).is_empty());
+        assert!(emulator.update(300).is_empty());
+        assert_eq!(emulator.update(500).len(), 1);
+        assert_eq!(emulator.write("command->arm\n", 510), vec!["Armed..."]);
+    }
+
+    #[test]
+    fn telemetry_follows_commands() {
+        let mut emulator = armed();
+        emulator.write("rc->1200,1400,1600,1550\n", 120);
+        emulator.write("pid->2.5,0.2,0.01\n", 120);
+
+        let lines = emulator.update(130);
+        let data = SerialData::decode(&lines[0]).unwrap();
+        let (throttle, roll, front_left, kp_r, elapsed_time) = (
+            data.rc_throttle,
+            data.rc_roll,
+            data.front_left,
+            data.kp_r,
+            data.elapsed_time,
+        );
+        assert_eq!((throttle, roll, front_left), (1200, 1550, 1200));
+        assert_eq!(kp_r, 2.5);
+        assert_eq!(elapsed_time, 130.0);
+
+        // Telemetry goes out every 20 ms
+        assert!(emulator.update(140).is_empty());
+        assert_eq!(emulator.update(150).len(), 1);
+    }
+
+    #[test]
+    fn motors_need_keepalive_and_low_throttle() {
+        let mut emulator = armed();
+        emulator.update(120);
+        assert!(emulator.motors_disabled);
+
+        emulator.write("command->enable_motors\n", 130);
+        emulator.update(140);
+        assert!(!emulator.motors_disabled);
+
+        emulator.update(331);
+        assert!(emulator.motors_disabled);
+
+        emulator.write("rc->1500,1500,1500,1500\n", 340);
+        emulator.write("command->enable_motors\n", 340);
+        emulator.update(350);
+        assert!(emulator.motors_disabled);
+    }
+
+    #[test]
+    fn abort_and_reboot() {
+        let mut emulator = armed();
+        emulator.write("command->reboot\n", 200);
+        assert!(emulator.update(250).is_empty());
+        assert_eq!(emulator.update(300)[0], "Waiting for command to arm...");
+
+        emulator.write("command->arm\n", 310);
+        assert_eq!(emulator.write("command->abort\n", 320), vec!["Aborting..."]);
+        assert!(emulator.update(400).is_empty());
+        assert!(emulator.write("command->reboot\n", 410).is_empty());
+        assert!(emulator.update(1000).is_empty());
+    }
+
+    #[test]
+    fn reads_up_to_newlines() {
+        let mut emulator = armed();
+        // Two commands run together are one line the firmware does not know
+        assert!(emulator
+            .write("command->abortrc->1000,1500,1500,1500\n", 200)
+            .is_empty());
+        assert_eq!(emulator.state, State::Flying);
+
+        assert!(emulator.write("rc->1100,1500,", 210).is_empty());
+        assert_eq!(emulator.rc.0, 1000);
+        assert_eq!(
+            emulator.write("1500,1500\ncommand->abort\n", 220),
+            vec!["Aborting..."]
+        );
+        assert_eq!(emulator.rc.0, 1100);
+    }
+}
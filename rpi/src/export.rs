@@ -0,0 +1,250 @@
+use protocol::{LineKind, SerialData};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::classify::classify;
+use crate::config::ExportArgs;
+use crate::foxglove;
+use crate::mcap::McapWriter;
+use crate::recorder::{Entry, Record};
+
+const LINE_SCHEMA: &str = r#"{"type":"object","properties":{"line":{"type":"string"}}}"#;
+const ACK_SCHEMA: &str =
+    r#"{"type":"object","properties":{"command":{"type":"string"},"line":{"type":"string"}}}"#;
+const EVENT_SCHEMA: &str = r#"{"type":"object","properties":{"kind":{"type":"string"},"timestamp":{"type":"integer"},"reason":{"type":"string"},"sent":{"type":"array","items":{"type":"string"}}}}"#;
+const SESSION_SCHEMA: &str =
+    r#"{"type":"object","properties":{"kind":{"type":"string"},"session":{"type":"string"}}}"#;
+
+// A flight log as MCAP, one JSON channel per kind of record
+pub struct FlightLog<W: Write> {
+    mcap: McapWriter<W>,
+    telemetry: u16,
+    log: u16,
+    ack: u16,
+    unknown: u16,
+    command: u16,
+    event: u16,
+    session: u16,
+}
+
+impl<W: Write> FlightLog<W> {
+    pub fn new(out: W) -> io::Result<FlightLog<W>> {
+        let mut mcap = McapWriter::new(out)?;
+        let fields =
+            serde_json::to_value(SerialData::default()).expect("SerialData is always serializable");
+        let fields: Vec<&str> = fields
+            .as_object()
+            .map(|fields| fields.keys().map(String::as_str).collect())
+            .unwrap_or_default();
+
+        let mut channel = |topic: &str, name: &str, schema: &str| {
+            let schema = mcap.schema(name, "jsonschema", schema.as_bytes())?;
+            mcap.channel(schema, topic, "json")
+        };
+        let telemetry = channel("/telemetry", "SerialData", &foxglove::schema(&fields))?;
+        let log = channel("/log", "foxglove.Log", foxglove::LOG_SCHEMA)?;
+        let ack = channel("/ack", "Ack", ACK_SCHEMA)?;
+        let unknown = channel("/unknown", "Line", LINE_SCHEMA)?;
+        let command = channel("/command", "Line", LINE_SCHEMA)?;
+        let event = channel("/event", "BridgeEvent", EVENT_SCHEMA)?;
+        let session = channel("/session", "Session", SESSION_SCHEMA)?;
+        Ok(FlightLog {
+            mcap,
+            telemetry,
+            log,
+            ack,
+            unknown,
+            command,
+            event,
+            session,
+        })
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        let log_time = record.wall_ms * 1_000_000;
+        let (channel, message) = match &record.entry {
+            Entry::Serial { line } => {
+                let line = line.trim();
+                let kind = classify(line);
+                match &kind {
+                    LineKind::Telemetry => match SerialData::decode(line) {
+                        Ok(data) => (self.telemetry, json!(data)),
+                        Err(_) => (self.unknown, json!({ "line": line })),
+                    },
+                    LineKind::Ack { command } => {
+                        (self.ack, json!({ "command": command, "line": line }))
+                    }
+                    LineKind::Unknown => (self.unknown, json!({ "line": line })),
+                    LineKind::Log { .. } => match foxglove::log(line, &kind, log_time) {
+                        Some(message) => (self.log, message),
+                        None => return Ok(()),
+                    },
+                }
+            }
+            Entry::Command { line } => (self.command, json!({ "line": line })),
+            Entry::Event { event } => (self.event, json!(event)),
+            Entry::SessionStart { .. } | Entry::SessionStop { .. } => {
+                (self.session, json!(record.entry))
+            }
+        };
+        self.mcap
+            .message(channel, log_time, Value::to_string(&message).as_bytes())
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        self.mcap.finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Read(PathBuf, io::Error),
+    Write(PathBuf, io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Read(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ExportError::Write(path, e) => write!(f, "failed to write {}: {}", path.display(), e),
+        }
+    }
+}
+
+// Converts the parts of a recorded session, in the order given, to one MCAP file
+pub fn run(args: &ExportArgs) -> Result<(), ExportError> {
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => args.logs[0].with_extension("mcap"),
+    };
+    let write_error = |e| ExportError::Write(output.clone(), e);
+    let file = File::create(&output).map_err(write_error)?;
+    let mut log = FlightLog::new(BufWriter::new(file)).map_err(write_error)?;
+
+    let (mut written, mut skipped) = (0, 0);
+    for path in &args.logs {
+        let read_error = |e| ExportError::Read(path.clone(), e);
+        let file = File::open(path).map_err(read_error)?;
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(read_error)?;
+            match serde_json::from_str::<Record>(&line) {
+                Ok(record) => {
+                    log.write(&record).map_err(write_error)?;
+                    written += 1;
+                }
+                Err(_) => skipped += 1,
+            }
+        }
+    }
+    log.finish().map_err(write_error)?;
+
+    println!("Wrote {} records to {}", written, output.display());
+    if skipped > 0 {
+        eprintln!("Skipped {} lines that are not flight log records", skipped);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::BridgeEvent;
+
+    fn record(wall_ms: u64, entry: Entry) -> Record {
+        Record {
+            mono_ms: wall_ms - 1000,
+            wall_ms,
+            entry,
+        }
+    }
+
+    fn serial(wall_ms: u64, line: &str) -> Record {
+        record(
+            wall_ms,
+            Entry::Serial {
+                line: line.to_string(),
+            },
+        )
+    }
+
+    // Topic of every message, in the order they were written
+    fn topics(file: &[u8]) -> Vec<String> {
+        let topics = [
+            "/telemetry",
+            "/log",
+            "/ack",
+            "/unknown",
+            "/command",
+            "/event",
+            "/session",
+        ];
+        let mut found = Vec::new();
+        // Message records inside the chunk, after the schemas and channels
+        let first = br#"{"kind":"session_start""#;
+        let data = file
+            .windows(first.len())
+            .position(|window| window == first)
+            .unwrap();
+        // Back over the times, sequence, channel, length and opcode
+        let mut rest = &file[data - 8 - 8 - 4 - 2 - 9..];
+        while rest[0] == 0x05 {
+            let len = u64::from_le_bytes(rest[1..9].try_into().unwrap()) as usize;
+            let channel = u16::from_le_bytes([rest[9], rest[10]]);
+            found.push(topics[channel as usize - 1].to_string());
+            rest = &rest[9 + len..];
+        }
+        found
+    }
+
+    #[test]
+    fn one_channel_per_kind() {
+        let records = [
+            record(
+                1000,
+                Entry::SessionStart {
+                    session: "session-a".to_string(),
+                },
+            ),
+            serial(1001, "Waiting for command to arm..."),
+            record(
+                1002,
+                Entry::Command {
+                    line: "command->arm".to_string(),
+                },
+            ),
+            serial(1003, "Armed...\r\n"),
+            serial(1004, &SerialData::default().encode()),
+            serial(1005, "\u{1}\u{fffd}"),
+            record(
+                1006,
+                Entry::Event {
+                    event: BridgeEvent::SerialUp { timestamp: 1006 },
+                },
+            ),
+        ];
+        let mut log = FlightLog::new(Vec::new()).unwrap();
+        for record in &records {
+            log.write(record).unwrap();
+        }
+        let file = log.finish().unwrap();
+
+        assert_eq!(
+            topics(&file),
+            [
+                "/session",
+                "/log",
+                "/command",
+                "/ack",
+                "/telemetry",
+                "/unknown",
+                "/event"
+            ]
+        );
+        let text = String::from_utf8_lossy(&file);
+        assert!(text.contains(r#"{"command":"command->arm","line":"Armed..."}"#));
+        assert!(text.contains(r#""level":2,"line":0,"message":"Waiting for command to arm...""#));
+    }
+}
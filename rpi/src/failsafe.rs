@@ -0,0 +1,146 @@
+use protocol::{BridgeEvent, Command};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::bridge::Bridge;
+use crate::config::FailsafeAction;
+use crate::delivery;
+use crate::recorder::Entry;
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+// Armed while a pilot is connected, fed by every message from the pilot
+#[derive(Default)]
+pub struct Watchdog {
+    last_heartbeat: Mutex<Option<Instant>>,
+}
+
+impl Watchdog {
+    pub fn feed(&self, now: Instant) {
+        *self.last_heartbeat.lock().unwrap() = Some(now);
+    }
+
+    // Called when the pilot gives up control on purpose
+    pub fn disarm(&self) {
+        *self.last_heartbeat.lock().unwrap() = None;
+    }
+
+    // Returns true once when the timeout elapses, the watchdog is disarmed until fed again
+    pub fn expired(&self, now: Instant, timeout: Duration) -> bool {
+        let mut last_heartbeat = self.last_heartbeat.lock().unwrap();
+        match *last_heartbeat {
+            Some(last) if now.duration_since(last) > timeout => {
+                *last_heartbeat = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Throttle low first, then the action
+pub fn safe_sequence(action: FailsafeAction) -> Vec<Command> {
+    let mut sequence = vec![Command::Rc {
+        throttle: 1000,
+        yaw: 1500,
+        pitch: 1500,
+        roll: 1500,
+    }];
+    match action {
+        FailsafeAction::Abort => sequence.push(Command::Abort),
+        FailsafeAction::Reboot => sequence.push(Command::Reboot),
+        FailsafeAction::None => {}
+    }
+    sequence
+}
+
+pub async fn run(bridge: Arc<Bridge>) {
+    let config = bridge.config.failsafe.clone();
+    if !config.enabled {
+        return;
+    }
+
+    let timeout = Duration::from_millis(config.timeout_ms);
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if !bridge.watchdog.expired(Instant::now(), timeout) {
+            continue;
+        }
+
+        trigger(
+            &bridge,
+            config.action,
+            format!("no heartbeat from pilot for {} ms", config.timeout_ms),
+        );
+    }
+}
+
+// Queues the safe sequence in place of everything waiting and reports why to every client
+pub fn trigger(bridge: &Bridge, action: FailsafeAction, reason: String) {
+    let sequence = safe_sequence(action);
+    let lines: Vec<String> = sequence.iter().map(|command| command.encode()).collect();
+    // Nothing is retried after the safe sequence
+    delivery::cancel(bridge, None, "failsafe triggered");
+    bridge.serial.commands.preempt(&lines);
+
+    let event = BridgeEvent::Failsafe {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        reason,
+        sent: sequence
+            .iter()
+            .map(|command| command.encode().trim_end().to_string())
+            .collect(),
+    };
+    eprintln!("{}", event.describe());
+    bridge.recorder.record(Entry::Event {
+        event: event.clone(),
+    });
+    *bridge.last_failsafe.lock().unwrap() = Some(event.clone());
+    // Sending only fails when no client is connected
+    let _ = bridge.events.send(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_once_after_timeout() {
+        let watchdog = Watchdog::default();
+        let start = Instant::now();
+        let timeout = Duration::from_millis(500);
+
+        assert!(!watchdog.expired(start + Duration::from_secs(10), timeout));
+
+        watchdog.feed(start);
+        assert!(!watchdog.expired(start + Duration::from_millis(400), timeout));
+        assert!(watchdog.expired(start + Duration::from_millis(600), timeout));
+        assert!(!watchdog.expired(start + Duration::from_millis(700), timeout));
+    }
+
+    #[test]
+    fn feeding_and_disarming() {
+        let watchdog = Watchdog::default();
+        let start = Instant::now();
+        let timeout = Duration::from_millis(500);
+
+        watchdog.feed(start);
+        watchdog.feed(start + Duration::from_millis(400));
+        assert!(!watchdog.expired(start + Duration::from_millis(800), timeout));
+
+        watchdog.disarm();
+        assert!(!watchdog.expired(start + Duration::from_secs(10), timeout));
+    }
+
+    #[test]
+    fn throttle_low_first() {
+        let sequence = safe_sequence(FailsafeAction::Abort);
+        assert_eq!(sequence[0].encode(), "rc->1000,1500,1500,1500\n");
+        assert_eq!(sequence[1], Command::Abort);
+        assert_eq!(safe_sequence(FailsafeAction::None).len(), 1);
+    }
+}
@@ -0,0 +1,426 @@
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use protocol::{LineKind, SerialData, Severity};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::handshake::server::{
+    Callback, ErrorResponse, Request, Response,
+};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{accept_hdr_async, WebSocketStream};
+
+use crate::bridge::Bridge;
+use crate::classify::classify;
+
+// https://github.com/foxglove/ws-protocol/blob/main/docs/spec.md
+pub const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+const OP_MESSAGE_DATA: u8 = 0x01;
+
+// A topic made of `SerialData` fields, with `elapsed_time` in front
+struct Channel {
+    topic: &'static str,
+    schema_name: &'static str,
+    fields: &'static [&'static str],
+}
+
+const CHANNELS: [Channel; 5] = [
+    Channel {
+        topic: "/attitude",
+        schema_name: "Attitude",
+        fields: &["roll", "pitch", "yaw"],
+    },
+    Channel {
+        topic: "/imu",
+        schema_name: "Imu",
+        fields: &[
+            "acc_x", "acc_y", "acc_z", "gyro_x", "gyro_y", "gyro_z", "mag_x", "mag_y", "mag_z",
+        ],
+    },
+    Channel {
+        topic: "/barometer",
+        schema_name: "Barometer",
+        fields: &["altitude", "temp"],
+    },
+    Channel {
+        topic: "/rc",
+        schema_name: "Rc",
+        fields: &["rc_throttle", "rc_yaw", "rc_pitch", "rc_roll"],
+    },
+    Channel {
+        topic: "/motors",
+        schema_name: "Motors",
+        fields: &["front_right", "back_right", "back_left", "front_left"],
+    },
+];
+
+// Text lines from the flight controller, shown in Foxglove's Log panel
+const LOG_CHANNEL: u32 = CHANNELS.len() as u32 + 1;
+pub const LOG_SCHEMA: &str = r#"{"type":"object","properties":{"timestamp":{"type":"object","properties":{"sec":{"type":"integer"},"nsec":{"type":"integer"}}},"level":{"type":"integer"},"message":{"type":"string"},"name":{"type":"string"},"file":{"type":"string"},"line":{"type":"integer"}}}"#;
+
+// Channel ids are the position in `CHANNELS` plus one
+fn channel_id(index: usize) -> u32 {
+    index as u32 + 1
+}
+
+// JSON schema of `fields`, typed after how `SerialData` serializes them
+pub fn schema(fields: &[&str]) -> String {
+    let defaults =
+        serde_json::to_value(SerialData::default()).expect("SerialData is always serializable");
+    let properties: Map<String, Value> = std::iter::once("elapsed_time")
+        .chain(fields.iter().copied())
+        .map(|field| {
+            let kind = match &defaults[field] {
+                Value::Number(number) if number.is_f64() => "number",
+                _ => "integer",
+            };
+            (field.to_string(), json!({ "type": kind }))
+        })
+        .collect();
+    json!({ "type": "object", "properties": properties }).to_string()
+}
+
+fn server_info(session_id: u64) -> String {
+    json!({
+        "op": "serverInfo",
+        "name": "drone bridge",
+        "capabilities": [],
+        "supportedEncodings": [],
+        "metadata": {},
+        "sessionId": session_id.to_string(),
+    })
+    .to_string()
+}
+
+fn advertise() -> String {
+    let mut channels: Vec<Value> = CHANNELS
+        .iter()
+        .enumerate()
+        .map(|(index, channel)| {
+            json!({
+                "id": channel_id(index),
+                "topic": channel.topic,
+                "encoding": "json",
+                "schemaName": channel.schema_name,
+                "schema": schema(channel.fields),
+                "schemaEncoding": "jsonschema",
+            })
+        })
+        .collect();
+    channels.push(json!({
+        "id": LOG_CHANNEL,
+        "topic": "/log",
+        "encoding": "json",
+        "schemaName": "foxglove.Log",
+        "schema": LOG_SCHEMA,
+        "schemaEncoding": "jsonschema",
+    }));
+    json!({ "op": "advertise", "channels": channels }).to_string()
+}
+
+// One message per channel, in channel id order
+fn split(data: &SerialData) -> Vec<Value> {
+    let value = serde_json::to_value(data).expect("SerialData is always serializable");
+    CHANNELS
+        .iter()
+        .map(|channel| {
+            let message: Map<String, Value> = std::iter::once("elapsed_time")
+                .chain(channel.fields.iter().copied())
+                .map(|field| (field.to_string(), value[field].clone()))
+                .collect();
+            Value::Object(message)
+        })
+        .collect()
+}
+
+// `foxglove.Log` levels: 2 info, 3 warning, 4 error
+pub fn log(line: &str, kind: &LineKind, timestamp_ns: u64) -> Option<Value> {
+    let level = match kind {
+        LineKind::Log {
+            severity: Severity::Error,
+        } => 4,
+        LineKind::Log {
+            severity: Severity::Warning,
+        } => 3,
+        LineKind::Log { .. } | LineKind::Ack { .. } => 2,
+        LineKind::Telemetry | LineKind::Unknown => return None,
+    };
+    Some(json!({
+        "timestamp": {
+            "sec": timestamp_ns / 1_000_000_000,
+            "nsec": timestamp_ns % 1_000_000_000,
+        },
+        "level": level,
+        "message": line,
+        "name": "flight controller",
+        "file": "",
+        "line": 0,
+    }))
+}
+
+// Binary message data frame: opcode, subscription id and receive time in ns
+fn message_data(subscription: u32, timestamp_ns: u64, payload: &Value) -> Vec<u8> {
+    let mut frame = vec![OP_MESSAGE_DATA];
+    frame.extend_from_slice(&subscription.to_le_bytes());
+    frame.extend_from_slice(&timestamp_ns.to_le_bytes());
+    frame.extend_from_slice(payload.to_string().as_bytes());
+    frame
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum ClientMessage {
+    Subscribe {
+        subscriptions: Vec<Subscription>,
+    },
+    Unsubscribe {
+        #[serde(rename = "subscriptionIds")]
+        subscription_ids: Vec<u32>,
+    },
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Subscription {
+    id: u32,
+    #[serde(rename = "channelId")]
+    channel_id: u32,
+}
+
+// Serves live telemetry to Foxglove Studio, read-only, until the bridge exits
+pub async fn run(bridge: Arc<Bridge>, listener: TcpListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_connection(stream, Arc::clone(&bridge)));
+            }
+            Err(e) => eprintln!("Failed to accept Foxglove connection: {}", e),
+        }
+    }
+}
+
+// Answers with the Foxglove subprotocol when the client offers it
+struct Negotiate;
+
+impl Callback for Negotiate {
+    fn on_request(
+        self,
+        request: &Request,
+        mut response: Response,
+    ) -> Result<Response, ErrorResponse> {
+        let offered = request
+            .headers()
+            .get_all("Sec-WebSocket-Protocol")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|protocol| protocol.trim() == SUBPROTOCOL);
+        if offered {
+            response.headers_mut().insert(
+                "Sec-WebSocket-Protocol",
+                HeaderValue::from_static(SUBPROTOCOL),
+            );
+        }
+        Ok(response)
+    }
+}
+
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+async fn handle_connection(stream: TcpStream, bridge: Arc<Bridge>) {
+    let ws_stream = match accept_hdr_async(stream, Negotiate).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            eprintln!("Failed to accept Foxglove connection: {}", e);
+            return;
+        }
+    };
+    println!("Foxglove client connected");
+
+    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    let mut lines = bridge.serial.lines.subscribe();
+    // When the bridge started, the same for every connection until it restarts
+    let session_id =
+        (now_ns() / 1_000_000).saturating_sub(bridge.started.elapsed().as_millis() as u64);
+    for text in [server_info(session_id), advertise()] {
+        if let Err(e) = ws_sender.send(Message::Text(text)).await {
+            eprintln!("Foxglove send error: {}", e);
+            return;
+        }
+    }
+
+    // Subscription id by channel id
+    let mut subscriptions: HashMap<u32, u32> = HashMap::new();
+    loop {
+        tokio::select! {
+            line = lines.recv() => {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                if subscriptions.is_empty() {
+                    continue;
+                }
+                if let Err(e) = send_line(&mut ws_sender, &subscriptions, line.trim()).await {
+                    eprintln!("Foxglove send error: {}", e);
+                    break;
+                }
+            }
+            msg = ws_receiver.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                        Ok(ClientMessage::Subscribe { subscriptions: added }) => {
+                            for subscription in added {
+                                subscriptions.insert(subscription.channel_id, subscription.id);
+                            }
+                        }
+                        Ok(ClientMessage::Unsubscribe { subscription_ids }) => {
+                            subscriptions.retain(|_, id| !subscription_ids.contains(id));
+                        }
+                        // Parameters, services and the like are not advertised
+                        Err(_) => {}
+                    },
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Err(e)) => {
+                        eprintln!("Foxglove receive error: {}", e);
+                        break;
+                    }
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+    println!("Foxglove client disconnected");
+}
+
+async fn send_line(
+    ws_sender: &mut SplitSink<WebSocketStream<TcpStream>, Message>,
+    subscriptions: &HashMap<u32, u32>,
+    line: &str,
+) -> Result<(), tungstenite::Error> {
+    let timestamp_ns = now_ns();
+    let kind = classify(line);
+    let messages: Vec<(u32, Value)> = match (&kind, SerialData::decode(line)) {
+        (LineKind::Telemetry, Ok(data)) => split(&data)
+            .into_iter()
+            .enumerate()
+            .map(|(index, message)| (channel_id(index), message))
+            .collect(),
+        _ => log(line, &kind, timestamp_ns)
+            .map(|message| (LOG_CHANNEL, message))
+            .into_iter()
+            .collect(),
+    };
+    for (channel, message) in messages {
+        if let Some(subscription) = subscriptions.get(&channel) {
+            let frame = message_data(*subscription, timestamp_ns, &message);
+            ws_sender.send(Message::Binary(frame)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::emulator;
+    use crate::recorder::Recorder;
+    use std::time::Duration;
+    use tokio_tungstenite::connect_async;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    #[test]
+    fn schemas_follow_serial_data() {
+        assert_eq!(
+            schema(CHANNELS[0].fields),
+            r#"{"properties":{"elapsed_time":{"type":"number"},"pitch":{"type":"integer"},"roll":{"type":"integer"},"yaw":{"type":"integer"}},"type":"object"}"#
+        );
+
+        let data = SerialData {
+            elapsed_time: 1500.0,
+            altitude: 12.5,
+            rc_throttle: 1200,
+            ..Default::default()
+        };
+        let messages = split(&data);
+        assert_eq!(messages.len(), CHANNELS.len());
+        assert_eq!(
+            messages[2],
+            json!({"elapsed_time": 1500.0, "altitude": 12.5, "temp": 0.0})
+        );
+        assert_eq!(messages[3]["rc_throttle"], 1200);
+
+        let frame = message_data(7, 42, &messages[2]);
+        assert_eq!(frame[0], OP_MESSAGE_DATA);
+        assert_eq!(&frame[1..5], 7u32.to_le_bytes());
+        assert_eq!(&frame[5..13], 42u64.to_le_bytes());
+        assert_eq!(
+            serde_json::from_slice::<Value>(&frame[13..]).unwrap(),
+            messages[2]
+        );
+
+        let warning = classify("Last enable motor check: disabling motors...");
+        assert_eq!(
+            log("...", &warning, 1_500_000_000).unwrap()["timestamp"]["sec"],
+            1
+        );
+        assert_eq!(log("...", &warning, 0).unwrap()["level"], 3);
+        assert!(log("", &LineKind::Unknown, 0).is_none());
+    }
+
+    #[tokio::test]
+    async fn streams_to_a_subscriber() {
+        let mut config = Config::default();
+        config.emulator.boot_ms = 0;
+        let recorder = Recorder::disabled();
+        let serial = emulator::open(&config.emulator, recorder.clone());
+        let bridge = Arc::new(Bridge::new(config, serial, recorder, None));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(run(Arc::clone(&bridge), listener));
+
+        let mut request = format!("ws://{}", address).into_client_request().unwrap();
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            HeaderValue::from_static(SUBPROTOCOL),
+        );
+        let (mut ws, response) = connect_async(request).await.unwrap();
+        assert_eq!(response.headers()["Sec-WebSocket-Protocol"], SUBPROTOCOL);
+
+        let mut next_json = async || match ws.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str::<Value>(&text).unwrap(),
+            other => panic!("expected text, got {:?}", other),
+        };
+        assert_eq!(next_json().await["op"], "serverInfo");
+        let advertised = next_json().await;
+        assert_eq!(advertised["channels"].as_array().unwrap().len(), 6);
+        assert_eq!(advertised["channels"][4]["topic"], "/motors");
+
+        let subscribe = r#"{"op":"subscribe","subscriptions":[{"id":9,"channelId":2}]}"#;
+        ws.send(Message::Text(subscribe.to_string())).await.unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(3), async {
+            loop {
+                if let Message::Binary(frame) = ws.next().await.unwrap().unwrap() {
+                    return frame;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(&frame[1..5], 9u32.to_le_bytes());
+        let imu: Value = serde_json::from_slice(&frame[13..]).unwrap();
+        assert!(imu["acc_z"].is_number());
+    }
+}
@@ -0,0 +1,307 @@
+// Small linear algebra helpers for the camera code
+
+pub type Vector = [f64; 3];
+pub type Matrix = [[f64; 3]; 3];
+
+// Levenberg-Marquardt settings of `least_squares`
+const INITIAL_DAMPING: f64 = 1e-3;
+const MAX_DAMPING: f64 = 1e10;
+// Relative cost decrease below which the fit has converged
+const MIN_IMPROVEMENT: f64 = 1e-12;
+const JACOBIAN_STEP: f64 = 1e-7;
+
+pub fn add(a: Vector, b: Vector) -> Vector {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn subtract(a: Vector, b: Vector) -> Vector {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale_by(a: Vector, scale: f64) -> Vector {
+    [a[0] * scale, a[1] * scale, a[2] * scale]
+}
+
+pub fn dot(a: Vector, b: Vector) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vector, b: Vector) -> Vector {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn norm(a: Vector) -> f64 {
+    dot(a, a).sqrt()
+}
+
+pub fn normalize(a: Vector) -> Vector {
+    scale_by(a, 1.0 / norm(a))
+}
+
+pub fn apply(m: &Matrix, v: Vector) -> Vector {
+    [dot(m[0], v), dot(m[1], v), dot(m[2], v)]
+}
+
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+// Rodrigues' formula, from a rotation vector to a rotation matrix
+pub fn rotation_matrix(vector: Vector) -> Matrix {
+    let angle = norm(vector);
+    if angle < 1e-12 {
+        return [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    }
+    let [x, y, z] = scale_by(vector, 1.0 / angle);
+    let (sin, cos) = angle.sin_cos();
+    let c = 1.0 - cos;
+    [
+        [cos + x * x * c, x * y * c - z * sin, x * z * c + y * sin],
+        [y * x * c + z * sin, cos + y * y * c, y * z * c - x * sin],
+        [z * x * c - y * sin, z * y * c + x * sin, cos + z * z * c],
+    ]
+}
+
+// Inverse of `rotation_matrix`, the angle is in [0, pi]
+pub fn rotation_vector(m: &Matrix) -> Vector {
+    let cos = ((m[0][0] + m[1][1] + m[2][2] - 1.0) / 2.0).clamp(-1.0, 1.0);
+    let angle = cos.acos();
+    // Twice the sine of the angle times the axis
+    let axis = [m[2][1] - m[1][2], m[0][2] - m[2][0], m[1][0] - m[0][1]];
+    let sin = norm(axis) / 2.0;
+
+    if sin > 1e-6 {
+        return scale_by(axis, angle / (2.0 * sin));
+    }
+    if cos > 0.0 {
+        // Close to the identity, the angle is about the sine
+        return scale_by(axis, 0.5);
+    }
+
+    // Close to a half turn, the axis comes from the diagonal of m = 2 a a^T - I
+    let largest = (0..3)
+        .max_by(|&i, &j| m[i][i].partial_cmp(&m[j][j]).unwrap())
+        .unwrap();
+    let diagonal = ((m[largest][largest] + 1.0) / 2.0).max(0.0).sqrt();
+    let axis: Vector = std::array::from_fn(|i| {
+        if i == largest {
+            diagonal
+        } else {
+            (m[i][largest] + m[largest][i]) / (4.0 * diagonal)
+        }
+    });
+    scale_by(normalize(axis), angle)
+}
+
+// Rotation and translation of a plane seen through `h`, the homography from
+// plane coordinates (x, y, 1) to the normalized image plane of a camera
+pub fn pose_from_homography(h: &Matrix) -> (Vector, Vector) {
+    // The columns of `h` are r1, r2 and t up to a common scale
+    let column = |i: usize| [h[0][i], h[1][i], h[2][i]];
+    let mut scale = 2.0 / (norm(column(0)) + norm(column(1)));
+    // The plane is in front of the camera
+    if h[2][2] < 0.0 {
+        scale = -scale;
+    }
+    let r1 = normalize(scale_by(column(0), scale));
+    let r2 = scale_by(column(1), scale);
+    let r2 = normalize(subtract(r2, scale_by(r1, dot(r1, r2))));
+    let r3 = cross(r1, r2);
+    let rotation = [
+        [r1[0], r2[0], r3[0]],
+        [r1[1], r2[1], r3[1]],
+        [r1[2], r2[2], r3[2]],
+    ];
+    (rotation_vector(&rotation), scale_by(column(2), scale))
+}
+
+// Solves `a x = b` by Gaussian elimination with partial pivoting, leaving `x`
+// in `b`. Returns false if `a` is singular.
+pub fn solve_in_place<Row: AsRef<[f64]> + AsMut<[f64]>>(a: &mut [Row], b: &mut [f64]) -> bool {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| {
+                let (x, y) = (a[i].as_ref()[col].abs(), a[j].as_ref()[col].abs());
+                x.partial_cmp(&y).unwrap()
+            })
+            .unwrap();
+        if a[pivot].as_ref()[col].abs() < 1e-12 {
+            return false;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let (upper, lower) = a.split_at_mut(col + 1);
+        let pivot_row = upper[col].as_ref();
+        let (b_upper, b_lower) = b.split_at_mut(col + 1);
+        for (row, value) in lower.iter_mut().zip(b_lower) {
+            let row = row.as_mut();
+            let factor = row[col] / pivot_row[col];
+            for (x, pivot_x) in row[col..n].iter_mut().zip(&pivot_row[col..n]) {
+                *x -= factor * pivot_x;
+            }
+            *value -= factor * b_upper[col];
+        }
+    }
+
+    for i in (0..n).rev() {
+        let row = a[i].as_ref();
+        let known: f64 = row[i + 1..n]
+            .iter()
+            .zip(&b[i + 1..n])
+            .map(|(a, x)| a * x)
+            .sum();
+        b[i] = (b[i] - known) / row[i];
+    }
+    true
+}
+
+pub fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    solve_in_place(&mut a, &mut b).then_some(b)
+}
+
+// Minimizes the sum of squares of `residuals(params)` by Levenberg-Marquardt with
+// a forward-difference Jacobian, starting from `params`
+pub fn least_squares(
+    mut params: Vec<f64>,
+    residuals: impl Fn(&[f64]) -> Vec<f64>,
+    max_iterations: usize,
+) -> Vec<f64> {
+    let cost = |r: &[f64]| r.iter().map(|r| r * r).sum::<f64>();
+    let n = params.len();
+    let mut current = residuals(&params);
+    let mut damping = INITIAL_DAMPING;
+
+    for _ in 0..max_iterations {
+        let mut jacobian = vec![vec![0.0; current.len()]; n];
+        for (j, column) in jacobian.iter_mut().enumerate() {
+            let mut shifted = params.clone();
+            shifted[j] += JACOBIAN_STEP;
+            for (value, (moved, residual)) in column
+                .iter_mut()
+                .zip(residuals(&shifted).iter().zip(&current))
+            {
+                *value = (moved - residual) / JACOBIAN_STEP;
+            }
+        }
+
+        // Normal equations J^T J x = -J^T r
+        let mut normal = vec![vec![0.0; n]; n];
+        let mut gradient = vec![0.0; n];
+        for (j, column) in jacobian.iter().enumerate() {
+            gradient[j] = -column.iter().zip(&current).map(|(a, r)| a * r).sum::<f64>();
+            for k in j..n {
+                let value: f64 = column.iter().zip(&jacobian[k]).map(|(a, b)| a * b).sum();
+                normal[j][k] = value;
+                normal[k][j] = value;
+            }
+        }
+
+        loop {
+            let mut damped = normal.clone();
+            for (j, row) in damped.iter_mut().enumerate() {
+                row[j] *= 1.0 + damping;
+            }
+            let mut step = gradient.clone();
+            if !solve_in_place(&mut damped, &mut step) {
+                damping *= 10.0;
+            } else {
+                let candidate: Vec<f64> = params.iter().zip(&step).map(|(p, s)| p + s).collect();
+                let moved = residuals(&candidate);
+                let (before, after) = (cost(&current), cost(&moved));
+                if after < before {
+                    params = candidate;
+                    current = moved;
+                    damping = (damping / 10.0).max(1e-12);
+                    if before - after <= MIN_IMPROVEMENT * before {
+                        return params;
+                    }
+                    break;
+                }
+                damping *= 10.0;
+            }
+            if damping > MAX_DAMPING {
+                return params;
+            }
+        }
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_vector_round_trip() {
+        let pi = std::f64::consts::PI;
+        for vector in [
+            [0.0, 0.0, 0.0],
+            [1e-8, 0.0, -2e-8],
+            [0.3, -0.2, 0.1],
+            [0.0, 3.0, 0.5],
+            [pi, 0.0, 0.0],
+            [0.0, -pi, pi].map(|v| v * std::f64::consts::FRAC_1_SQRT_2),
+        ] {
+            let matrix = rotation_matrix(vector);
+            let back = rotation_vector(&matrix);
+            let back_matrix = rotation_matrix(back);
+            for i in 0..3 {
+                for j in 0..3 {
+                    assert!(
+                        (back_matrix[i][j] - matrix[i][j]).abs() < 1e-6,
+                        "{:?} became {:?}",
+                        vector,
+                        back
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn solves_linear_systems() {
+        let x = solve(
+            [[2.0, 1.0, -1.0], [-3.0, -1.0, 2.0], [-2.0, 1.0, 2.0]],
+            [8.0, -11.0, -3.0],
+        )
+        .unwrap();
+        for (value, expected) in x.iter().zip([2.0, 3.0, -1.0]) {
+            assert!((value - expected).abs() < 1e-12);
+        }
+        assert!(solve([[1.0, 2.0], [2.0, 4.0]], [1.0, 2.0]).is_none());
+
+        let mut a = vec![vec![0.0, 1.0], vec![1.0, 0.0]];
+        let mut b = vec![5.0, 7.0];
+        assert!(solve_in_place(&mut a, &mut b));
+        assert_eq!(b, vec![7.0, 5.0]);
+    }
+
+    #[test]
+    fn fits_curve() {
+        // y = a exp(b x) through exact samples
+        let samples: Vec<(f64, f64)> = (0..10)
+            .map(|i| {
+                let x = i as f64 / 5.0;
+                (x, 2.5 * (-1.3 * x).exp())
+            })
+            .collect();
+        let params = least_squares(
+            vec![1.0, 0.0],
+            |p| {
+                samples
+                    .iter()
+                    .map(|(x, y)| p[0] * (p[1] * x).exp() - y)
+                    .collect()
+            },
+            100,
+        );
+        assert!((params[0] - 2.5).abs() < 1e-6, "{:?}", params);
+        assert!((params[1] + 1.3).abs() < 1e-6, "{:?}", params);
+    }
+}
@@ -0,0 +1,342 @@
+use protocol::BridgeEvent;
+use rppal::gpio::{InputPin, OutputPin};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::TryRecvError;
+
+use crate::bridge::Bridge;
+use crate::classify::arming;
+use crate::config::{FailsafeAction, GpioConfig};
+use crate::failsafe;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+// Consecutive reads the kill switch has to agree on, 60 ms at the poll interval
+const DEBOUNCE_READS: u32 = 3;
+
+// The pins the bridge drives and reads
+pub trait Gpio: Send {
+    fn set_led(&mut self, on: bool);
+    // True while the kill switch is engaged, before debouncing
+    fn kill_switch(&mut self) -> bool;
+}
+
+// GPIO header of the Raspberry Pi, pins are BCM numbers
+pub struct PiGpio {
+    led: OutputPin,
+    kill_switch: InputPin,
+    active_low: bool,
+}
+
+impl PiGpio {
+    pub fn open(config: &GpioConfig) -> Result<PiGpio, rppal::gpio::Error> {
+        let gpio = rppal::gpio::Gpio::new()?;
+        let led = gpio.get(config.led_pin)?.into_output_low();
+        let kill_switch = gpio.get(config.kill_switch_pin)?;
+        // An open switch reads as released
+        let kill_switch = match config.kill_switch_active_low {
+            true => kill_switch.into_input_pullup(),
+            false => kill_switch.into_input_pulldown(),
+        };
+        Ok(PiGpio {
+            led,
+            kill_switch,
+            active_low: config.kill_switch_active_low,
+        })
+    }
+}
+
+impl Gpio for PiGpio {
+    fn set_led(&mut self, on: bool) {
+        if on {
+            self.led.set_high();
+        } else {
+            self.led.set_low();
+        }
+    }
+
+    fn kill_switch(&mut self) -> bool {
+        self.kill_switch.is_low() == self.active_low
+    }
+}
+
+// What the status LED shows, the first one that applies wins
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    // Latched until the drone is armed again
+    Failsafe,
+    Armed,
+    Connected,
+    NoClient,
+}
+
+// Whether the LED is lit `elapsed` into the pattern for `status`
+fn led_on(status: Status, elapsed: Duration) -> bool {
+    let millis = elapsed.as_millis() % 1000;
+    match status {
+        // 5 Hz blink
+        Status::Failsafe => millis % 200 < 100,
+        Status::Armed => true,
+        // 1 Hz blink
+        Status::Connected => millis < 500,
+        // Short flash every second, the bridge is alive
+        Status::NoClient => millis < 100,
+    }
+}
+
+// A switch reading that only changes after `DEBOUNCE_READS` agreeing reads
+#[derive(Default)]
+struct Debounce {
+    engaged: bool,
+    disagreeing: u32,
+}
+
+impl Debounce {
+    // Returns true once when the switch becomes engaged
+    fn update(&mut self, reading: bool) -> bool {
+        if reading == self.engaged {
+            self.disagreeing = 0;
+            return false;
+        }
+        self.disagreeing += 1;
+        if self.disagreeing < DEBOUNCE_READS {
+            return false;
+        }
+        self.disagreeing = 0;
+        self.engaged = reading;
+        reading
+    }
+}
+
+// The status LED and kill switch, fed with what happens on the bridge
+pub struct Panel<G: Gpio> {
+    gpio: G,
+    kill_switch: Debounce,
+    armed: bool,
+    failsafe: bool,
+}
+
+impl<G: Gpio> Panel<G> {
+    pub fn new(gpio: G) -> Panel<G> {
+        Panel {
+            gpio,
+            kill_switch: Debounce::default(),
+            armed: false,
+            failsafe: false,
+        }
+    }
+
+    // Follows arming from the flight controller's answers
+    pub fn line(&mut self, line: &str) {
+        match arming(line) {
+            Some(true) => {
+                self.armed = true;
+                self.failsafe = false;
+            }
+            Some(false) => self.armed = false,
+            None => {}
+        }
+    }
+
+    pub fn failsafe(&mut self) {
+        self.failsafe = true;
+    }
+
+    pub fn status(&self, clients: usize) -> Status {
+        if self.failsafe {
+            Status::Failsafe
+        } else if self.armed {
+            Status::Armed
+        } else if clients > 0 {
+            Status::Connected
+        } else {
+            Status::NoClient
+        }
+    }
+
+    pub fn kill_switch_engaged(&self) -> bool {
+        self.kill_switch.engaged
+    }
+
+    // Updates the LED and reads the kill switch, returns true when it was just engaged
+    pub fn tick(&mut self, clients: usize, elapsed: Duration) -> bool {
+        self.gpio.set_led(led_on(self.status(clients), elapsed));
+        let reading = self.gpio.kill_switch();
+        self.kill_switch.update(reading)
+    }
+}
+
+// Polls the kill switch and drives the LED until the bridge exits
+pub async fn run<G: Gpio>(bridge: Arc<Bridge>, gpio: G) {
+    let mut panel = Panel::new(gpio);
+    let mut lines = bridge.serial.lines.subscribe();
+    let mut events = bridge.events.subscribe();
+    let started = Instant::now();
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        loop {
+            match lines.try_recv() {
+                Ok(line) => panel.line(&line),
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+        loop {
+            match events.try_recv() {
+                Ok(BridgeEvent::Failsafe { .. }) => panel.failsafe(),
+                Ok(_) | Err(TryRecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+
+        let clients = bridge.clients.load(Ordering::Relaxed);
+        let engaged = panel.tick(clients, started.elapsed());
+        // Held engaged, nothing may arm or spin up the motors again
+        bridge
+            .serial
+            .commands
+            .set_kill_switch(panel.kill_switch_engaged());
+        if engaged {
+            failsafe::trigger(
+                &bridge,
+                FailsafeAction::Abort,
+                "kill switch engaged".to_string(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::recorder::Recorder;
+    use crate::serial::{firmware_reads, test_link};
+    use std::collections::VecDeque;
+
+    // Records the LED and plays back kill switch readings, released once they run out
+    #[derive(Default)]
+    struct MockGpio {
+        led: Vec<bool>,
+        kill_switch: VecDeque<bool>,
+    }
+
+    impl Gpio for MockGpio {
+        fn set_led(&mut self, on: bool) {
+            self.led.push(on);
+        }
+
+        fn kill_switch(&mut self) -> bool {
+            self.kill_switch.pop_front().unwrap_or(false)
+        }
+    }
+
+    fn ticks(panel: &mut Panel<MockGpio>, clients: usize, count: usize) -> Vec<bool> {
+        (0..count)
+            .map(|i| panel.tick(clients, POLL_INTERVAL * i as u32))
+            .collect()
+    }
+
+    #[test]
+    fn led_patterns() {
+        let mut panel = Panel::new(MockGpio::default());
+        let second = 1000 / POLL_INTERVAL.as_millis() as usize;
+        let lit = |panel: &mut Panel<MockGpio>, clients| {
+            panel.gpio.led.clear();
+            ticks(panel, clients, second);
+            panel.gpio.led.iter().filter(|on| **on).count()
+        };
+
+        assert_eq!(panel.status(0), Status::NoClient);
+        assert_eq!(lit(&mut panel, 0), 5);
+        assert_eq!(panel.status(1), Status::Connected);
+        assert_eq!(lit(&mut panel, 1), 25);
+
+        panel.line("Waiting for command to arm...");
+        panel.line("Armed...\r");
+        assert_eq!(panel.status(0), Status::Armed);
+        assert_eq!(lit(&mut panel, 0), second);
+
+        panel.failsafe();
+        panel.line("Aborting...");
+        assert_eq!(panel.status(1), Status::Failsafe);
+        assert_eq!(lit(&mut panel, 1), 25);
+        assert_eq!(panel.gpio.led[..6], [true, true, true, true, true, false]);
+
+        // Cleared by arming again
+        panel.line("Armed...");
+        assert_eq!(panel.status(1), Status::Armed);
+    }
+
+    #[test]
+    fn kill_switch_is_debounced() {
+        let mut panel = Panel::new(MockGpio::default());
+        // Contact bounce, then held down and released
+        panel.gpio.kill_switch = [true, false, true, true, false]
+            .into_iter()
+            .chain([true; 10])
+            .collect();
+
+        let engaged = ticks(&mut panel, 1, 20);
+        assert_eq!(engaged.iter().filter(|e| **e).count(), 1);
+        assert!(engaged[7]);
+
+        // Engaging again after the release triggers again
+        panel.gpio.kill_switch = [true; 3].into_iter().collect();
+        assert_eq!(ticks(&mut panel, 1, 3), [false, false, true]);
+    }
+
+    #[tokio::test]
+    async fn kill_switch_aborts_the_firmware() {
+        let (serial, mut firmware) = tokio::io::duplex(1024);
+        let recorder = Recorder::disabled();
+        let bridge = Bridge::new(Config::default(), test_link(serial), recorder, None);
+        let gpio = MockGpio {
+            kill_switch: [true; 10].into_iter().collect(),
+            ..Default::default()
+        };
+        tokio::spawn(run(Arc::new(bridge), gpio));
+
+        assert_eq!(
+            firmware_reads(&mut firmware, 2).await,
+            ["rc->1000,1500,1500,1500", "command->abort"]
+        );
+    }
+
+    #[tokio::test]
+    async fn arming_fails_while_the_kill_switch_is_held() {
+        let (serial, mut firmware) = tokio::io::duplex(1024);
+        let recorder = Recorder::disabled();
+        let bridge = Arc::new(Bridge::new(
+            Config::default(),
+            test_link(serial),
+            recorder,
+            None,
+        ));
+        // Held for 20 s
+        let gpio = MockGpio {
+            kill_switch: [true; 1000].into_iter().collect(),
+            ..Default::default()
+        };
+        tokio::spawn(run(Arc::clone(&bridge), gpio));
+        assert_eq!(
+            firmware_reads(&mut firmware, 2).await,
+            ["rc->1000,1500,1500,1500", "command->abort"]
+        );
+
+        // The firmware ignored the abort if it was not armed yet
+        bridge
+            .serial
+            .commands
+            .push("command->enable_motors\n".to_string());
+        bridge
+            .serial
+            .commands
+            .push("rc->1800,1500,1500,1500\n".to_string());
+        bridge.serial.commands.push("command->arm\n".to_string());
+        bridge.serial.commands.push("command->reboot\n".to_string());
+        assert_eq!(firmware_reads(&mut firmware, 1).await, ["command->reboot"]);
+        assert_eq!(bridge.serial.commands.stats().dropped, 3);
+    }
+}
@@ -0,0 +1,495 @@
+use protocol::{FixType, GpsFix, GpsSource};
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::bridge::Bridge;
+use crate::config::GpsConfig;
+
+// u-blox M9N interface description
+const SYNC: [u8; 2] = [0xB5, 0x62];
+const NAV_PVT: (u8, u8) = (0x01, 0x07);
+const NAV_PVT_LEN: usize = 92;
+const ACK_ACK: (u8, u8) = (0x05, 0x01);
+const ACK_NAK: (u8, u8) = (0x05, 0x00);
+const CFG_VALSET: (u8, u8) = (0x06, 0x8A);
+// Anything longer is a lost sync, the receiver sends nothing that large here
+const MAX_PAYLOAD: usize = 1024;
+// Including the `$` and line ending
+const MAX_SENTENCE: usize = 82;
+
+// Configuration keys, written to RAM only so a bad setting is gone after a power cycle
+const LAYER_RAM: u8 = 0x01;
+const CFG_UART1OUTPROT_UBX: u32 = 0x1074_0001;
+const CFG_MSGOUT_UBX_NAV_PVT_UART1: u32 = 0x2091_0007;
+const CFG_RATE_MEAS: u32 = 0x3021_0001;
+const CFG_NAVSPG_DYNMODEL: u32 = 0x2011_0021;
+const DYNMODEL_AIRBORNE_1G: u8 = 6;
+
+const KNOTS_TO_M_S: f32 = 0.514_444;
+const RETRY: Duration = Duration::from_secs(5);
+// The fix is withdrawn from clients when no new one arrived for this long
+const STALE_AFTER: Duration = Duration::from_secs(3);
+// NMEA fixes are ignored this long after a NAV-PVT, the receiver sends both
+const UBX_PREFERRED_FOR: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Fix(GpsFix),
+    // Answer to a configuration message
+    Ack { class: u8, id: u8, accepted: bool },
+}
+
+// What the front of the buffer holds
+enum Step {
+    Incomplete,
+    // Bytes between messages
+    Skip(usize),
+    // A frame or sentence that failed its checksum, or a false start
+    Bad(usize),
+    Frame(usize, Option<Message>),
+}
+
+// Splits the receiver's byte stream into UBX frames and NMEA sentences, both
+// may be interleaved on the same UART
+#[derive(Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    nmea: Nmea,
+    pub errors: u64,
+}
+
+impl Decoder {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Message> {
+        self.buffer.extend_from_slice(bytes);
+        let mut messages = Vec::new();
+        let mut used = 0;
+        loop {
+            match step(&self.buffer[used..], &mut self.nmea) {
+                Step::Incomplete => break,
+                Step::Skip(skipped) => used += skipped,
+                Step::Bad(skipped) => {
+                    self.errors += 1;
+                    used += skipped;
+                }
+                Step::Frame(length, message) => {
+                    used += length;
+                    messages.extend(message);
+                }
+            }
+        }
+        self.buffer.drain(..used);
+        messages
+    }
+}
+
+fn step(buffer: &[u8], nmea: &mut Nmea) -> Step {
+    match buffer.iter().position(|b| *b == SYNC[0] || *b == b'$') {
+        Some(0) => {}
+        Some(start) => return Step::Skip(start),
+        None if buffer.is_empty() => return Step::Incomplete,
+        None => return Step::Skip(buffer.len()),
+    }
+
+    if buffer[0] == b'$' {
+        let Some(end) = buffer.iter().position(|b| *b == b'\n') else {
+            return match buffer.len() > MAX_SENTENCE {
+                true => Step::Bad(1),
+                false => Step::Incomplete,
+            };
+        };
+        let sentence = std::str::from_utf8(&buffer[..end])
+            .ok()
+            .filter(|_| end < MAX_SENTENCE)
+            .and_then(|sentence| checked(sentence.trim_end()));
+        return match sentence {
+            Some(sentence) => Step::Frame(end + 1, nmea.sentence(sentence).map(Message::Fix)),
+            None => Step::Bad(1),
+        };
+    }
+
+    if buffer.len() < 6 {
+        return match buffer.len() < 2 || buffer[1] == SYNC[1] {
+            true => Step::Incomplete,
+            false => Step::Skip(1),
+        };
+    }
+    if buffer[1] != SYNC[1] {
+        return Step::Skip(1);
+    }
+    let length = u16::from_le_bytes([buffer[4], buffer[5]]) as usize;
+    if length > MAX_PAYLOAD {
+        return Step::Bad(1);
+    }
+    if buffer.len() < length + 8 {
+        return Step::Incomplete;
+    }
+    if checksum(&buffer[2..6 + length]) != [buffer[6 + length], buffer[7 + length]] {
+        return Step::Bad(1);
+    }
+    let message = ubx((buffer[2], buffer[3]), &buffer[6..6 + length]);
+    Step::Frame(length + 8, message)
+}
+
+// 8-bit Fletcher over class, id, length and payload
+fn checksum(bytes: &[u8]) -> [u8; 2] {
+    let (mut a, mut b) = (0u8, 0u8);
+    for byte in bytes {
+        a = a.wrapping_add(*byte);
+        b = b.wrapping_add(a);
+    }
+    [a, b]
+}
+
+pub fn frame(message: (u8, u8), payload: &[u8]) -> Vec<u8> {
+    let mut frame = SYNC.to_vec();
+    frame.extend_from_slice(&[message.0, message.1]);
+    frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    frame.extend_from_slice(payload);
+    let checksum = checksum(&frame[2..]);
+    frame.extend_from_slice(&checksum);
+    frame
+}
+
+fn ubx(message: (u8, u8), payload: &[u8]) -> Option<Message> {
+    match message {
+        NAV_PVT => nav_pvt(payload).map(Message::Fix),
+        ACK_ACK | ACK_NAK if payload.len() >= 2 => Some(Message::Ack {
+            class: payload[0],
+            id: payload[1],
+            accepted: message == ACK_ACK,
+        }),
+        _ => None,
+    }
+}
+
+fn nav_pvt(payload: &[u8]) -> Option<GpsFix> {
+    if payload.len() < NAV_PVT_LEN {
+        return None;
+    }
+    let i32_at =
+        |offset: usize| i32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap());
+    let u32_at =
+        |offset: usize| u32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap());
+    let millimetres = |value: i32| value as f32 / 1000.0;
+    // Positions are only valid with gnssFixOK set
+    let fix_ok = payload[21] & 0x01 != 0;
+    let fix = match payload[20] {
+        1 => FixType::DeadReckoning,
+        2 if fix_ok => FixType::Fix2d,
+        3 if fix_ok => FixType::Fix3d,
+        4 if fix_ok => FixType::GnssDeadReckoning,
+        5 => FixType::TimeOnly,
+        _ => FixType::NoFix,
+    };
+    Some(GpsFix {
+        fix,
+        source: GpsSource::Ubx,
+        satellites: payload[23],
+        lon: i32_at(24) as f64 * 1e-7,
+        lat: i32_at(28) as f64 * 1e-7,
+        alt_m: millimetres(i32_at(36)),
+        speed_m_s: millimetres(i32_at(60)),
+        course_deg: i32_at(64) as f32 * 1e-5,
+        velocity_ned_m_s: Some([
+            millimetres(i32_at(48)),
+            millimetres(i32_at(52)),
+            millimetres(i32_at(56)),
+        ]),
+        h_acc_m: Some(u32_at(40) as f32 / 1000.0),
+        v_acc_m: Some(u32_at(44) as f32 / 1000.0),
+        speed_acc_m_s: Some(u32_at(68) as f32 / 1000.0),
+    })
+}
+
+// The sentence between `$` and `*` when its checksum matches
+fn checked(sentence: &str) -> Option<&str> {
+    let (body, checksum) = sentence.strip_prefix('$')?.split_once('*')?;
+    let expected = u8::from_str_radix(checksum, 16).ok()?;
+    let actual = body.bytes().fold(0, |checksum, byte| checksum ^ byte);
+    (actual == expected).then_some(body)
+}
+
+// `4717.11399` and `N` as 47.285233 degrees
+fn coordinate(value: &str, hemisphere: &str) -> Option<f64> {
+    let value: f64 = value.parse().ok()?;
+    let degrees = (value / 100.0).trunc();
+    let degrees = degrees + (value - degrees * 100.0) / 60.0;
+    match hemisphere {
+        "S" | "W" => Some(-degrees),
+        _ => Some(degrees),
+    }
+}
+
+// Fix assembled from NMEA: GGA carries the position and is sent once per
+// measurement, RMC the velocity and GSA whether the fix is 2D or 3D
+#[derive(Default)]
+struct Nmea {
+    speed_m_s: f32,
+    course_deg: f32,
+    mode_2d: bool,
+}
+
+impl Nmea {
+    // Returns a fix for every GGA sentence
+    fn sentence(&mut self, body: &str) -> Option<GpsFix> {
+        let fields: Vec<&str> = body.split(',').collect();
+        let field = |index: usize| fields.get(index).copied().unwrap_or("");
+        // After the two letter talker id: GP, GN, GL...
+        match fields[0].get(2..) {
+            Some("GGA") => {
+                let fix = match field(6).parse::<u8>().ok()? {
+                    0 => FixType::NoFix,
+                    6 => FixType::DeadReckoning,
+                    _ if self.mode_2d => FixType::Fix2d,
+                    _ => FixType::Fix3d,
+                };
+                Some(GpsFix {
+                    fix,
+                    source: GpsSource::Nmea,
+                    satellites: field(7).parse().unwrap_or(0),
+                    lat: coordinate(field(2), field(3)).unwrap_or(0.0),
+                    lon: coordinate(field(4), field(5)).unwrap_or(0.0),
+                    alt_m: field(9).parse().unwrap_or(0.0),
+                    speed_m_s: self.speed_m_s,
+                    course_deg: self.course_deg,
+                    velocity_ned_m_s: None,
+                    h_acc_m: None,
+                    v_acc_m: None,
+                    speed_acc_m_s: None,
+                })
+            }
+            Some("RMC") => {
+                let valid = field(2) == "A";
+                let knots: f32 = field(7).parse().unwrap_or(0.0);
+                self.speed_m_s = if valid { knots * KNOTS_TO_M_S } else { 0.0 };
+                self.course_deg = field(8).parse().unwrap_or(0.0);
+                None
+            }
+            Some("GSA") => {
+                self.mode_2d = field(2) == "2";
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+// UBX-CFG-VALSET enabling NAV-PVT on UART1 every measurement, at `rate_hz`,
+// with the dynamic model for an airborne platform
+pub fn configuration(rate_hz: f32) -> Vec<u8> {
+    let measurement_ms = (1000.0 / rate_hz).round() as u16;
+    // Version 0, layers, two reserved bytes, then keys and values
+    let mut payload = vec![0x00, LAYER_RAM, 0x00, 0x00];
+    payload.extend_from_slice(&CFG_UART1OUTPROT_UBX.to_le_bytes());
+    payload.push(1);
+    payload.extend_from_slice(&CFG_MSGOUT_UBX_NAV_PVT_UART1.to_le_bytes());
+    payload.push(1);
+    payload.extend_from_slice(&CFG_RATE_MEAS.to_le_bytes());
+    payload.extend_from_slice(&measurement_ms.to_le_bytes());
+    payload.extend_from_slice(&CFG_NAVSPG_DYNMODEL.to_le_bytes());
+    payload.push(DYNMODEL_AIRBORNE_1G);
+    frame(CFG_VALSET, &payload)
+}
+
+// Reads the receiver until the bridge exits, reopening the port whenever it fails
+pub async fn run(bridge: Arc<Bridge>) {
+    let config = bridge.config.gps.clone();
+    loop {
+        match tokio_serial::new(&config.path, config.baud).open_native_async() {
+            Ok(port) => {
+                println!("Reading GPS receiver on {}", config.path);
+                let e = read_port(&bridge, port, &config).await;
+                eprintln!("GPS receiver {} lost: {}", config.path, e);
+            }
+            Err(e) => eprintln!("Failed to open GPS receiver {}: {}", config.path, e),
+        }
+        *bridge.gps.lock().unwrap() = None;
+        tokio::time::sleep(RETRY).await;
+    }
+}
+
+async fn read_port(bridge: &Bridge, mut port: SerialStream, config: &GpsConfig) -> io::Error {
+    if config.configure {
+        if let Err(e) = port.write_all(&configuration(config.rate_hz)).await {
+            return e;
+        }
+    }
+
+    let mut decoder = Decoder::default();
+    let mut buffer = [0u8; 512];
+    let mut last_fix = Instant::now();
+    let mut last_ubx: Option<Instant> = None;
+    loop {
+        let messages = match tokio::time::timeout(STALE_AFTER, port.read(&mut buffer)).await {
+            Ok(Ok(0)) => return io::Error::new(io::ErrorKind::UnexpectedEof, "end of stream"),
+            Ok(Ok(read)) => decoder.push(&buffer[..read]),
+            Ok(Err(e)) => return e,
+            Err(_) => Vec::new(),
+        };
+        for message in messages {
+            match message {
+                Message::Fix(fix) => {
+                    let ubx = fix.source == GpsSource::Ubx;
+                    if !ubx && last_ubx.is_some_and(|at| at.elapsed() < UBX_PREFERRED_FOR) {
+                        continue;
+                    }
+                    if ubx {
+                        last_ubx = Some(Instant::now());
+                    }
+                    last_fix = Instant::now();
+                    *bridge.gps.lock().unwrap() = Some(fix);
+                }
+                Message::Ack {
+                    class,
+                    id,
+                    accepted,
+                } if (class, id) == CFG_VALSET => match accepted {
+                    true => println!("GPS receiver configured"),
+                    false => eprintln!("GPS receiver rejected the configuration"),
+                },
+                Message::Ack { .. } => {}
+            }
+        }
+        if last_fix.elapsed() >= STALE_AFTER && bridge.gps.lock().unwrap().take().is_some() {
+            eprintln!(
+                "No GPS fix from {} for {} s ({} bad frames)",
+                config.path,
+                STALE_AFTER.as_secs(),
+                decoder.errors
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NAV-PVT of a 3D fix with 12 satellites, moving north-west at about 1 m/s
+    const NAV_PVT_FRAME: [u8; 100] = [
+        0xb5, 0x62, 0x01, 0x07, 0x5c, 0x00, 0xc0, 0x26, 0x11, 0x17, 0xe8, 0x07, 0x05, 0x12, 0x0b,
+        0x1e, 0x00, 0x37, 0x19, 0x00, 0x00, 0x00, 0x20, 0xd1, 0xff, 0xff, 0x03, 0x01, 0xea, 0x0c,
+        0xaa, 0xf4, 0x1a, 0x05, 0x6c, 0x27, 0x2f, 0x1c, 0x10, 0x5b, 0x08, 0x00, 0x90, 0x9f, 0x07,
+        0x00, 0xdc, 0x05, 0x00, 0x00, 0xfc, 0x08, 0x00, 0x00, 0xe8, 0x03, 0x00, 0x00, 0x0c, 0xfe,
+        0xff, 0xff, 0x14, 0x00, 0x00, 0x00, 0x5e, 0x04, 0x00, 0x00, 0x98, 0x02, 0x33, 0x00, 0x78,
+        0x00, 0x00, 0x00, 0xc8, 0xaf, 0x00, 0x00, 0x9e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc6, 0x3d,
+    ];
+    // ACK-ACK of a CFG-VALSET
+    const ACK_FRAME: [u8; 10] = [0xb5, 0x62, 0x05, 0x01, 0x02, 0x00, 0x06, 0x8a, 0x98, 0xc1];
+    // Default NMEA output, the examples of the u-blox interface description
+    const NMEA: &str = "\
+$GPGSA,A,3,23,29,07,08,09,18,26,28,,,,,1.94,1.18,1.54*0D\r
+$GPRMC,083559.00,A,4717.11437,N,00833.91522,E,0.004,77.52,091202,,,A*57\r
+$GPGGA,092725.00,4717.11399,N,00833.91590,E,1,08,1.01,499.6,M,48.0,M,,*5B\r
+";
+    // A receiver still searching for satellites
+    const NMEA_NO_FIX: &str = "$GNRMC,,V,,,,,,,,,,N*4D\r\n$GNGGA,,,,,,0,00,99.99,,,,,,*56\r\n";
+
+    fn fixes(messages: Vec<Message>) -> Vec<GpsFix> {
+        messages
+            .into_iter()
+            .filter_map(|message| match message {
+                Message::Fix(fix) => Some(fix),
+                Message::Ack { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_nav_pvt() {
+        let mut decoder = Decoder::default();
+        // Split across reads
+        assert!(decoder.push(&NAV_PVT_FRAME[..37]).is_empty());
+        let fix = fixes(decoder.push(&NAV_PVT_FRAME[37..])).remove(0);
+
+        assert_eq!(
+            (fix.fix, fix.source, fix.satellites),
+            (FixType::Fix3d, GpsSource::Ubx, 12)
+        );
+        assert!((fix.lat - 47.285_233_2).abs() < 1e-9);
+        assert!((fix.lon - 8.565_265).abs() < 1e-9);
+        assert_eq!(fix.alt_m, 499.6);
+        assert_eq!(fix.velocity_ned_m_s, Some([1.0, -0.5, 0.02]));
+        assert_eq!((fix.speed_m_s, fix.course_deg), (1.118, 33.43));
+        assert_eq!((fix.h_acc_m, fix.v_acc_m), (Some(1.5), Some(2.3)));
+        assert_eq!(fix.speed_acc_m_s, Some(0.12));
+        assert_eq!(decoder.errors, 0);
+    }
+
+    #[test]
+    fn decodes_nmea() {
+        let mut decoder = Decoder::default();
+        let fix = fixes(decoder.push(NMEA.as_bytes())).remove(0);
+
+        assert_eq!(
+            (fix.fix, fix.source, fix.satellites),
+            (FixType::Fix3d, GpsSource::Nmea, 8)
+        );
+        assert!((fix.lat - (47.0 + 17.11399 / 60.0)).abs() < 1e-9);
+        assert!((fix.lon - (8.0 + 33.91590 / 60.0)).abs() < 1e-9);
+        assert_eq!(fix.alt_m, 499.6);
+        assert_eq!(
+            (fix.speed_m_s, fix.course_deg),
+            (0.004 * KNOTS_TO_M_S, 77.52)
+        );
+        assert_eq!((fix.velocity_ned_m_s, fix.h_acc_m), (None, None));
+
+        let fix = fixes(decoder.push(NMEA_NO_FIX.as_bytes())).remove(0);
+        assert_eq!(
+            (fix.fix, fix.satellites, fix.speed_m_s),
+            (FixType::NoFix, 0, 0.0)
+        );
+        assert_eq!(decoder.errors, 0);
+    }
+
+    #[test]
+    fn resyncs_after_noise() {
+        let mut corrupted = NAV_PVT_FRAME;
+        corrupted[40] ^= 0x01;
+        let stream: Vec<u8> = [
+            &b"\x00\xff\xb5"[..],
+            &corrupted,
+            NMEA.replace("*5B", "*5C").as_bytes(),
+            &ACK_FRAME,
+            &NAV_PVT_FRAME,
+        ]
+        .concat();
+
+        let mut decoder = Decoder::default();
+        let messages: Vec<Message> = stream
+            .chunks(7)
+            .flat_map(|chunk| decoder.push(chunk))
+            .collect();
+
+        // The GSA and RMC lines pass, the GGA with a bad checksum does not
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0],
+            Message::Ack {
+                class: 0x06,
+                id: 0x8A,
+                accepted: true
+            }
+        );
+        assert_eq!(fixes(messages)[0].source, GpsSource::Ubx);
+        assert!(decoder.errors >= 2);
+    }
+
+    #[test]
+    fn configuration_sets_rate() {
+        let frame = configuration(5.0);
+        assert_eq!(&frame[..6], [0xb5, 0x62, 0x06, 0x8a, 25, 0]);
+        // 200 ms between measurements
+        let rate = 6 + 4 + 5 + 5;
+        assert_eq!(&frame[rate..rate + 6], [0x01, 0x00, 0x21, 0x30, 200, 0]);
+
+        // A well-formed frame the decoder skips
+        let mut decoder = Decoder::default();
+        assert!(decoder.push(&frame).is_empty());
+        assert_eq!((decoder.errors, decoder.buffer.len()), (0, 0));
+    }
+}
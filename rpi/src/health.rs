@@ -0,0 +1,184 @@
+use protocol::{CompanionHealth, WifiSignal};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::bridge::Bridge;
+
+const CPU_TEMP: &str = "/sys/class/thermal/thermal_zone0/temp";
+const LOAD_AVERAGE: &str = "/proc/loadavg";
+const MEMORY: &str = "/proc/meminfo";
+// Exposed by the Raspberry Pi firmware driver, what `vcgencmd get_throttled` reads
+const THROTTLED: &str = "/sys/devices/platform/soc/soc:firmware/get_throttled";
+const WIRELESS: &str = "/proc/net/wireless";
+
+// Where the bridge reads the health of the Pi from
+pub trait HealthSource: Send {
+    // Contents of a procfs or sysfs file such as `/proc/loadavg`
+    fn read(&self, path: &str) -> io::Result<String>;
+}
+
+// Reads the files under `root`, `/` on the Pi
+pub struct SystemFiles {
+    root: PathBuf,
+}
+
+impl SystemFiles {
+    pub fn new(root: PathBuf) -> SystemFiles {
+        SystemFiles { root }
+    }
+}
+
+impl HealthSource for SystemFiles {
+    fn read(&self, path: &str) -> io::Result<String> {
+        fs::read_to_string(self.root.join(path.trim_start_matches('/')))
+    }
+}
+
+// Reads every source, the ones that are missing or do not parse are left out
+pub fn sample(source: &dyn HealthSource) -> CompanionHealth {
+    let read = |path| source.read(path).ok();
+    let memory = read(MEMORY);
+    let meminfo = |key| memory.as_deref().and_then(|text| parse_meminfo(text, key));
+    CompanionHealth {
+        cpu_temp_c: read(CPU_TEMP)
+            .and_then(|text| text.trim().parse::<f32>().ok())
+            .map(|millidegrees| millidegrees / 1000.0),
+        load: read(LOAD_AVERAGE).and_then(|text| parse_load(&text)),
+        mem_total_kb: meminfo("MemTotal"),
+        mem_available_kb: meminfo("MemAvailable"),
+        throttled: read(THROTTLED).and_then(|text| parse_throttled(&text)),
+        wifi: read(WIRELESS).and_then(|text| parse_wireless(&text)),
+    }
+}
+
+// `0.52 0.58 0.59 1/234 5678`
+fn parse_load(text: &str) -> Option<[f32; 3]> {
+    let mut fields = text.split_whitespace().map(|field| field.parse().ok());
+    Some([fields.next()??, fields.next()??, fields.next()??])
+}
+
+// `MemAvailable:    3589324 kB`
+fn parse_meminfo(text: &str, key: &str) -> Option<u64> {
+    text.lines().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix(':')?;
+        value.split_whitespace().next()?.parse().ok()
+    })
+}
+
+// Hex, with `0x` or `throttled=0x` in front when it comes from `vcgencmd`
+fn parse_throttled(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let text = text.strip_prefix("throttled=").unwrap_or(text);
+    let text = text.strip_prefix("0x").unwrap_or(text);
+    u32::from_str_radix(text, 16).ok()
+}
+
+// First interface after the two header lines:
+// ` wlan0: 0000   70.  -40.  -256        0      0      0      0      0        0`
+fn parse_wireless(text: &str) -> Option<WifiSignal> {
+    let line = text.lines().nth(2)?;
+    let (interface, values) = line.split_once(':')?;
+    let mut values = values
+        .split_whitespace()
+        .skip(1)
+        .map(|value| value.trim_end_matches('.').parse::<f32>().ok());
+    Some(WifiSignal {
+        interface: interface.trim().to_string(),
+        link_quality: values.next()??,
+        signal_dbm: values.next()??,
+    })
+}
+
+// Samples the health on its own thread at `rate_hz`, reading sysfs never
+// blocks the bridge
+pub fn run(bridge: Arc<Bridge>, source: Box<dyn HealthSource>, rate_hz: f32) {
+    let interval = Duration::from_secs_f32(1.0 / rate_hz);
+    thread::spawn(move || loop {
+        let started = Instant::now();
+        *bridge.companion.lock().unwrap() = Some(sample(source.as_ref()));
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIRELESS_FIXTURE: &str = "\
+Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+ wlan0: 0000   58.  -52.  -256        0      0      0      3      0        0
+";
+
+    const MEMINFO_FIXTURE: &str = "\
+MemTotal:        3880092 kB
+MemFree:         2709604 kB
+MemAvailable:    3306348 kB
+Buffers:           58312 kB
+";
+
+    // Fixture files laid out like the root of a Pi
+    fn fixtures(name: &str, files: &[(&str, &str)]) -> SystemFiles {
+        let root = std::env::temp_dir().join(format!("rpi-health-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path.trim_start_matches('/'));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        SystemFiles::new(root)
+    }
+
+    #[test]
+    fn samples_a_pi() {
+        let source = fixtures(
+            "pi",
+            &[
+                (CPU_TEMP, "48312\n"),
+                (LOAD_AVERAGE, "0.52 0.58 0.59 1/234 5678\n"),
+                (MEMORY, MEMINFO_FIXTURE),
+                (THROTTLED, "50005\n"),
+                (WIRELESS, WIRELESS_FIXTURE),
+            ],
+        );
+
+        let health = sample(&source);
+
+        assert_eq!(health.cpu_temp_c, Some(48.312));
+        assert_eq!(health.load, Some([0.52, 0.58, 0.59]));
+        assert_eq!(health.mem_total_kb, Some(3880092));
+        assert_eq!(health.mem_available_kb, Some(3306348));
+        assert_eq!(health.throttled, Some(0x50005));
+        assert_eq!(
+            health.wifi,
+            Some(WifiSignal {
+                interface: "wlan0".to_string(),
+                link_quality: 58.0,
+                signal_dbm: -52.0,
+            })
+        );
+    }
+
+    #[test]
+    fn missing_and_garbled_sources() {
+        // A laptop: no firmware driver, no Wi-Fi interface, odd temperature
+        let source = fixtures(
+            "laptop",
+            &[
+                (CPU_TEMP, "n/a\n"),
+                (LOAD_AVERAGE, "0.10 0.20\n"),
+                (
+                    WIRELESS,
+                    &WIRELESS_FIXTURE[..WIRELESS_FIXTURE.find(" wlan0").unwrap()],
+                ),
+            ],
+        );
+
+        assert_eq!(sample(&source), CompanionHealth::default());
+        assert_eq!(parse_throttled("throttled=0x0"), Some(0));
+    }
+}
@@ -1,146 +1,253 @@
-use futures_util::{SinkExt, StreamExt};
-use serde_json::json;
-use std::sync::atomic::{AtomicBool, Ordering};
+mod aruco;
+mod auth;
+mod bridge;
+mod calibration;
+mod camera;
+mod classify;
+mod client;
+mod config;
+mod control;
+mod delivery;
+mod emulator;
+mod export;
+mod failsafe;
+mod foxglove;
+mod geometry;
+mod gpio;
+mod gps;
+mod health;
+mod mavlink;
+mod mcap;
+mod metrics;
+mod queue;
+mod recorder;
+mod replay;
+mod serial;
+mod shutdown;
+mod tls;
+
+use aruco::{Detector, Dictionary, PoseEstimator};
+use bridge::Bridge;
+use calibration::Calibration;
+use camera::ImageFiles;
+use clap::Parser;
+use config::{Cli, CliCommand, Config};
+use recorder::Recorder;
+use serial::SerialLink;
 use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc;
-use tokio_serial::SerialPortBuilderExt;
-use tokio_tungstenite::accept_async;
-use tokio_tungstenite::tungstenite::Message;
-
-const IP: &str = "0.0.0.0";
-const PORT: u16 = 8765;
+use tokio::net::{TcpListener, UdpSocket};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("Starting WebSocket server at ws://{}:{}", IP, PORT);
-
-    let listener = TcpListener::bind(format!("{}:{}", IP, PORT)).await?;
-
-    while let Ok((stream, _)) = listener.accept().await {
-        tokio::spawn(handle_connection(stream));
+    let cli = Cli::parse();
+    if let Some(CliCommand::Calibrate(args)) = &cli.command {
+        if let Err(e) = calibration::run(args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(CliCommand::Export(args)) = &cli.command {
+        if let Err(e) = export::run(args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
     }
 
-    Ok(())
-}
-
-async fn handle_connection(stream: TcpStream) {
-    let ws_stream = match accept_async(stream).await {
-        Ok(ws) => ws,
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("Failed to accept WebSocket connection: {}", e);
-            return;
+            eprintln!("{}", e);
+            std::process::exit(2);
         }
     };
 
-    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+    if let Err(e) = std::fs::create_dir_all(&config.log.dir) {
+        eprintln!(
+            "Failed to create log directory {}: {}",
+            config.log.dir.display(),
+            e
+        );
+        std::process::exit(2);
+    }
 
-    let tty_path = "/dev/ttyS0";
-    let serial = match tokio_serial::new(tty_path, 1_000_000).open_native_async() {
-        Ok(s) => s,
+    // A replay is not recorded again
+    let recorder = match config.replay.file {
+        Some(_) => Ok(Recorder::disabled()),
+        None => Recorder::start(&config.log),
+    };
+    let recorder = match recorder {
+        Ok(recorder) => recorder,
         Err(e) => {
-            eprintln!("Failed to open serial port: {}", e);
-            return;
+            eprintln!("Failed to start flight recorder: {}", e);
+            std::process::exit(2);
         }
     };
 
-    let (serial_reader, mut serial_writer) = tokio::io::split(serial);
-    let mut serial_reader = BufReader::new(serial_reader);
-
-    // Channel for sending data from serial reader to WebSocket sender
-    let (tx, mut rx) = mpsc::channel::<String>(100);
-
-    // Shared state for signaling tasks to stop
-    let stop_signal = Arc::new(AtomicBool::new(false));
-
-    // Spawn a task to read from serial and send to channel
-    let serial_task = {
-        let stop_signal = Arc::clone(&stop_signal);
-        tokio::spawn(async move {
-            let mut line = String::new();
-            while !stop_signal.load(Ordering::Relaxed) {
-                tokio::select! {
-                    result = serial_reader.read_line(&mut line) => {
-                        match result {
-                            Ok(0) => break, // EOF
-                            Ok(_) => {
-                                if tx.send(line.clone()).await.is_err() {
-                                    break;
-                                }
-                                line.clear();
-                            }
-                            Err(e) => {
-                                eprintln!("Error reading from serial: {}", e);
-                                continue;
-                            }
-                        }
-                    }
-                    _ = tokio::time::sleep(tokio::time::Duration::from_millis(100)) => {
-                        // This branch ensures we periodically check the stop_signal
-                    }
-                }
+    let (serial, playback) = match &config.replay.file {
+        Some(path) => match replay::open(&config.replay, path) {
+            Ok((serial, playback)) => (serial, Some(playback)),
+            Err(e) => {
+                eprintln!("Failed to replay {}: {}", path.display(), e);
+                std::process::exit(1);
             }
-            println!("Stopping serial read task");
-        })
+        },
+        None if config.emulator.enabled => {
+            (emulator::open(&config.emulator, recorder.clone()), None)
+        }
+        None => (SerialLink::open(&config.serial, recorder.clone()), None),
     };
 
-    // Spawn a task to receive from channel and send to WebSocket
-    let ws_task = tokio::spawn(async move {
-        while let Some(line) = rx.recv().await {
-            let aruco_ids = vec![1, 2, 3, 4, 5]; // TODO: Replace with actual Aruco ID detection
-            let combined_data = json!({
-                "aruco_ids": aruco_ids,
-                "serial_data": line.trim(),
-            });
-            if let Err(e) = ws_sender
-                .send(Message::Text(combined_data.to_string()))
-                .await
-            {
-                eprintln!("WebSocket send error: {}", e);
-                break;
-            }
-        }
-    });
-
-    // Handle WebSocket messages and write to serial
-    let mut latest_command = String::new();
-    while let Some(msg) = ws_receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                // Update the latest command
-                latest_command = text;
+    let tls = match (&config.server.cert, &config.server.key) {
+        (Some(cert), Some(key)) => match tls::acceptor(cert, key) {
+            Ok(acceptor) => Some(acceptor),
+            Err(e) => {
+                eprintln!("Failed to set up TLS: {}", e);
+                std::process::exit(2);
             }
-            Ok(Message::Close(_)) => {
-                println!("WebSocket connection closed");
-                break;
+        },
+        _ => None,
+    };
+    let scheme = if tls.is_some() { "wss" } else { "ws" };
+    println!(
+        "Starting WebSocket server at {}://{}",
+        scheme,
+        config.bind_address()
+    );
+    if !config.auth.enabled() {
+        println!("No auth token configured, any client on the network can send commands");
+    }
+
+    let listener = TcpListener::bind(config.bind_address()).await?;
+    let bridge = Arc::new(Bridge::new(config, serial, recorder.clone(), playback));
+    tokio::spawn(failsafe::run(Arc::clone(&bridge)));
+    tokio::spawn(delivery::run(Arc::clone(&bridge)));
+    tokio::spawn(serial::report_link(Arc::clone(&bridge)));
+
+    if bridge.config.metrics.enabled {
+        let address = bridge.config.metrics_address();
+        println!("Serving status and metrics at http://{}", address);
+        let metrics_listener = TcpListener::bind(&address).await?;
+        tokio::spawn(metrics::run(Arc::clone(&bridge), metrics_listener));
+    }
+
+    if bridge.config.foxglove.enabled {
+        let address = bridge.config.foxglove_address();
+        println!("Serving Foxglove WebSocket protocol at ws://{}", address);
+        let foxglove_listener = TcpListener::bind(&address).await?;
+        tokio::spawn(foxglove::run(Arc::clone(&bridge), foxglove_listener));
+    }
+
+    if bridge.config.mavlink.enabled {
+        let address = bridge.config.mavlink_address();
+        println!("Serving MAVLink at udp://{}", address);
+        let socket = UdpSocket::bind(&address).await?;
+        tokio::spawn(mavlink::run(Arc::clone(&bridge), socket));
+    }
+
+    if bridge.config.health.enabled {
+        let health = &bridge.config.health;
+        let source = health::SystemFiles::new(health.root.clone());
+        health::run(Arc::clone(&bridge), Box::new(source), health.rate_hz);
+    }
+
+    if bridge.config.gpio.enabled {
+        let config = &bridge.config.gpio;
+        match gpio::PiGpio::open(config) {
+            Ok(pins) => {
+                println!(
+                    "Status LED on GPIO {}, kill switch on GPIO {}",
+                    config.led_pin, config.kill_switch_pin
+                );
+                tokio::spawn(gpio::run(Arc::clone(&bridge), pins));
             }
             Err(e) => {
-                eprintln!("WebSocket receive error: {}", e);
-                break;
+                eprintln!("Failed to open GPIO: {}", e);
+                std::process::exit(2);
             }
-            _ => {}
         }
+    }
+
+    if bridge.config.gps.enabled {
+        tokio::spawn(gps::run(Arc::clone(&bridge)));
+    }
 
-        // Process the latest command immediately
-        if !latest_command.is_empty() {
-            println!("Processing command: {}", latest_command);
-            if let Err(e) = serial_writer.write_all(latest_command.as_bytes()).await {
-                eprintln!("Serial write error: {}", e);
-                break;
+    if bridge.config.aruco.enabled {
+        let aruco = &bridge.config.aruco;
+        let dictionary = match &aruco.dictionary {
+            Some(path) => Dictionary::load(path),
+            None => Ok(Dictionary::aruco_original()),
+        };
+        let dictionary = match dictionary {
+            Ok(dictionary) => dictionary,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        };
+        let source = match ImageFiles::open(&aruco.images) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!(
+                    "Failed to open camera images {}: {}",
+                    aruco.images.display(),
+                    e
+                );
+                std::process::exit(2);
             }
-            // Clear the command after processing
-            latest_command.clear();
+        };
+        println!(
+            "Detecting {} markers ({} ids) at {} Hz",
+            dictionary.name,
+            dictionary.len(),
+            aruco.rate_hz
+        );
+        let mut detector = Detector::new(dictionary);
+        if let Some(path) = &aruco.calibration {
+            let calibration = match Calibration::load(path) {
+                Ok(calibration) => calibration,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(2);
+                }
+            };
+            println!(
+                "Estimating marker poses, markers are {} m wide",
+                aruco.marker_size_m
+            );
+            detector = detector.with_poses(PoseEstimator::new(calibration, aruco.marker_size_m));
         }
+        aruco::run(
+            Arc::clone(&bridge),
+            detector,
+            Box::new(source),
+            aruco.rate_hz,
+        );
     }
 
-    // Signal tasks to stop
-    stop_signal.store(true, Ordering::Relaxed);
+    let signal = shutdown::wait_for_signal();
+    tokio::pin!(signal);
+    let reason = loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(client::handle_connection(stream, Arc::clone(&bridge), tls.clone()));
+                }
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {}", e);
+                    break "WebSocket server failed";
+                }
+            },
+            reason = &mut signal => break reason,
+        }
+    };
 
-    // Wait for tasks to finish
-    let _ = serial_task.await;
-    let _ = ws_task.await;
+    // No new clients while the safe sequence is written
+    drop(listener);
+    shutdown::run(&bridge, reason).await;
 
-    println!("Connection handler finished");
-}
\ No newline at end of file
+    // Close the flight log with a session stop marker
+    tokio::task::spawn_blocking(move || recorder.stop()).await?;
+    Ok(())
+}
@@ -0,0 +1,858 @@
+use protocol::{Command, SerialData};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::bridge::Bridge;
+use crate::classify::arming;
+use crate::config::MavlinkConfig;
+use crate::serial::LinkState;
+
+const STX_V1: u8 = 0xFE;
+const STX_V2: u8 = 0xFD;
+// Start byte to message id, then the payload and a CRC
+const HEADER_V1: usize = 6;
+const HEADER_V2: usize = 10;
+const CRC_LEN: usize = 2;
+const SIGNATURE_LEN: usize = 13;
+const INCOMPAT_SIGNED: u8 = 0x01;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+// A ground station silent for this long gives up control
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+// The autopilot component of the vehicle
+const COMPONENT_ID: u8 = 1;
+
+const MAV_TYPE_QUADROTOR: u8 = 2;
+const MAV_AUTOPILOT_GENERIC: u8 = 0;
+const MAV_MODE_FLAG_MANUAL_INPUT_ENABLED: u8 = 64;
+const MAV_MODE_FLAG_SAFETY_ARMED: u8 = 128;
+const MAV_STATE_STANDBY: u8 = 3;
+const MAV_STATE_ACTIVE: u8 = 4;
+const MAV_STATE_CRITICAL: u8 = 5;
+const MAV_CMD_COMPONENT_ARM_DISARM: u16 = 400;
+const MAV_RESULT_ACCEPTED: u8 = 0;
+const MAV_RESULT_DENIED: u8 = 2;
+const MAV_RESULT_UNSUPPORTED: u8 = 3;
+
+// A message of the common set: id, CRC extra and payload length with extensions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Message {
+    id: u32,
+    crc_extra: u8,
+    len: usize,
+}
+
+pub const HEARTBEAT: Message = Message {
+    id: 0,
+    crc_extra: 50,
+    len: 9,
+};
+pub const RAW_IMU: Message = Message {
+    id: 27,
+    crc_extra: 144,
+    len: 29,
+};
+pub const SCALED_PRESSURE: Message = Message {
+    id: 29,
+    crc_extra: 115,
+    len: 16,
+};
+pub const ATTITUDE: Message = Message {
+    id: 30,
+    crc_extra: 39,
+    len: 28,
+};
+pub const SERVO_OUTPUT_RAW: Message = Message {
+    id: 36,
+    crc_extra: 222,
+    len: 37,
+};
+pub const RC_CHANNELS: Message = Message {
+    id: 65,
+    crc_extra: 118,
+    len: 42,
+};
+pub const RC_CHANNELS_OVERRIDE: Message = Message {
+    id: 70,
+    crc_extra: 124,
+    len: 38,
+};
+pub const COMMAND_LONG: Message = Message {
+    id: 76,
+    crc_extra: 152,
+    len: 33,
+};
+pub const COMMAND_ACK: Message = Message {
+    id: 77,
+    crc_extra: 143,
+    len: 10,
+};
+
+// Packets with other ids are skipped, their CRC cannot be checked
+const MESSAGES: [Message; 9] = [
+    HEARTBEAT,
+    RAW_IMU,
+    SCALED_PRESSURE,
+    ATTITUDE,
+    SERVO_OUTPUT_RAW,
+    RC_CHANNELS,
+    RC_CHANNELS_OVERRIDE,
+    COMMAND_LONG,
+    COMMAND_ACK,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum MavlinkError {
+    StartByte(u8),
+    Truncated(usize),
+    Crc { expected: u16, actual: u16 },
+}
+
+impl fmt::Display for MavlinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MavlinkError::StartByte(byte) => write!(f, "unexpected start byte 0x{:02x}", byte),
+            MavlinkError::Truncated(len) => write!(f, "packet truncated at {} bytes", len),
+            MavlinkError::Crc { expected, actual } => write!(
+                f,
+                "CRC mismatch (expected 0x{:04x}, got 0x{:04x})",
+                expected, actual
+            ),
+        }
+    }
+}
+
+// CRC-16/MCRF4XX, what MAVLink calls X.25
+pub fn crc(bytes: &[u8], mut crc: u16) -> u16 {
+    for byte in bytes {
+        let mut tmp = byte ^ (crc & 0xFF) as u8;
+        tmp ^= tmp << 4;
+        let tmp = tmp as u16;
+        crc = (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4);
+    }
+    crc
+}
+
+fn checksum(frame: &[u8], message: &Message) -> u16 {
+    crc(&[message.crc_extra], crc(frame, 0xFFFF))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub system: u8,
+    pub component: u8,
+    pub message: Message,
+    // Zero-extended to the full length, senders drop trailing zeros
+    pub payload: Vec<u8>,
+}
+
+// Splits a datagram into packets, MAVLink v1 or v2, stops at the first one that
+// cannot be framed
+pub fn decode(mut datagram: &[u8]) -> Vec<Result<Packet, MavlinkError>> {
+    let mut packets = Vec::new();
+    while let Some(&start) = datagram.first() {
+        let (header, id) = match start {
+            STX_V1 if datagram.len() >= HEADER_V1 => (HEADER_V1, datagram[5] as u32),
+            STX_V2 if datagram.len() >= HEADER_V2 => (
+                HEADER_V2,
+                u32::from_le_bytes([datagram[7], datagram[8], datagram[9], 0]),
+            ),
+            STX_V1 | STX_V2 => {
+                packets.push(Err(MavlinkError::Truncated(datagram.len())));
+                break;
+            }
+            byte => {
+                packets.push(Err(MavlinkError::StartByte(byte)));
+                break;
+            }
+        };
+        let len = datagram[1] as usize;
+        let signed = start == STX_V2 && datagram[2] & INCOMPAT_SIGNED != 0;
+        let end = header + len + CRC_LEN;
+        let total = end + if signed { SIGNATURE_LEN } else { 0 };
+        if datagram.len() < total {
+            packets.push(Err(MavlinkError::Truncated(datagram.len())));
+            break;
+        }
+
+        if let Some(message) = MESSAGES.iter().find(|message| message.id == id) {
+            let expected = checksum(&datagram[1..header + len], message);
+            let actual = u16::from_le_bytes([datagram[end - 2], datagram[end - 1]]);
+            packets.push(if expected == actual {
+                let (system, component) = match start {
+                    STX_V1 => (datagram[3], datagram[4]),
+                    _ => (datagram[5], datagram[6]),
+                };
+                let mut payload = datagram[header..header + len].to_vec();
+                payload.resize(message.len.max(len), 0);
+                Ok(Packet {
+                    system,
+                    component,
+                    message: *message,
+                    payload,
+                })
+            } else {
+                Err(MavlinkError::Crc { expected, actual })
+            });
+        }
+        datagram = &datagram[total..];
+    }
+    packets
+}
+
+// Frames MAVLink v2 packets sent as one system and component
+pub struct Encoder {
+    system: u8,
+    component: u8,
+    seq: u8,
+}
+
+impl Encoder {
+    pub fn new(system: u8, component: u8) -> Encoder {
+        Encoder {
+            system,
+            component,
+            seq: 0,
+        }
+    }
+
+    pub fn packet(&mut self, message: Message, payload: &[u8]) -> Vec<u8> {
+        // MAVLink 2 drops trailing zeros, at least one byte is always sent
+        let len = payload
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(1, |i| i + 1);
+        let id = message.id.to_le_bytes();
+        let mut packet = vec![
+            STX_V2,
+            len as u8,
+            0,
+            0,
+            self.seq,
+            self.system,
+            self.component,
+            id[0],
+            id[1],
+            id[2],
+        ];
+        packet.extend_from_slice(&payload[..len]);
+        packet.extend_from_slice(&checksum(&packet[1..], &message).to_le_bytes());
+        self.seq = self.seq.wrapping_add(1);
+        packet
+    }
+}
+
+// Little-endian payload in wire order, the largest fields first
+#[derive(Default)]
+struct Payload(Vec<u8>);
+
+impl Payload {
+    fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+
+    fn u16(mut self, value: u16) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn i16(mut self, value: f32) -> Self {
+        // Saturates at the bounds of i16
+        self.0
+            .extend_from_slice(&(value.round() as i16).to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, value: u64) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn f32(mut self, value: f32) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+}
+
+fn payload_u16(payload: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([payload[offset], payload[offset + 1]])
+}
+
+// Pulse widths in µs, UINT16_MAX marks an unused channel
+fn pulse(value: i32) -> u16 {
+    value.clamp(0, u16::MAX as i32 - 1) as u16
+}
+
+// Pressure in hPa at `altitude` metres in the standard atmosphere
+fn pressure(altitude: f32) -> f32 {
+    1013.25 * (1.0 - altitude / 44_330.0).powf(5.255)
+}
+
+pub fn heartbeat(armed: bool, link_up: bool) -> Vec<u8> {
+    let mut base_mode = MAV_MODE_FLAG_MANUAL_INPUT_ENABLED;
+    if armed {
+        base_mode |= MAV_MODE_FLAG_SAFETY_ARMED;
+    }
+    let system_status = match (link_up, armed) {
+        (false, _) => MAV_STATE_CRITICAL,
+        (true, true) => MAV_STATE_ACTIVE,
+        (true, false) => MAV_STATE_STANDBY,
+    };
+    Payload::default()
+        // custom_mode
+        .u32(0)
+        .u8(MAV_TYPE_QUADROTOR)
+        .u8(MAV_AUTOPILOT_GENERIC)
+        .u8(base_mode)
+        .u8(system_status)
+        // mavlink_version
+        .u8(3)
+        .0
+}
+
+// Sent on every telemetry line, heartbeats go out on their own timer. The
+// firmware reports m/s², °/s, µT, m, °C, degrees and µs, with the time
+// since boot in ms
+pub fn telemetry(data: &SerialData) -> Vec<(Message, Vec<u8>)> {
+    let time_boot_ms = data.elapsed_time as u32;
+    let time_usec = time_boot_ms as u64 * 1000;
+    let milli_g = |acc: f32| acc / 9.80665 * 1000.0;
+    let milli_rad = |dps: f32| dps.to_radians() * 1000.0;
+    let milli_gauss = |microtesla: f32| microtesla * 10.0;
+
+    let attitude = Payload::default()
+        .u32(time_boot_ms)
+        .f32((data.roll as f32).to_radians())
+        .f32((data.pitch as f32).to_radians())
+        .f32((data.yaw as f32).to_radians())
+        .f32(data.gyro_x.to_radians())
+        .f32(data.gyro_y.to_radians())
+        .f32(data.gyro_z.to_radians());
+
+    let raw_imu = Payload::default()
+        .u64(time_usec)
+        .i16(milli_g(data.acc_x))
+        .i16(milli_g(data.acc_y))
+        .i16(milli_g(data.acc_z))
+        .i16(milli_rad(data.gyro_x))
+        .i16(milli_rad(data.gyro_y))
+        .i16(milli_rad(data.gyro_z))
+        .i16(milli_gauss(data.mag_x))
+        .i16(milli_gauss(data.mag_y))
+        .i16(milli_gauss(data.mag_z))
+        // id, then temperature in cdegC
+        .u8(0)
+        .i16(data.temp * 100.0);
+
+    let scaled_pressure = Payload::default()
+        .u32(time_boot_ms)
+        .f32(pressure(data.altitude))
+        // press_diff
+        .f32(0.0)
+        .i16(data.temp * 100.0);
+
+    // AETR, the channel order ArduPilot and PX4 default to
+    let mut rc_channels = Payload::default()
+        .u32(time_boot_ms)
+        .u16(pulse(data.rc_roll))
+        .u16(pulse(data.rc_pitch))
+        .u16(pulse(data.rc_throttle))
+        .u16(pulse(data.rc_yaw));
+    for _ in 4..18 {
+        rc_channels = rc_channels.u16(u16::MAX);
+    }
+    // chancount, then rssi unknown
+    let rc_channels = rc_channels.u8(4).u8(u8::MAX);
+
+    // Quad X motor order: front right, back left, front left, back right
+    let servo_output = Payload::default()
+        .u32(time_usec as u32)
+        .u16(pulse(data.front_right))
+        .u16(pulse(data.back_left))
+        .u16(pulse(data.front_left))
+        .u16(pulse(data.back_right))
+        .u16(0)
+        .u16(0)
+        .u16(0)
+        .u16(0)
+        // port
+        .u8(0);
+
+    vec![
+        (ATTITUDE, attitude.0),
+        (RAW_IMU, raw_imu.0),
+        (SCALED_PRESSURE, scaled_pressure.0),
+        (RC_CHANNELS, rc_channels.0),
+        (SERVO_OUTPUT_RAW, servo_output.0),
+    ]
+}
+
+// What a ground station asks of the vehicle
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    // COMMAND_LONG, answered with COMMAND_ACK. `command` is `None` for the ones
+    // the flight controller has no equivalent of.
+    Command { id: u16, command: Option<Command> },
+    // RC_CHANNELS_OVERRIDE with all four sticks set
+    Sticks(Command),
+}
+
+// Requests addressed to `system`, or to every system
+pub fn request(packet: &Packet, system: u8) -> Option<Request> {
+    let payload = &packet.payload;
+    let for_us = |target: u8| target == 0 || target == system;
+    match packet.message {
+        COMMAND_LONG if for_us(payload[30]) => {
+            let param1 = f32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+            let id = payload_u16(payload, 28);
+            let command = match id {
+                MAV_CMD_COMPONENT_ARM_DISARM if param1 == 1.0 => Some(Command::Arm),
+                MAV_CMD_COMPONENT_ARM_DISARM if param1 == 0.0 => Some(Command::Abort),
+                _ => None,
+            };
+            Some(Request::Command { id, command })
+        }
+        RC_CHANNELS_OVERRIDE if for_us(payload[16]) => {
+            let [roll, pitch, throttle, yaw] =
+                [0, 2, 4, 6].map(|offset| payload_u16(payload, offset));
+            // 0 hands a channel back to the radio and UINT16_MAX leaves it alone,
+            // neither has an equivalent on the flight controller
+            if [roll, pitch, throttle, yaw]
+                .iter()
+                .any(|value| *value == 0 || *value == u16::MAX)
+            {
+                return None;
+            }
+            Some(Request::Sticks(Command::Rc {
+                throttle: throttle as i32,
+                yaw: yaw as i32,
+                pitch: pitch as i32,
+                roll: roll as i32,
+            }))
+        }
+        _ => None,
+    }
+}
+
+fn command_ack(command: u16, result: u8, target: &Packet) -> Vec<u8> {
+    Payload::default()
+        .u16(command)
+        .u8(result)
+        // progress, result_param2
+        .u8(0)
+        .u32(0)
+        .u8(target.system)
+        .u8(target.component)
+        .0
+}
+
+// Translates telemetry to MAVLink for the ground station at `mavlink.peer`, or
+// the last one heard from, and with `mavlink.control` the commands of the one
+// at `mavlink.peer` to the flight controller. The gateway is a client like the
+// WebSocket ones, commands need control.
+pub async fn run(bridge: Arc<Bridge>, socket: UdpSocket) {
+    let config = bridge.config.mavlink.clone();
+    let client = bridge.next_client_id();
+    let mut encoder = Encoder::new(config.system_id, COMPONENT_ID);
+    let mut lines = bridge.serial.lines.subscribe();
+    let mut heartbeats = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut buf = vec![0; 65536];
+
+    let telemetry_interval = match config.rate_hz {
+        rate if rate > 0.0 => Duration::from_secs_f32(1.0 / rate),
+        _ => Duration::ZERO,
+    };
+    let mut last_telemetry: Option<Instant> = None;
+    let mut peer = config.peer;
+    // Last sender ignored for not being `mavlink.peer`, logged once
+    let mut ignored = None;
+    let mut last_heard: Option<Instant> = None;
+    let mut armed = false;
+
+    loop {
+        let mut packets = Vec::new();
+        tokio::select! {
+            _ = heartbeats.tick() => {
+                let link_up = *bridge.serial.state.borrow() == LinkState::Up;
+                packets.push(encoder.packet(HEARTBEAT, &heartbeat(armed, link_up)));
+
+                let silent = last_heard.is_some_and(|at| at.elapsed() > PEER_TIMEOUT);
+                if silent && bridge.control.is_pilot(client) {
+                    // Like a WebSocket pilot that disappears, the watchdog stays armed
+                    bridge.control.release(client);
+                    println!("MAVLink ground station silent, released control");
+                }
+            }
+            line = lines.recv() => {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                if let Some(now_armed) = arming(&line) {
+                    armed = now_armed;
+                    continue;
+                }
+                let Ok(data) = SerialData::decode(&line) else {
+                    continue;
+                };
+                if last_telemetry.is_some_and(|at| at.elapsed() < telemetry_interval) {
+                    continue;
+                }
+                last_telemetry = Some(Instant::now());
+                for (message, payload) in telemetry(&data) {
+                    packets.push(encoder.packet(message, &payload));
+                }
+            }
+            received = socket.recv_from(&mut buf) => {
+                let (len, from) = match received {
+                    Ok(received) => received,
+                    Err(e) => {
+                        eprintln!("MAVLink receive error: {}", e);
+                        continue;
+                    }
+                };
+                let mut received = Vec::new();
+                for packet in decode(&buf[..len]) {
+                    match packet {
+                        Ok(packet) => received.push(packet),
+                        Err(e) => eprintln!("Ignoring MAVLink packet from {}: {}", from, e),
+                    }
+                }
+                // Only a valid packet may redirect telemetry
+                if received.is_empty() {
+                    continue;
+                }
+                match config.peer {
+                    Some(configured) if from != configured => {
+                        if ignored != Some(from) {
+                            eprintln!(
+                                "Ignoring MAVLink packets from {}, mavlink.peer is {}",
+                                from, configured
+                            );
+                            ignored = Some(from);
+                        }
+                        continue;
+                    }
+                    None if peer != Some(from) => {
+                        println!("MAVLink ground station at {}", from);
+                        peer = Some(from);
+                    }
+                    _ => {}
+                }
+                last_heard = Some(Instant::now());
+                if bridge.control.is_pilot(client) {
+                    bridge.watchdog.feed(Instant::now());
+                }
+                for packet in received {
+                    if let Some(reply) = handle_request(&bridge, client, &packet, &config) {
+                        packets.push(encoder.packet(COMMAND_ACK, &reply));
+                    }
+                }
+            }
+        }
+
+        if let Some(peer) = peer {
+            send(&socket, peer, packets).await;
+        }
+    }
+}
+
+// Queues what the ground station asked for, returns the COMMAND_ACK payload if it needs one
+fn handle_request(
+    bridge: &Bridge,
+    client: u64,
+    packet: &Packet,
+    config: &MavlinkConfig,
+) -> Option<Vec<u8>> {
+    let request = request(packet, config.system_id)?;
+    let command = match &request {
+        Request::Command { command: None, id } => {
+            return Some(command_ack(*id, MAV_RESULT_UNSUPPORTED, packet));
+        }
+        Request::Command {
+            command: Some(command),
+            ..
+        } => command,
+        Request::Sticks(command) => command,
+    };
+
+    // Only a command takes control, sticks alone never do
+    let pilot = config.control
+        && (bridge.control.is_pilot(client)
+            || matches!(request, Request::Command { .. }) && {
+                let took = bridge.control.take(client);
+                if took {
+                    bridge.watchdog.feed(Instant::now());
+                    println!("MAVLink ground station has control");
+                }
+                took
+            });
+    let line = command.encode();
+    let blocked = bridge.serial.commands.blocked(&line);
+    if pilot && !blocked {
+        bridge.serial.commands.push(line);
+    }
+    match request {
+        Request::Command { id, .. } => {
+            let result = if pilot && blocked {
+                eprintln!("Denied MAVLink command {}, kill switch engaged", id);
+                MAV_RESULT_DENIED
+            } else if pilot {
+                MAV_RESULT_ACCEPTED
+            } else if !config.control {
+                eprintln!("Denied MAVLink command {}, mavlink.control is off", id);
+                MAV_RESULT_DENIED
+            } else {
+                eprintln!("Denied MAVLink command {}, another client is the pilot", id);
+                MAV_RESULT_DENIED
+            };
+            Some(command_ack(id, result, packet))
+        }
+        Request::Sticks(_) => None,
+    }
+}
+
+async fn send(socket: &UdpSocket, peer: SocketAddr, packets: Vec<Vec<u8>>) {
+    for packet in packets {
+        if let Err(e) = socket.send_to(&packet, peer).await {
+            eprintln!("MAVLink send error to {}: {}", peer, e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::emulator;
+    use crate::recorder::Recorder;
+
+    fn command_long(command: u16, param1: f32, target: u8) -> Vec<u8> {
+        let mut payload = Payload::default().f32(param1);
+        for _ in 1..7 {
+            payload = payload.f32(0.0);
+        }
+        payload.u16(command).u8(target).u8(0).u8(0).0
+    }
+
+    fn rc_override(channels: [u16; 4], target: u8) -> Vec<u8> {
+        let mut payload = Payload::default();
+        for value in channels.into_iter().chain([u16::MAX; 4]) {
+            payload = payload.u16(value);
+        }
+        payload.u8(target).u8(0).0
+    }
+
+    #[test]
+    fn frames_and_checks_packets() {
+        assert_eq!(crc(b"123456789", 0xFFFF), 0x6F91);
+
+        let mut encoder = Encoder::new(255, 190);
+        let arm = encoder.packet(COMMAND_LONG, &command_long(400, 1.0, 1));
+        // Trailing zeros after the target system are dropped
+        assert_eq!(arm[1], 31);
+        let alive = encoder.packet(HEARTBEAT, &heartbeat(true, true));
+        assert_eq!(alive[4], 1);
+
+        // A v1 packet of a message the gateway does not know, skipped
+        let mut unknown = vec![STX_V1, 1, 0, 255, 190, 200, 7];
+        unknown.extend_from_slice(&crc(&unknown[1..], 0xFFFF).to_le_bytes());
+
+        let datagram = [unknown, arm.clone(), alive].concat();
+        let packets = decode(&datagram);
+        assert_eq!(packets.len(), 2);
+        let packet = packets[0].as_ref().unwrap();
+        assert_eq!((packet.system, packet.component), (255, 190));
+        assert_eq!(packet.payload, command_long(400, 1.0, 1));
+        assert_eq!(packets[1].as_ref().unwrap().payload, heartbeat(true, true));
+
+        let mut corrupted = arm.clone();
+        corrupted[12] ^= 1;
+        assert!(matches!(
+            decode(&corrupted)[0],
+            Err(MavlinkError::Crc { .. })
+        ));
+        assert_eq!(decode(&arm[..20]), vec![Err(MavlinkError::Truncated(20))]);
+        assert_eq!(decode(&[0x55, 1]), vec![Err(MavlinkError::StartByte(0x55))]);
+    }
+
+    #[test]
+    fn maps_requests_to_commands() {
+        let mut encoder = Encoder::new(255, 190);
+        let request_of = |encoder: &mut Encoder, message, payload: Vec<u8>| {
+            let packet = encoder.packet(message, &payload);
+            request(decode(&packet)[0].as_ref().unwrap(), 1)
+        };
+
+        assert_eq!(
+            request_of(&mut encoder, COMMAND_LONG, command_long(400, 1.0, 1)),
+            Some(Request::Command {
+                id: 400,
+                command: Some(Command::Arm)
+            })
+        );
+        assert_eq!(
+            request_of(&mut encoder, COMMAND_LONG, command_long(400, 0.0, 0)),
+            Some(Request::Command {
+                id: 400,
+                command: Some(Command::Abort)
+            })
+        );
+        assert_eq!(
+            request_of(&mut encoder, COMMAND_LONG, command_long(520, 1.0, 1)),
+            Some(Request::Command {
+                id: 520,
+                command: None
+            })
+        );
+        // Addressed to another vehicle
+        assert_eq!(
+            request_of(&mut encoder, COMMAND_LONG, command_long(400, 1.0, 2)),
+            None
+        );
+
+        // Roll, pitch, throttle, yaw
+        assert_eq!(
+            request_of(
+                &mut encoder,
+                RC_CHANNELS_OVERRIDE,
+                rc_override([1400, 1600, 1200, 1550], 1)
+            ),
+            Some(Request::Sticks(Command::Rc {
+                throttle: 1200,
+                yaw: 1550,
+                pitch: 1600,
+                roll: 1400
+            }))
+        );
+        assert_eq!(
+            request_of(
+                &mut encoder,
+                RC_CHANNELS_OVERRIDE,
+                rc_override([1400, 1600, 0, 1550], 1)
+            ),
+            None
+        );
+    }
+
+    async fn receive(peer: &UdpSocket, message: Message) -> Packet {
+        let mut buf = vec![0; 2048];
+        loop {
+            let len = peer.recv(&mut buf).await.unwrap();
+            if let Some(Ok(packet)) = decode(&buf[..len]).pop() {
+                if packet.message == message {
+                    return packet;
+                }
+            }
+        }
+    }
+
+    // Serves the gateway on the emulator for a ground station at `peer`
+    async fn serve(peer: &UdpSocket, control: bool) -> Arc<Bridge> {
+        let mut config = Config::default();
+        config.emulator.boot_ms = 0;
+        config.mavlink.rate_hz = 0.0;
+        config.mavlink.peer = Some(peer.local_addr().unwrap());
+        config.mavlink.control = control;
+        let recorder = Recorder::disabled();
+        let serial = emulator::open(&config.emulator, recorder.clone());
+        let bridge = Arc::new(Bridge::new(config, serial, recorder, None));
+        let mut lines = bridge.serial.lines.subscribe();
+        while lines.recv().await.unwrap() != "Waiting for command to arm..." {}
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        peer.connect(socket.local_addr().unwrap()).await.unwrap();
+        tokio::spawn(run(Arc::clone(&bridge), socket));
+        bridge
+    }
+
+    #[tokio::test]
+    async fn local_ground_station() {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let bridge = serve(&peer, true).await;
+        let mut written = bridge.serial.written.subscribe();
+        let mut ground = Encoder::new(255, 190);
+        let timeout = Duration::from_secs(3);
+
+        // Anyone else on the network is ignored
+        let stranger = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let arm = ground.packet(COMMAND_LONG, &command_long(400, 1.0, 1));
+        let gateway = peer.peer_addr().unwrap();
+        stranger.send_to(&arm, gateway).await.unwrap();
+        let sticks = ground.packet(RC_CHANNELS_OVERRIDE, &rc_override([1500; 4], 1));
+        peer.send(&sticks).await.unwrap();
+        let unsupported = ground.packet(COMMAND_LONG, &command_long(176, 1.0, 1));
+        peer.send(&unsupported).await.unwrap();
+        let ack = tokio::time::timeout(timeout, receive(&peer, COMMAND_ACK))
+            .await
+            .unwrap();
+        assert_eq!(ack.payload[2], MAV_RESULT_UNSUPPORTED);
+        // Sticks do not take control either
+        assert_eq!(bridge.control.pilot(), None);
+        assert!(written.try_recv().is_err());
+
+        let arm = ground.packet(COMMAND_LONG, &command_long(400, 1.0, 1));
+        peer.send(&arm).await.unwrap();
+        let ack = tokio::time::timeout(timeout, receive(&peer, COMMAND_ACK))
+            .await
+            .unwrap();
+        assert_eq!(payload_u16(&ack.payload, 0), 400);
+        assert_eq!(ack.payload[2], MAV_RESULT_ACCEPTED);
+        assert_eq!(&ack.payload[8..10], [255, 190]);
+        assert_eq!(written.recv().await.unwrap(), "command->arm\n");
+
+        let heartbeat = tokio::time::timeout(timeout, async {
+            loop {
+                let heartbeat = receive(&peer, HEARTBEAT).await;
+                if heartbeat.payload[6] & MAV_MODE_FLAG_SAFETY_ARMED != 0 {
+                    return heartbeat;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(heartbeat.payload[7], MAV_STATE_ACTIVE);
+        let rc = tokio::time::timeout(timeout, receive(&peer, RC_CHANNELS))
+            .await
+            .unwrap();
+        assert_eq!(rc.payload[40], 4);
+
+        // The gateway has control, a WebSocket client cannot take it
+        assert!(!bridge.control.take(1000));
+        bridge.control.release(bridge.control.pilot().unwrap());
+        assert!(bridge.control.take(1000));
+        let disarm = ground.packet(COMMAND_LONG, &command_long(400, 0.0, 1));
+        peer.send(&disarm).await.unwrap();
+        let ack = tokio::time::timeout(timeout, receive(&peer, COMMAND_ACK))
+            .await
+            .unwrap();
+        assert_eq!(ack.payload[2], MAV_RESULT_DENIED);
+    }
+
+    #[tokio::test]
+    async fn commands_need_control() {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let bridge = serve(&peer, false).await;
+        let mut ground = Encoder::new(255, 190);
+
+        let arm = ground.packet(COMMAND_LONG, &command_long(400, 1.0, 1));
+        peer.send(&arm).await.unwrap();
+        let ack = tokio::time::timeout(Duration::from_secs(3), receive(&peer, COMMAND_ACK))
+            .await
+            .unwrap();
+        assert_eq!(ack.payload[2], MAV_RESULT_DENIED);
+        assert_eq!(bridge.control.pilot(), None);
+    }
+}
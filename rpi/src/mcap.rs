@@ -0,0 +1,416 @@
+use crc32fast::Hasher;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+// https://mcap.dev/spec
+pub const MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+// Messages are buffered into chunks of about this size, each indexed in the summary
+const CHUNK_SIZE: usize = 1024 * 1024;
+const LIBRARY: &str = "drone bridge";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_MESSAGE_INDEX: u8 = 0x07;
+const OP_CHUNK_INDEX: u8 = 0x08;
+const OP_STATISTICS: u8 = 0x0B;
+const OP_SUMMARY_OFFSET: u8 = 0x0E;
+const OP_DATA_END: u8 = 0x0F;
+
+// Record fields, little-endian, strings and byte arrays prefixed with their length
+#[derive(Default)]
+struct Fields(Vec<u8>);
+
+impl Fields {
+    fn u16(mut self, value: u16) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, value: u64) -> Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn string(self, value: &str) -> Self {
+        self.bytes(value.as_bytes())
+    }
+
+    // Also used for maps and arrays, which are prefixed with their size in bytes
+    fn bytes(mut self, value: &[u8]) -> Self {
+        self.0
+            .extend_from_slice(&(value.len() as u32).to_le_bytes());
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    fn raw(mut self, value: &[u8]) -> Self {
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    fn record(self, op: u8) -> Vec<u8> {
+        let mut record = vec![op];
+        record.extend_from_slice(&(self.0.len() as u64).to_le_bytes());
+        record.extend_from_slice(&self.0);
+        record
+    }
+}
+
+// Writes an indexed MCAP file: schemas and channels up front, messages in
+// uncompressed chunks, then a summary with the chunk indexes and statistics
+pub struct McapWriter<W: Write> {
+    out: W,
+    position: u64,
+    data_crc: Hasher,
+    schemas: Vec<Vec<u8>>,
+    channels: Vec<Vec<u8>>,
+    sequences: Vec<u32>,
+    chunk: Vec<u8>,
+    // Log times and offsets in `chunk` of every message, by channel
+    chunk_messages: BTreeMap<u16, Vec<(u64, u64)>>,
+    chunk_times: Option<(u64, u64)>,
+    chunk_indexes: Vec<Vec<u8>>,
+    message_counts: BTreeMap<u16, u64>,
+    message_times: Option<(u64, u64)>,
+}
+
+impl<W: Write> McapWriter<W> {
+    pub fn new(out: W) -> io::Result<McapWriter<W>> {
+        let mut writer = McapWriter {
+            out,
+            position: 0,
+            data_crc: Hasher::new(),
+            schemas: Vec::new(),
+            channels: Vec::new(),
+            sequences: Vec::new(),
+            chunk: Vec::new(),
+            chunk_messages: BTreeMap::new(),
+            chunk_times: None,
+            chunk_indexes: Vec::new(),
+            message_counts: BTreeMap::new(),
+            message_times: None,
+        };
+        writer.write(MAGIC)?;
+        // No profile, messages are plain JSON
+        let header = Fields::default()
+            .string("")
+            .string(LIBRARY)
+            .record(OP_HEADER);
+        writer.write(&header)?;
+        Ok(writer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.data_crc.update(bytes);
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    // Returns the schema id, ids start at 1
+    pub fn schema(&mut self, name: &str, encoding: &str, data: &[u8]) -> io::Result<u16> {
+        let id = self.schemas.len() as u16 + 1;
+        let record = Fields::default()
+            .u16(id)
+            .string(name)
+            .string(encoding)
+            .bytes(data)
+            .record(OP_SCHEMA);
+        self.write(&record)?;
+        self.schemas.push(record);
+        Ok(id)
+    }
+
+    // Returns the channel id, ids start at 1
+    pub fn channel(&mut self, schema: u16, topic: &str, encoding: &str) -> io::Result<u16> {
+        let id = self.channels.len() as u16 + 1;
+        let record = Fields::default()
+            .u16(id)
+            .u16(schema)
+            .string(topic)
+            .string(encoding)
+            // No metadata
+            .bytes(&[])
+            .record(OP_CHANNEL);
+        self.write(&record)?;
+        self.channels.push(record);
+        self.sequences.push(0);
+        Ok(id)
+    }
+
+    // `log_time` in nanoseconds since the Unix epoch
+    pub fn message(&mut self, channel: u16, log_time: u64, data: &[u8]) -> io::Result<()> {
+        let sequence = &mut self.sequences[channel as usize - 1];
+        let record = Fields::default()
+            .u16(channel)
+            .u32(*sequence)
+            .u64(log_time)
+            // Publish time, the same as the log time
+            .u64(log_time)
+            .raw(data)
+            .record(OP_MESSAGE);
+        *sequence += 1;
+
+        self.chunk_messages
+            .entry(channel)
+            .or_default()
+            .push((log_time, self.chunk.len() as u64));
+        self.chunk.extend_from_slice(&record);
+        self.chunk_times = Some(extend(self.chunk_times, log_time));
+        self.message_times = Some(extend(self.message_times, log_time));
+        *self.message_counts.entry(channel).or_default() += 1;
+
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        let Some((start, end)) = self.chunk_times.take() else {
+            return Ok(());
+        };
+        let chunk = std::mem::take(&mut self.chunk);
+        let chunk_start = self.position;
+        let record = Fields::default()
+            .u64(start)
+            .u64(end)
+            .u64(chunk.len() as u64)
+            .u32(crc32fast::hash(&chunk))
+            // Not compressed
+            .string("")
+            .u64(chunk.len() as u64)
+            .raw(&chunk)
+            .record(OP_CHUNK);
+        self.write(&record)?;
+        let chunk_length = self.position - chunk_start;
+
+        let indexes_start = self.position;
+        let mut index_offsets = Fields::default();
+        for (channel, messages) in std::mem::take(&mut self.chunk_messages) {
+            index_offsets = index_offsets.u16(channel).u64(self.position);
+            let entries = messages
+                .iter()
+                .fold(Fields::default(), |entries, (time, offset)| {
+                    entries.u64(*time).u64(*offset)
+                });
+            let index = Fields::default()
+                .u16(channel)
+                .bytes(&entries.0)
+                .record(OP_MESSAGE_INDEX);
+            self.write(&index)?;
+        }
+
+        let chunk_index = Fields::default()
+            .u64(start)
+            .u64(end)
+            .u64(chunk_start)
+            .u64(chunk_length)
+            .bytes(&index_offsets.0)
+            .u64(self.position - indexes_start)
+            .string("")
+            // Compressed and uncompressed sizes
+            .u64(chunk.len() as u64)
+            .u64(chunk.len() as u64)
+            .record(OP_CHUNK_INDEX);
+        self.chunk_indexes.push(chunk_index);
+        Ok(())
+    }
+
+    // Writes the last chunk, the summary and the footer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_chunk()?;
+        let data_end = Fields::default()
+            .u32(self.data_crc.clone().finalize())
+            .record(OP_DATA_END);
+        self.write(&data_end)?;
+
+        let (start, end) = self.message_times.unwrap_or((0, 0));
+        let counts = self
+            .message_counts
+            .iter()
+            .fold(Fields::default(), |counts, (channel, count)| {
+                counts.u16(*channel).u64(*count)
+            });
+        let statistics = Fields::default()
+            .u64(self.message_counts.values().sum())
+            .u16(self.schemas.len() as u16)
+            .u32(self.channels.len() as u32)
+            // Attachments and metadata
+            .u32(0)
+            .u32(0)
+            .u32(self.chunk_indexes.len() as u32)
+            .u64(start)
+            .u64(end)
+            .bytes(&counts.0)
+            .record(OP_STATISTICS);
+
+        let summary_start = self.position;
+        let mut summary = Vec::new();
+        let mut offsets = Vec::new();
+        let groups = [
+            (OP_SCHEMA, std::mem::take(&mut self.schemas)),
+            (OP_CHANNEL, std::mem::take(&mut self.channels)),
+            (OP_STATISTICS, vec![statistics]),
+            (OP_CHUNK_INDEX, std::mem::take(&mut self.chunk_indexes)),
+        ];
+        for (op, records) in groups {
+            if records.is_empty() {
+                continue;
+            }
+            let group_start = summary_start + summary.len() as u64;
+            summary.extend(records.concat());
+            let group_length = summary_start + summary.len() as u64 - group_start;
+            offsets.push(
+                Fields::default()
+                    .raw(&[op])
+                    .u64(group_start)
+                    .u64(group_length)
+                    .record(OP_SUMMARY_OFFSET),
+            );
+        }
+        let summary_offset_start = summary_start + summary.len() as u64;
+        summary.extend(offsets.concat());
+
+        // The footer is a record whose CRC covers everything from the summary up to the CRC itself
+        let mut footer = Fields::default()
+            .u64(summary_start)
+            .u64(summary_offset_start)
+            .record(OP_FOOTER);
+        // Length of the whole record, including the CRC
+        footer[1..9].copy_from_slice(&20u64.to_le_bytes());
+        let mut crc = Hasher::new();
+        crc.update(&summary);
+        crc.update(&footer);
+        footer.extend_from_slice(&crc.finalize().to_le_bytes());
+
+        self.out.write_all(&summary)?;
+        self.out.write_all(&footer)?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn extend(range: Option<(u64, u64)>, time: u64) -> (u64, u64) {
+    match range {
+        Some((start, end)) => (start.min(time), end.max(time)),
+        None => (time, time),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Opcode and content of every record between the two magics
+    fn records(file: &[u8]) -> Vec<(u64, u8, &[u8])> {
+        assert_eq!(&file[..8], MAGIC);
+        assert_eq!(&file[file.len() - 8..], MAGIC);
+        let mut records = Vec::new();
+        let mut position = 8;
+        while position < file.len() - 8 {
+            let op = file[position];
+            let len = u64::from_le_bytes(file[position + 1..position + 9].try_into().unwrap());
+            let content = &file[position + 9..position + 9 + len as usize];
+            records.push((position as u64, op, content));
+            position += 9 + len as usize;
+        }
+        assert_eq!(position, file.len() - 8);
+        records
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_an_indexed_file() {
+        let mut writer = McapWriter::new(Vec::new()).unwrap();
+        let schema = writer.schema("Line", "jsonschema", b"{}").unwrap();
+        let telemetry = writer.channel(schema, "/telemetry", "json").unwrap();
+        let log = writer.channel(schema, "/log", "json").unwrap();
+        writer.message(telemetry, 2_000, b"{\"a\":1}").unwrap();
+        writer.message(log, 1_000, b"{\"b\":2}").unwrap();
+        writer.message(telemetry, 3_000, b"{\"a\":3}").unwrap();
+        let file = writer.finish().unwrap();
+
+        let records = records(&file);
+        let ops: Vec<u8> = records.iter().map(|(_, op, _)| *op).collect();
+        assert_eq!(
+            ops,
+            [
+                OP_HEADER,
+                OP_SCHEMA,
+                OP_CHANNEL,
+                OP_CHANNEL,
+                OP_CHUNK,
+                OP_MESSAGE_INDEX,
+                OP_MESSAGE_INDEX,
+                OP_DATA_END,
+                OP_SCHEMA,
+                OP_CHANNEL,
+                OP_CHANNEL,
+                OP_STATISTICS,
+                OP_CHUNK_INDEX,
+                OP_SUMMARY_OFFSET,
+                OP_SUMMARY_OFFSET,
+                OP_SUMMARY_OFFSET,
+                OP_SUMMARY_OFFSET,
+                OP_FOOTER
+            ]
+        );
+
+        // Chunk: times, sizes and a CRC over the messages it holds
+        let (chunk_start, _, chunk) = records[4];
+        assert_eq!((u64_at(chunk, 0), u64_at(chunk, 8)), (1_000, 3_000));
+        let messages = &chunk[8 * 3 + 4 + 4 + 8..];
+        assert_eq!(u32_at(chunk, 24), crc32fast::hash(messages));
+        assert_eq!(messages[0], OP_MESSAGE);
+
+        // The second message of /telemetry, found through its message index
+        let (index_start, _, index) = records[5];
+        assert_eq!(&index[..2], telemetry.to_le_bytes());
+        assert_eq!(u32_at(index, 2), 32);
+        let offset = u64_at(index, 6 + 16 + 8) as usize;
+        assert_eq!(
+            &messages[offset + 9 + 2..offset + 9 + 6],
+            1u32.to_le_bytes()
+        );
+
+        let (data_end_start, _, data_end) = records[7];
+        assert_eq!(
+            u32_at(data_end, 0),
+            crc32fast::hash(&file[..data_end_start as usize])
+        );
+
+        let (_, _, statistics) = records[11];
+        assert_eq!(u64_at(statistics, 0), 3);
+        let (_, _, chunk_index) = records[12];
+        assert_eq!(u64_at(chunk_index, 16), chunk_start);
+        // Offsets of the message indexes, /telemetry first
+        assert_eq!(u64_at(chunk_index, 32 + 4 + 2), index_start);
+
+        let (footer_start, _, footer) = records[17];
+        let summary_start = u64_at(footer, 0) as usize;
+        assert_eq!(summary_start, records[8].0 as usize);
+        assert_eq!(u64_at(footer, 8), records[13].0);
+        assert_eq!(
+            u32_at(footer, 16),
+            crc32fast::hash(&file[summary_start..footer_start as usize + 9 + 16])
+        );
+    }
+}
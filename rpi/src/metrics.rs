@@ -0,0 +1,338 @@
+use protocol::QueueStats;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::bridge::Bridge;
+use crate::serial::LinkState;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Larger request headers are refused, nothing here needs them
+const MAX_REQUEST: usize = 8 * 1024;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub uptime_s: f64,
+    // "serial", "emulator" or "replay"
+    pub source: &'static str,
+    pub clients: usize,
+    pub pilot: Option<u64>,
+    pub serial: SerialSnapshot,
+    pub command_queue: QueueStats,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SerialSnapshot {
+    // False while the bridge is reopening the serial port
+    pub up: bool,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub lines: u64,
+    pub lines_per_sec: f64,
+    pub parse_errors: u64,
+    // None until the first telemetry line
+    pub last_telemetry_age_s: Option<f64>,
+}
+
+// Serves `/status` as JSON and `/metrics` in the Prometheus text format
+pub async fn run(bridge: Arc<Bridge>, listener: TcpListener) {
+    let lines_per_sec = Arc::new(Mutex::new(0.0));
+    tokio::spawn(sample_lines(
+        Arc::clone(&bridge),
+        Arc::clone(&lines_per_sec),
+    ));
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept metrics connection: {}", e);
+                continue;
+            }
+        };
+        let bridge = Arc::clone(&bridge);
+        let lines_per_sec = Arc::clone(&lines_per_sec);
+        tokio::spawn(async move {
+            let rate = *lines_per_sec.lock().unwrap();
+            let serve = serve(stream, &bridge, rate);
+            // Slow or idle connections are dropped at the timeout
+            if let Ok(Err(e)) = tokio::time::timeout(REQUEST_TIMEOUT, serve).await {
+                eprintln!("Metrics request failed: {}", e);
+            }
+        });
+    }
+}
+
+// Lines read during the last sample interval
+async fn sample_lines(bridge: Arc<Bridge>, lines_per_sec: Arc<Mutex<f64>>) {
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+    let mut last = (Instant::now(), bridge.serial.stats.lines());
+    loop {
+        interval.tick().await;
+        let now = (Instant::now(), bridge.serial.stats.lines());
+        let elapsed = now.0.duration_since(last.0).as_secs_f64();
+        if elapsed > 0.0 {
+            *lines_per_sec.lock().unwrap() = (now.1 - last.1) as f64 / elapsed;
+        }
+        last = now;
+    }
+}
+
+async fn serve(mut stream: TcpStream, bridge: &Bridge, lines_per_sec: f64) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST {
+            return stream.write_all(&response(431, "text/plain", "")).await;
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&request);
+    let reply = match parse_request(&head) {
+        None => response(400, "text/plain", "bad request\n"),
+        Some((method, _)) if method != "GET" => response(405, "text/plain", "only GET\n"),
+        Some((_, "/status")) => {
+            let snapshot = snapshot(bridge, lines_per_sec, Instant::now());
+            let json = serde_json::to_string(&snapshot).unwrap();
+            response(200, "application/json", &json)
+        }
+        Some((_, "/metrics")) => {
+            let snapshot = snapshot(bridge, lines_per_sec, Instant::now());
+            response(200, "text/plain; version=0.0.4", &render_metrics(&snapshot))
+        }
+        Some((_, "/")) => response(200, "text/plain", "rpi bridge: /status /metrics\n"),
+        Some(_) => response(404, "text/plain", "not found\n"),
+    };
+    stream.write_all(&reply).await?;
+    stream.shutdown().await
+}
+
+// Method and path of the request line, without the query string
+fn parse_request(head: &str) -> Option<(&str, &str)> {
+    let mut parts = head.lines().next()?.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    if !parts.next()?.starts_with("HTTP/1.") {
+        return None;
+    }
+    let path = target.split('?').next().unwrap_or(target);
+    Some((method, path))
+}
+
+fn response(status: u16, content_type: &str, body: &str) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        _ => "",
+    };
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
+
+fn snapshot(bridge: &Bridge, lines_per_sec: f64, now: Instant) -> Snapshot {
+    let config = &bridge.config;
+    let source = match (&config.replay.file, config.emulator.enabled) {
+        (Some(_), _) => "replay",
+        (None, true) => "emulator",
+        (None, false) => "serial",
+    };
+    let stats = &bridge.serial.stats;
+    Snapshot {
+        uptime_s: now.duration_since(bridge.started).as_secs_f64(),
+        source,
+        clients: bridge.clients.load(Ordering::Relaxed),
+        pilot: bridge.control.pilot(),
+        serial: SerialSnapshot {
+            up: *bridge.serial.state.borrow() == LinkState::Up,
+            bytes_in: stats.bytes_in(),
+            bytes_out: stats.bytes_out(),
+            lines: stats.lines(),
+            lines_per_sec,
+            parse_errors: stats.errors(),
+            last_telemetry_age_s: stats
+                .last_telemetry()
+                .map(|time| now.duration_since(time).as_secs_f64()),
+        },
+        command_queue: bridge.serial.commands.stats(),
+    }
+}
+
+fn render_metrics(snapshot: &Snapshot) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(out, "{} {}", name, value);
+    };
+    let serial = &snapshot.serial;
+    let queue = &snapshot.command_queue;
+    metric(
+        "bridge_uptime_seconds",
+        "gauge",
+        "Time since the bridge started.",
+        snapshot.uptime_s,
+    );
+    metric(
+        "bridge_serial_up",
+        "gauge",
+        "Whether the serial port is open.",
+        if serial.up { 1.0 } else { 0.0 },
+    );
+    metric(
+        "bridge_serial_bytes_received_total",
+        "counter",
+        "Bytes read from the flight controller.",
+        serial.bytes_in as f64,
+    );
+    metric(
+        "bridge_serial_bytes_sent_total",
+        "counter",
+        "Bytes of commands written to the flight controller.",
+        serial.bytes_out as f64,
+    );
+    metric(
+        "bridge_serial_lines_total",
+        "counter",
+        "Lines read from the flight controller.",
+        serial.lines as f64,
+    );
+    metric(
+        "bridge_serial_lines_per_second",
+        "gauge",
+        "Lines read during the last second.",
+        serial.lines_per_sec,
+    );
+    metric(
+        "bridge_serial_parse_errors_total",
+        "counter",
+        "Telemetry lines or frames that could not be parsed.",
+        serial.parse_errors as f64,
+    );
+    if let Some(age) = serial.last_telemetry_age_s {
+        metric(
+            "bridge_last_telemetry_age_seconds",
+            "gauge",
+            "Time since the last telemetry line.",
+            age,
+        );
+    }
+    metric(
+        "bridge_clients",
+        "gauge",
+        "Connected WebSocket clients.",
+        snapshot.clients as f64,
+    );
+    metric(
+        "bridge_command_queue_depth",
+        "gauge",
+        "Commands waiting to be written.",
+        queue.depth as f64,
+    );
+    metric(
+        "bridge_commands_coalesced_total",
+        "counter",
+        "Commands replaced by a newer one before being written.",
+        queue.coalesced as f64,
+    );
+    metric(
+        "bridge_commands_dropped_total",
+        "counter",
+        "Commands dropped because the queue was full.",
+        queue.dropped as f64,
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            uptime_s: 12.5,
+            source: "emulator",
+            clients: 2,
+            pilot: Some(1),
+            serial: SerialSnapshot {
+                up: true,
+                bytes_in: 4096,
+                bytes_out: 120,
+                lines: 600,
+                lines_per_sec: 50.0,
+                parse_errors: 1,
+                last_telemetry_age_s: None,
+            },
+            command_queue: QueueStats {
+                depth: 3,
+                coalesced: 40,
+                dropped: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn renders_prometheus_text() {
+        let text = render_metrics(&snapshot());
+        assert!(text.contains(
+            "# TYPE bridge_serial_bytes_received_total counter\nbridge_serial_bytes_received_total 4096\n"
+        ));
+        assert!(text.contains("bridge_uptime_seconds 12.5\n"));
+        assert!(text.contains("bridge_serial_up 1\n"));
+        assert!(text.contains("bridge_clients 2\n"));
+        assert!(text.contains("bridge_command_queue_depth 3\n"));
+        assert!(!text.contains("bridge_last_telemetry_age_seconds"));
+
+        let mut snapshot = snapshot();
+        snapshot.serial.last_telemetry_age_s = Some(0.25);
+        let text = render_metrics(&snapshot);
+        assert!(text.contains("bridge_last_telemetry_age_seconds 0.25\n"));
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let (_, value) = line.split_once(' ').unwrap();
+            assert!(value.parse::<f64>().is_ok(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn status_json() {
+        let json = serde_json::to_value(snapshot()).unwrap();
+        assert_eq!(json["source"], "emulator");
+        assert_eq!(json["serial"]["lines_per_sec"], 50.0);
+        assert_eq!(
+            json["serial"]["last_telemetry_age_s"],
+            serde_json::Value::Null
+        );
+        assert_eq!(json["command_queue"]["coalesced"], 40);
+    }
+
+    #[test]
+    fn parses_request_line() {
+        let head = "GET /metrics?format=text HTTP/1.1\r\nHost: pi\r\n\r\n";
+        assert_eq!(parse_request(head), Some(("GET", "/metrics")));
+        assert_eq!(
+            parse_request("POST /status HTTP/1.0\r\n\r\n"),
+            Some(("POST", "/status"))
+        );
+        assert_eq!(parse_request("GET /status\r\n\r\n"), None);
+        assert_eq!(parse_request("\r\n\r\n"), None);
+    }
+}
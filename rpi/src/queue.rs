@@ -0,0 +1,331 @@
+use protocol::{Command, QueueStats};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+// Ordered commands waiting beyond this are dropped, the serial writer is stuck
+const MAX_ORDERED: usize = 100;
+
+// Outbound commands waiting for the serial writer. Safety commands go out
+// first, then the newest RC values and keepalive, then everything else in the
+// order it was pushed. RC and keepalive only keep their latest value so a
+// burst of stick updates can never hold back an abort. An abort, like the
+// failsafe's safe sequence, drops everything else waiting so nothing queued
+// before it can move the motors once it has been written. While the kill
+// switch is engaged, commands that could move the motors are refused.
+#[derive(Default)]
+pub struct CommandQueue {
+    pending: Mutex<Pending>,
+    ready: Notify,
+}
+
+#[derive(Default)]
+struct Pending {
+    safety: VecDeque<String>,
+    rc: Option<String>,
+    keepalive: Option<String>,
+    ordered: VecDeque<String>,
+    coalesced: u64,
+    dropped: u64,
+    // Set on shutdown, nothing is queued anymore
+    closed: bool,
+    // Set while the kill switch is engaged
+    killed: bool,
+}
+
+enum Priority {
+    Safety,
+    Rc,
+    Keepalive,
+    Ordered,
+}
+
+fn priority(command: &str) -> Priority {
+    match Command::decode(command) {
+        Ok(Command::Abort) => Priority::Safety,
+        Ok(Command::Rc { .. }) => Priority::Rc,
+        // The ground repeats this while the motors are enabled
+        Ok(Command::EnableMotors) => Priority::Keepalive,
+        _ => Priority::Ordered,
+    }
+}
+
+impl Pending {
+    fn insert(&mut self, command: String) {
+        match priority(&command) {
+            Priority::Safety => {
+                self.clear();
+                self.safety.push_back(command);
+            }
+            Priority::Rc => {
+                if self.rc.replace(command).is_some() {
+                    self.coalesced += 1;
+                }
+            }
+            Priority::Keepalive => {
+                if self.keepalive.replace(command).is_some() {
+                    self.coalesced += 1;
+                }
+            }
+            Priority::Ordered => {
+                if self.ordered.len() >= MAX_ORDERED {
+                    eprintln!("Command queue full, dropping: {}", command.trim_end());
+                    self.dropped += 1;
+                    return;
+                }
+                self.ordered.push_back(command);
+            }
+        }
+    }
+
+    // Drops the RC values, keepalive and ordered commands waiting, queued
+    // safety commands stay
+    fn clear(&mut self) {
+        let cleared = self.rc.take().is_some() as usize
+            + self.keepalive.take().is_some() as usize
+            + self.ordered.len();
+        self.ordered.clear();
+        self.dropped += cleared as u64;
+    }
+
+    fn depth(&self) -> usize {
+        self.safety.len()
+            + self.ordered.len()
+            + self.rc.is_some() as usize
+            + self.keepalive.is_some() as usize
+    }
+}
+
+// Commands that arm or spin up the motors, refused while the kill switch is
+// engaged. The firmware ignores an abort before arming, so the abort alone
+// cannot hold them off.
+fn moves_motors(command: &str) -> bool {
+    matches!(
+        Command::decode(command),
+        Ok(Command::Arm | Command::EnableMotors | Command::Rc { .. })
+    )
+}
+
+impl CommandQueue {
+    pub fn push(&self, command: String) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                eprintln!("Command queue closed, dropping: {}", command.trim_end());
+                pending.dropped += 1;
+                return;
+            }
+            if pending.killed && moves_motors(&command) {
+                eprintln!("Kill switch engaged, dropping: {}", command.trim_end());
+                pending.dropped += 1;
+                return;
+            }
+            pending.insert(command);
+        }
+        self.ready.notify_one();
+    }
+
+    // Queues `commands` with the safety commands in this order, and drops
+    // everything else waiting so it cannot undo a throttle-low in `commands`
+    pub fn preempt(&self, commands: &[String]) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            if pending.closed {
+                pending.dropped += commands.len() as u64;
+                return;
+            }
+            pending.clear();
+            pending.safety.extend(commands.iter().cloned());
+        }
+        self.ready.notify_one();
+    }
+
+    // Whether `command` would be dropped because the kill switch is engaged
+    pub fn blocked(&self, command: &str) -> bool {
+        self.pending.lock().unwrap().killed && moves_motors(command)
+    }
+
+    // Follows the kill switch, `push` refuses commands that could move the
+    // motors until it is released
+    pub fn set_kill_switch(&self, engaged: bool) {
+        self.pending.lock().unwrap().killed = engaged;
+    }
+
+    // Drops everything waiting and queues `last` instead in this order, later
+    // pushes are dropped
+    pub fn close(&self, last: &[String]) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            let dropped = pending.dropped + pending.depth() as u64;
+            *pending = Pending {
+                coalesced: pending.coalesced,
+                dropped,
+                closed: true,
+                ..Default::default()
+            };
+            pending.safety.extend(last.iter().cloned());
+        }
+        self.ready.notify_one();
+    }
+
+    // Waits for the next command to write. Meant for a single writer task.
+    pub async fn pop(&self) -> String {
+        loop {
+            if let Some(command) = self.try_pop() {
+                return command;
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    fn try_pop(&self) -> Option<String> {
+        let mut pending = self.pending.lock().unwrap();
+        pending
+            .safety
+            .pop_front()
+            .or_else(|| pending.rc.take())
+            .or_else(|| pending.keepalive.take())
+            .or_else(|| pending.ordered.pop_front())
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let pending = self.pending.lock().unwrap();
+        QueueStats {
+            depth: pending.depth() as u32,
+            coalesced: pending.coalesced,
+            dropped: pending.dropped,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &CommandQueue) -> Vec<String> {
+        std::iter::from_fn(|| queue.try_pop()).collect()
+    }
+
+    #[test]
+    fn abort_drops_everything_before_it() {
+        let queue = CommandQueue::default();
+        queue.push("pid->1,0,0\n".to_string());
+        for throttle in 1100..1200 {
+            queue.push(format!("rc->{},1500,1500,1500\n", throttle));
+        }
+        queue.push("command->enable_motors".to_string());
+        queue.push("command->arm".to_string());
+        queue.push("command->reboot".to_string());
+        queue.push("command->abort".to_string());
+        assert_eq!(
+            queue.stats(),
+            QueueStats {
+                depth: 1,
+                coalesced: 99,
+                dropped: 5
+            }
+        );
+
+        assert_eq!(drain(&queue), vec!["command->abort"]);
+
+        // Commands pushed after the abort go out as usual
+        queue.push("command->reboot".to_string());
+        queue.push("command->arm".to_string());
+        assert_eq!(drain(&queue), vec!["command->reboot", "command->arm"]);
+    }
+
+    #[test]
+    fn preempt_keeps_its_order() {
+        let queue = CommandQueue::default();
+        queue.push("rc->1800,1500,1500,1500\n".to_string());
+        queue.push("command->arm\n".to_string());
+        queue.push("command->enable_motors\n".to_string());
+        queue.push("command->abort\n".to_string());
+        queue.preempt(&[
+            "rc->1000,1500,1500,1500\n".to_string(),
+            "command->abort\n".to_string(),
+        ]);
+
+        assert_eq!(
+            drain(&queue),
+            vec![
+                "command->abort\n",
+                "rc->1000,1500,1500,1500\n",
+                "command->abort\n",
+            ]
+        );
+        assert_eq!(queue.stats().dropped, 3);
+    }
+
+    #[test]
+    fn drops_when_full() {
+        let queue = CommandQueue::default();
+        for i in 0..MAX_ORDERED + 3 {
+            queue.push(format!("hello {}", i));
+        }
+        let stats = queue.stats();
+        assert_eq!((stats.depth, stats.dropped), (MAX_ORDERED as u32, 3));
+        assert_eq!(queue.try_pop().unwrap(), "hello 0");
+    }
+
+    #[test]
+    fn close_keeps_only_the_last_commands() {
+        let queue = CommandQueue::default();
+        queue.push("command->enable_motors".to_string());
+        queue.push("rc->1500,1500,1500,1500\n".to_string());
+        queue.push("command->arm".to_string());
+        queue.close(&[
+            "rc->1000,1500,1500,1500\n".to_string(),
+            "command->abort\n".to_string(),
+        ]);
+        queue.push("command->enable_motors".to_string());
+
+        assert_eq!(queue.stats().dropped, 4);
+        assert_eq!(
+            drain(&queue),
+            vec!["rc->1000,1500,1500,1500\n", "command->abort\n"]
+        );
+    }
+
+    #[test]
+    fn kill_switch_refuses_motor_commands() {
+        let queue = CommandQueue::default();
+        queue.set_kill_switch(true);
+        queue.preempt(&[
+            "rc->1000,1500,1500,1500\n".to_string(),
+            "command->abort\n".to_string(),
+        ]);
+        assert!(queue.blocked("command->arm\n"));
+        queue.push("command->arm\n".to_string());
+        queue.push("command->enable_motors\n".to_string());
+        queue.push("rc->1800,1500,1500,1500\n".to_string());
+        queue.push("pid->1,0,0\n".to_string());
+
+        assert_eq!(queue.stats().dropped, 3);
+        assert_eq!(
+            drain(&queue),
+            vec![
+                "rc->1000,1500,1500,1500\n",
+                "command->abort\n",
+                "pid->1,0,0\n"
+            ]
+        );
+
+        queue.set_kill_switch(false);
+        assert!(!queue.blocked("command->arm\n"));
+        queue.push("command->arm\n".to_string());
+        assert_eq!(drain(&queue), vec!["command->arm\n"]);
+    }
+
+    #[tokio::test]
+    async fn pop_waits_for_push() {
+        let queue = std::sync::Arc::new(CommandQueue::default());
+        let writer = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.pop().await }
+        });
+        tokio::task::yield_now().await;
+        queue.push("command->arm".to_string());
+        assert_eq!(writer.await.unwrap(), "command->arm");
+    }
+}
@@ -0,0 +1,435 @@
+use chrono::Local;
+use protocol::{BridgeEvent, LogFile};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::LogConfig;
+use crate::export::FlightLog;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+const LOG_EXTENSION: &str = "jsonl";
+const MCAP_EXTENSION: &str = "mcap";
+// Sessions started within the same second, e.g. by a bridge in a crash loop
+const MAX_SESSIONS_PER_NAME: u32 = 100;
+
+// One line of a flight log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    // Milliseconds since the session started, from a monotonic clock
+    pub mono_ms: u64,
+    // Unix time in milliseconds
+    pub wall_ms: u64,
+    #[serde(flatten)]
+    pub entry: Entry,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    SessionStart { session: String },
+    SessionStop { session: String },
+    // Line read from the flight controller
+    Serial { line: String },
+    // Line written to the flight controller
+    Command { line: String },
+    Event { event: BridgeEvent },
+}
+
+enum Message {
+    Record(Record),
+    Stop(mpsc::Sender<()>),
+}
+
+// Black-box recorder writing every serial line and command to `log.dir`.
+// Writing happens on a dedicated thread so recording never blocks the bridge.
+#[derive(Clone)]
+pub struct Recorder {
+    tx: Option<mpsc::Sender<Message>>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn disabled() -> Recorder {
+        Recorder {
+            tx: None,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn start(config: &LogConfig) -> io::Result<Recorder> {
+        if !config.enabled {
+            return Ok(Recorder::disabled());
+        }
+
+        let started = format!("session-{}", Local::now().format("%Y%m%d-%H%M%S"));
+        let (session, mut writer) = create_session(config, &started)?;
+        println!("Recording flight log to {}", writer.path().display());
+
+        let (tx, rx) = mpsc::channel();
+        let recorder = Recorder {
+            tx: Some(tx),
+            start: Instant::now(),
+        };
+        recorder.record(Entry::SessionStart {
+            session: session.clone(),
+        });
+
+        let start = recorder.start;
+        thread::spawn(move || {
+            loop {
+                match rx.recv_timeout(FLUSH_INTERVAL) {
+                    Ok(Message::Record(record)) => {
+                        if let Err(e) = writer.write(&record) {
+                            eprintln!("Failed to write flight log: {}", e);
+                        }
+                    }
+                    Ok(Message::Stop(done)) => {
+                        let record = timestamped(start, Entry::SessionStop { session });
+                        if let Err(e) = writer.write(&record).and_then(|_| writer.finish()) {
+                            eprintln!("Failed to write flight log: {}", e);
+                        }
+                        let _ = done.send(());
+                        break;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        if let Err(e) = writer.flush() {
+                            eprintln!("Failed to flush flight log: {}", e);
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            let _ = writer.finish();
+        });
+
+        Ok(recorder)
+    }
+
+    pub fn record(&self, entry: Entry) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(Message::Record(timestamped(self.start, entry)));
+        }
+    }
+
+    // Writes the session stop marker and flushes the log, waiting at most `STOP_TIMEOUT`
+    pub fn stop(&self) {
+        if let Some(tx) = &self.tx {
+            let (done, wait) = mpsc::channel();
+            if tx.send(Message::Stop(done)).is_ok() {
+                let _ = wait.recv_timeout(STOP_TIMEOUT);
+            }
+        }
+    }
+}
+
+fn timestamped(start: Instant, entry: Entry) -> Record {
+    Record {
+        mono_ms: start.elapsed().as_millis() as u64,
+        wall_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        entry,
+    }
+}
+
+// Starts a session named `started`, or `started_<n>` when an earlier session
+// already took the name, so a bridge restarted within the same second does not
+// overwrite the log of the last one
+fn create_session(config: &LogConfig, started: &str) -> io::Result<(String, LogWriter)> {
+    let mut attempt = 0;
+    loop {
+        let session = match attempt {
+            0 => started.to_string(),
+            n => format!("{}_{}", started, n),
+        };
+        match LogWriter::create(
+            &config.dir,
+            &session,
+            config.max_file_mb * 1024 * 1024,
+            config.max_total_mb * 1024 * 1024,
+            config.mcap,
+        ) {
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                attempt += 1;
+                if attempt == MAX_SESSIONS_PER_NAME {
+                    return Err(e);
+                }
+            }
+            result => return result.map(|writer| (session, writer)),
+        }
+    }
+}
+
+// Appends records to `<session>-<part>.jsonl`, starting a new part when the file
+// reaches `max_file_bytes` and deleting the oldest logs above `max_total_bytes`.
+// With `mcap` the whole session also goes to `<session>.mcap`.
+struct LogWriter {
+    dir: PathBuf,
+    session: String,
+    part: u32,
+    file: BufWriter<File>,
+    written: u64,
+    max_file_bytes: u64,
+    max_total_bytes: u64,
+    mcap: Option<FlightLog<BufWriter<File>>>,
+}
+
+impl LogWriter {
+    fn create(
+        dir: &Path,
+        session: &str,
+        max_file_bytes: u64,
+        max_total_bytes: u64,
+        mcap: bool,
+    ) -> io::Result<LogWriter> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(part_name(session, 0));
+        let file = create_new(&path)?;
+        let mcap = match mcap {
+            true => match create_new(&dir.join(mcap_name(session))) {
+                Ok(file) => Some(FlightLog::new(BufWriter::new(file))?),
+                Err(e) => {
+                    // The name is taken, give it back for the next attempt
+                    let _ = fs::remove_file(&path);
+                    return Err(e);
+                }
+            },
+            false => None,
+        };
+        let mut writer = LogWriter {
+            dir: dir.to_owned(),
+            session: session.to_string(),
+            part: 0,
+            file: BufWriter::new(file),
+            written: 0,
+            max_file_bytes,
+            max_total_bytes,
+            mcap,
+        };
+        writer.prune()?;
+        Ok(writer)
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(part_name(&self.session, self.part))
+    }
+
+    fn write(&mut self, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        if self.written > 0 && self.written + line.len() as u64 > self.max_file_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        if let Some(mcap) = &mut self.mcap {
+            mcap.write(record)?;
+        }
+        Ok(())
+    }
+
+    // The MCAP file is only written in chunks, and completed by `finish`
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if let Some(mcap) = self.mcap.take() {
+            mcap.finish()?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.part += 1;
+        self.file = BufWriter::new(create_new(&self.path())?);
+        self.written = 0;
+        self.prune()
+    }
+
+    fn prune(&mut self) -> io::Result<()> {
+        let current = [
+            part_name(&self.session, self.part),
+            mcap_name(&self.session),
+        ];
+        let files = stored_files(&self.dir, &[LOG_EXTENSION, MCAP_EXTENSION])?;
+        let mut total: u64 = files.iter().map(|file| file.size).sum();
+
+        for file in files {
+            if total <= self.max_total_bytes {
+                break;
+            }
+            if current.contains(&file.name) {
+                continue;
+            }
+            fs::remove_file(self.dir.join(&file.name))?;
+            total -= file.size;
+        }
+        Ok(())
+    }
+}
+
+// Fails with `AlreadyExists` instead of truncating the log of another session
+fn create_new(path: &Path) -> io::Result<File> {
+    File::options().write(true).create_new(true).open(path)
+}
+
+fn part_name(session: &str, part: u32) -> String {
+    format!("{}-{:03}.{}", session, part, LOG_EXTENSION)
+}
+
+fn mcap_name(session: &str) -> String {
+    format!("{}.{}", session, MCAP_EXTENSION)
+}
+
+// Flight logs in `dir`, oldest first. MCAP files are not listed, clients
+// download logs as text.
+pub fn list_files(dir: &Path) -> io::Result<Vec<LogFile>> {
+    stored_files(dir, &[LOG_EXTENSION])
+}
+
+fn stored_files(dir: &Path, extensions: &[&str]) -> io::Result<Vec<LogFile>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if !extension.is_some_and(|ext| extensions.contains(&ext)) {
+            continue;
+        }
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            files.push(LogFile {
+                name: name.to_string(),
+                size: entry.metadata()?.len(),
+            });
+        }
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+// Only files listed by `list_files` can be downloaded
+pub fn resolve(dir: &Path, name: &str) -> Option<PathBuf> {
+    list_files(dir)
+        .ok()?
+        .into_iter()
+        .find(|file| file.name == name)
+        .map(|file| dir.join(file.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rpi-recorder-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn record(line: &str) -> Record {
+        Record {
+            mono_ms: 1,
+            wall_ms: 2,
+            entry: Entry::Serial {
+                line: line.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn golden_record() {
+        let line = serde_json::to_string(&record("Armed...")).unwrap();
+        assert_eq!(
+            line,
+            r#"{"mono_ms":1,"wall_ms":2,"kind":"serial","line":"Armed..."}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Record>(&line).unwrap(),
+            record("Armed...")
+        );
+    }
+
+    #[test]
+    fn rotates_and_prunes() {
+        let dir = temp_dir("rotate");
+        let mut writer = LogWriter::create(&dir, "session-a", 200, 500, false).unwrap();
+        for i in 0..20 {
+            writer.write(&record(&format!("line {}", i))).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let files = list_files(&dir).unwrap();
+        assert!(files.len() > 1);
+        assert!(files.iter().all(|file| file.size <= 200));
+        assert!(files.iter().map(|file| file.size).sum::<u64>() <= 500 + 200);
+        // The oldest parts were deleted, the current one is kept
+        assert!(!files.iter().any(|file| file.name == "session-a-000.jsonl"));
+        assert_eq!(
+            files.last().unwrap().name,
+            part_name("session-a", writer.part)
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolves_only_listed_files() {
+        let dir = temp_dir("resolve");
+        let mut writer = LogWriter::create(&dir, "session-b", 1000, 10_000, true).unwrap();
+        writer.write(&record("hello")).unwrap();
+        writer.finish().unwrap();
+
+        assert!(resolve(&dir, "session-b-000.jsonl").is_some());
+        assert!(resolve(&dir, "../session-b-000.jsonl").is_none());
+        assert!(resolve(&dir, "missing.jsonl").is_none());
+        // Written next to the log, but not offered for download
+        assert!(dir.join("session-b.mcap").exists());
+        assert!(resolve(&dir, "session-b.mcap").is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restarts_do_not_overwrite_sessions() {
+        let dir = temp_dir("restart");
+        let config = LogConfig {
+            dir: dir.clone(),
+            mcap: true,
+            ..Default::default()
+        };
+        let (session, mut writer) = create_session(&config, "session-c").unwrap();
+        assert_eq!(session, "session-c");
+        writer.write(&record("first run")).unwrap();
+        writer.finish().unwrap();
+
+        // Restarted within the same second
+        let (session, mut writer) = create_session(&config, "session-c").unwrap();
+        assert_eq!(session, "session-c_1");
+        writer.write(&record("second run")).unwrap();
+        writer.finish().unwrap();
+
+        let first = fs::read_to_string(dir.join("session-c-000.jsonl")).unwrap();
+        assert!(first.contains("first run"));
+        assert!(dir.join("session-c_1.mcap").exists());
+        let names: Vec<String> = list_files(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+        assert_eq!(names, ["session-c-000.jsonl", "session-c_1-000.jsonl"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
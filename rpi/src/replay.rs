@@ -0,0 +1,226 @@
+use protocol::ReplayRequest;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, watch};
+use tokio::time::Instant;
+
+use crate::config::ReplayConfig;
+use crate::queue::CommandQueue;
+use crate::recorder::{Entry, Record};
+use crate::serial::{LinkState, SerialLink, SerialStats};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Playback {
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl Playback {
+    pub fn apply(&mut self, request: ReplayRequest) {
+        match request {
+            ReplayRequest::Pause => self.paused = true,
+            ReplayRequest::Resume => self.paused = false,
+            ReplayRequest::Speed(speed) => self.speed = speed,
+        }
+    }
+}
+
+// Serial lines of a recorded session with their time since the session started
+pub fn load(path: &Path) -> io::Result<Vec<(u64, String)>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut lines = Vec::new();
+    let mut malformed = 0;
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str::<Record>(line) {
+            Ok(Record {
+                mono_ms,
+                entry: Entry::Serial { line },
+                ..
+            }) => lines.push((mono_ms, line)),
+            // Commands and events were sent by the bridge, not the flight controller
+            Ok(_) => {}
+            Err(_) => malformed += 1,
+        }
+    }
+    if malformed > 0 {
+        eprintln!(
+            "Skipped {} malformed records in {}",
+            malformed,
+            path.display()
+        );
+    }
+    Ok(lines)
+}
+
+// Serves a recorded session through the same channels as a live serial port,
+// so clients cannot tell the difference. Commands are dropped.
+pub fn open(
+    config: &ReplayConfig,
+    path: &Path,
+) -> io::Result<(SerialLink, watch::Sender<Playback>)> {
+    let records = load(path)?;
+    if records.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the log has no serial lines",
+        ));
+    }
+    println!(
+        "Replaying {} serial lines from {}",
+        records.len(),
+        path.display()
+    );
+
+    let (lines, _) = broadcast::channel::<String>(100);
+    let commands = Arc::new(CommandQueue::default());
+    // Nothing is ever written while replaying
+    let (written, _) = broadcast::channel::<String>(1);
+    let stats = Arc::new(SerialStats::default());
+    let (playback, playback_rx) = watch::channel(Playback {
+        speed: config.speed,
+        paused: false,
+    });
+
+    tokio::spawn(run(
+        records,
+        lines.clone(),
+        stats.clone(),
+        playback_rx,
+        config.repeat,
+    ));
+
+    let queue = commands.clone();
+    tokio::spawn(async move {
+        loop {
+            let command = queue.pop().await;
+            println!("Replay, dropping command: {}", command.trim_end());
+        }
+    });
+
+    let link = SerialLink {
+        lines,
+        commands,
+        written,
+        stats,
+        state: watch::channel(LinkState::Up).1,
+    };
+    Ok((link, playback))
+}
+
+async fn run(
+    records: Vec<(u64, String)>,
+    tx: broadcast::Sender<String>,
+    stats: Arc<SerialStats>,
+    mut playback: watch::Receiver<Playback>,
+    repeat: bool,
+) {
+    loop {
+        // Recorded time played back so far, starting with the first line
+        let mut position = records[0].0 as f64;
+
+        for (mono_ms, line) in &records {
+            let target = *mono_ms as f64;
+            while position < target {
+                let state = *playback.borrow_and_update();
+                let started = Instant::now();
+                let wait =
+                    Duration::from_secs_f64((target - position) / 1000.0 / state.speed as f64);
+                tokio::select! {
+                    _ = tokio::time::sleep(wait), if !state.paused => position = target,
+                    changed = playback.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                        if !state.paused {
+                            position += started.elapsed().as_secs_f64() * 1000.0 * state.speed as f64;
+                        }
+                    }
+                }
+            }
+            stats.read(line.len());
+            stats.line(line);
+            // Sending only fails when no client is connected
+            let _ = tx.send(line.clone());
+        }
+
+        println!("Replay finished");
+        if !repeat {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<(u64, String)> {
+        vec![
+            (0, "a".to_string()),
+            (1000, "b".to_string()),
+            (2000, "c".to_string()),
+        ]
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_recorded_timing() {
+        let (tx, mut rx) = broadcast::channel(16);
+        let (_playback, playback_rx) = watch::channel(Playback {
+            speed: 2.0,
+            paused: false,
+        });
+        let start = Instant::now();
+        tokio::spawn(run(records(), tx, Default::default(), playback_rx, false));
+
+        assert_eq!(rx.recv().await.unwrap(), "a");
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(rx.recv().await.unwrap(), "b");
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+        assert_eq!(rx.recv().await.unwrap(), "c");
+        assert_eq!(start.elapsed(), Duration::from_millis(1000));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pauses_and_resumes() {
+        let (tx, mut rx) = broadcast::channel(16);
+        let (playback, playback_rx) = watch::channel(Playback {
+            speed: 1.0,
+            paused: false,
+        });
+        tokio::spawn(run(records(), tx, Default::default(), playback_rx, false));
+        assert_eq!(rx.recv().await.unwrap(), "a");
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        playback.send_modify(|state| state.apply(ReplayRequest::Pause));
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(rx.try_recv().is_err());
+
+        let resumed = Instant::now();
+        playback.send_modify(|state| state.apply(ReplayRequest::Resume));
+        assert_eq!(rx.recv().await.unwrap(), "b");
+        assert_eq!(resumed.elapsed(), Duration::from_millis(600));
+    }
+
+    #[test]
+    fn loads_serial_lines_only() {
+        let path = std::env::temp_dir().join(format!("rpi-replay-{}.jsonl", std::process::id()));
+        std::fs::write(
+            &path,
+            concat!(
+                r#"{"mono_ms":0,"wall_ms":5,"kind":"session_start","session":"s"}"#,
+                "\n",
+                r#"{"mono_ms":10,"wall_ms":15,"kind":"serial","line":"Armed..."}"#,
+                "\n",
+                r#"{"mono_ms":20,"wall_ms":25,"kind":"command","line":"command->abort"}"#,
+                "\n",
+                "not json\n",
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(load(&path).unwrap(), vec![(10, "Armed...".to_string())]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,492 @@
+use protocol::{BridgeEvent, Frame, FrameDecoder, SerialData, FRAME_DELIMITER};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{broadcast, watch};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::bridge::Bridge;
+use crate::config::{Framing, SerialConfig};
+use crate::queue::CommandQueue;
+use crate::recorder::{Entry, Recorder};
+
+// Reopening a lost serial port waits between these, doubling after every failure
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+// A port that stayed open this long starts again from the shortest wait
+const STABLE_AFTER: Duration = Duration::from_secs(10);
+
+// The bridge owns the serial port once. Every line read is broadcast to all
+// connected clients, and commands are written from the queue by priority.
+pub struct SerialLink {
+    pub lines: broadcast::Sender<String>,
+    pub commands: Arc<CommandQueue>,
+    // Every command once it has been written to the flight controller
+    pub written: broadcast::Sender<String>,
+    pub stats: Arc<SerialStats>,
+    pub state: watch::Receiver<LinkState>,
+}
+
+// Whether the serial port is open. The emulator and replays are always up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkState {
+    Up,
+    Down(String),
+}
+
+#[derive(Debug)]
+struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { next: MIN_BACKOFF }
+    }
+}
+
+impl Backoff {
+    fn next(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.next = MIN_BACKOFF;
+    }
+}
+
+// Counters of the traffic with the flight controller
+#[derive(Default)]
+pub struct SerialStats {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    lines: AtomicU64,
+    errors: AtomicU64,
+    last_telemetry: Mutex<Option<Instant>>,
+}
+
+impl SerialStats {
+    pub fn read(&self, bytes: usize) {
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn wrote(&self, bytes: usize) {
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // Counts a line read, JSON that is not telemetry is a parse error
+    pub fn line(&self, line: &str) {
+        self.lines.fetch_add(1, Ordering::Relaxed);
+        if line.trim_start().starts_with('{') {
+            match SerialData::decode(line) {
+                Ok(_) => *self.last_telemetry.lock().unwrap() = Some(Instant::now()),
+                Err(_) => self.error(),
+            }
+        }
+    }
+
+    // Counts a frame or line that was dropped as unreadable
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn lines(&self) -> u64 {
+        self.lines.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::Relaxed)
+    }
+
+    pub fn last_telemetry(&self) -> Option<Instant> {
+        *self.last_telemetry.lock().unwrap()
+    }
+}
+
+impl SerialLink {
+    // Opens the port and keeps reopening it whenever it fails. Clients stay
+    // connected through an outage, the commands sent meanwhile are dropped.
+    pub fn open(config: &SerialConfig, recorder: Recorder) -> SerialLink {
+        let (lines, _) = broadcast::channel::<String>(100);
+        let commands = Arc::new(CommandQueue::default());
+        let (written, _) = broadcast::channel::<String>(100);
+        let stats = Arc::new(SerialStats::default());
+
+        let port = open_port(config);
+        let (state_tx, state) = watch::channel(match &port {
+            Ok(_) => LinkState::Up,
+            Err(e) => {
+                eprintln!("Failed to open serial port {}: {}", config.path, e);
+                LinkState::Down(e.to_string())
+            }
+        });
+
+        let channels = Channels {
+            lines: lines.clone(),
+            commands: commands.clone(),
+            written: written.clone(),
+            stats: stats.clone(),
+        };
+        tokio::spawn(supervise(
+            config.clone(),
+            port.ok(),
+            channels,
+            recorder,
+            state_tx,
+        ));
+
+        SerialLink {
+            lines,
+            commands,
+            written,
+            stats,
+            state,
+        }
+    }
+}
+
+// What the serial tasks share with the `SerialLink` they feed
+struct Channels {
+    lines: broadcast::Sender<String>,
+    commands: Arc<CommandQueue>,
+    written: broadcast::Sender<String>,
+    stats: Arc<SerialStats>,
+}
+
+fn open_port(config: &SerialConfig) -> Result<SerialStream, tokio_serial::Error> {
+    tokio_serial::new(&config.path, config.baud).open_native_async()
+}
+
+async fn supervise(
+    config: SerialConfig,
+    mut port: Option<SerialStream>,
+    channels: Channels,
+    recorder: Recorder,
+    state: watch::Sender<LinkState>,
+) {
+    let mut backoff = Backoff::default();
+    loop {
+        if let Some(serial) = port.take() {
+            let opened = Instant::now();
+            state.send_replace(LinkState::Up);
+            let e = run_port(serial, config.framing, &channels, &recorder).await;
+            eprintln!("Serial port {} lost: {}", config.path, e);
+            state.send_replace(LinkState::Down(e.to_string()));
+            if opened.elapsed() >= STABLE_AFTER {
+                backoff.reset();
+            }
+        }
+
+        // Nothing queued now would be written at the right time
+        let retry = tokio::time::sleep(backoff.next());
+        tokio::pin!(retry);
+        loop {
+            tokio::select! {
+                _ = &mut retry => break,
+                command = channels.commands.pop() => {
+                    println!("Serial port down, dropping command: {}", command.trim_end());
+                }
+            }
+        }
+
+        match open_port(&config) {
+            Ok(serial) => {
+                println!("Reopened serial port {}", config.path);
+                port = Some(serial);
+            }
+            Err(e) => {
+                eprintln!("Failed to reopen serial port {}: {}", config.path, e);
+                state.send_replace(LinkState::Down(e.to_string()));
+            }
+        }
+    }
+}
+
+// Reads and writes until the port fails, returns why it stopped
+async fn run_port(
+    serial: SerialStream,
+    framing: Framing,
+    channels: &Channels,
+    recorder: &Recorder,
+) -> io::Error {
+    let (serial_reader, mut serial_writer) = tokio::io::split(serial);
+    let mut serial_reader = BufReader::new(serial_reader);
+    let (tx, stats) = (&channels.lines, &*channels.stats);
+    let read = async {
+        match framing {
+            Framing::Lines => read_lines(&mut serial_reader, tx, recorder, stats).await,
+            Framing::Cobs => read_frames(&mut serial_reader, tx, recorder, stats).await,
+        }
+    };
+    let write = write_commands(&mut serial_writer, channels, recorder);
+    tokio::select! {
+        e = read => e,
+        e = write => e,
+    }
+}
+
+// The firmware reads commands up to `\n`, one sent without it would run into
+// the next
+pub fn terminated(command: &str) -> String {
+    match command.ends_with('\n') {
+        true => command.to_string(),
+        false => format!("{}\n", command),
+    }
+}
+
+async fn write_commands<W: AsyncWrite + Unpin>(
+    serial_writer: &mut W,
+    channels: &Channels,
+    recorder: &Recorder,
+) -> io::Error {
+    loop {
+        let command = channels.commands.pop().await;
+        println!("Processing command: {}", command);
+        let line = terminated(&command);
+        if let Err(e) = serial_writer.write_all(line.as_bytes()).await {
+            eprintln!(
+                "Serial write error, dropping command: {}",
+                command.trim_end()
+            );
+            return e;
+        }
+        channels.stats.wrote(line.len());
+        recorder.record(Entry::Command {
+            line: command.trim_end().to_string(),
+        });
+        let _ = channels.written.send(command);
+    }
+}
+
+// Turns changes of the serial link into events for every client
+pub async fn report_link(bridge: Arc<Bridge>) {
+    let mut state = bridge.serial.state.clone();
+    let mut up = matches!(*state.borrow_and_update(), LinkState::Up);
+    if let LinkState::Down(reason) = &*state.borrow() {
+        *bridge.link_down.lock().unwrap() = Some(BridgeEvent::SerialDown {
+            timestamp: unix_millis(),
+            reason: reason.clone(),
+        });
+    }
+
+    // Ends with the emulator and replays, which never go down
+    while state.changed().await.is_ok() {
+        let event = match &*state.borrow_and_update() {
+            LinkState::Up if !up => BridgeEvent::SerialUp {
+                timestamp: unix_millis(),
+            },
+            LinkState::Down(reason) if up => BridgeEvent::SerialDown {
+                timestamp: unix_millis(),
+                reason: reason.clone(),
+            },
+            // Another failed attempt to reopen the port
+            _ => continue,
+        };
+        up = matches!(event, BridgeEvent::SerialUp { .. });
+        *bridge.link_down.lock().unwrap() = (!up).then(|| event.clone());
+        bridge.recorder.record(Entry::Event {
+            event: event.clone(),
+        });
+        // Sending only fails when no client is connected
+        let _ = bridge.events.send(event);
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+async fn read_lines<R: AsyncBufRead + Unpin>(
+    serial_reader: &mut R,
+    tx: &broadcast::Sender<String>,
+    recorder: &Recorder,
+    stats: &SerialStats,
+) -> io::Error {
+    let mut line = String::new();
+    loop {
+        match serial_reader.read_line(&mut line).await {
+            Ok(0) => return io::Error::new(io::ErrorKind::UnexpectedEof, "end of stream"),
+            Ok(read) => {
+                stats.read(read);
+                stats.line(&line);
+                recorder.record(Entry::Serial {
+                    line: line.trim_end().to_string(),
+                });
+                // Sending only fails when no client is connected
+                let _ = tx.send(line.clone());
+                line.clear();
+            }
+            // Noise on the line, the port itself is fine
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                stats.error();
+                eprintln!("Dropping serial line: {}", e);
+                line.clear();
+            }
+            Err(e) => return e,
+        }
+    }
+}
+
+async fn read_frames<R: AsyncBufRead + Unpin>(
+    serial_reader: &mut R,
+    tx: &broadcast::Sender<String>,
+    recorder: &Recorder,
+    stats: &SerialStats,
+) -> io::Error {
+    let mut decoder = FrameDecoder::default();
+    let mut frame = Vec::new();
+    loop {
+        match serial_reader.read_until(FRAME_DELIMITER, &mut frame).await {
+            Ok(0) => return io::Error::new(io::ErrorKind::UnexpectedEof, "end of stream"),
+            Ok(read) => {
+                stats.read(read);
+                // Forward frames as the same lines the JSON mode produces
+                let line = match decoder.decode(&frame) {
+                    Ok(Frame::SerialData(data)) => Some(data.encode()),
+                    Ok(Frame::Text(text)) => Some(text),
+                    Err(e) => {
+                        stats.error();
+                        eprintln!(
+                            "Dropping serial frame: {} ({} CRC errors, {} malformed, {} good)",
+                            e, decoder.crc_errors, decoder.malformed, decoder.frames
+                        );
+                        None
+                    }
+                };
+                if let Some(line) = line {
+                    stats.line(&line);
+                    recorder.record(Entry::Serial { line: line.clone() });
+                    let _ = tx.send(line);
+                }
+                frame.clear();
+            }
+            Err(e) => return e,
+        }
+    }
+}
+
+// A link that writes commands to `serial` like a port that never fails
+#[cfg(test)]
+pub fn test_link<W: AsyncWrite + Unpin + Send + 'static>(mut serial: W) -> SerialLink {
+    let (lines, _) = broadcast::channel(100);
+    let commands = Arc::new(CommandQueue::default());
+    let (written, _) = broadcast::channel(100);
+    let stats = Arc::new(SerialStats::default());
+    let channels = Channels {
+        lines: lines.clone(),
+        commands: commands.clone(),
+        written: written.clone(),
+        stats: stats.clone(),
+    };
+    tokio::spawn(async move {
+        let recorder = Recorder::disabled();
+        write_commands(&mut serial, &channels, &recorder).await
+    });
+    SerialLink {
+        lines,
+        commands,
+        written,
+        stats,
+        state: watch::channel(LinkState::Up).1,
+    }
+}
+
+// Reads `count` lines like the firmware's `readStringUntil('\n')` and checks
+// that each is a command it acts on
+#[cfg(test)]
+pub async fn firmware_reads<R: tokio::io::AsyncRead + Unpin>(
+    firmware: &mut R,
+    count: usize,
+) -> Vec<String> {
+    use protocol::Command;
+    use tokio::io::AsyncReadExt;
+
+    let mut stream = Vec::new();
+    while stream.iter().filter(|b| **b == b'\n').count() < count {
+        let mut buf = [0; 256];
+        let read = tokio::time::timeout(Duration::from_secs(1), firmware.read(&mut buf))
+            .await
+            .expect("no command written")
+            .unwrap();
+        stream.extend_from_slice(&buf[..read]);
+    }
+    let stream = String::from_utf8(stream).unwrap();
+    let lines: Vec<String> = stream.lines().map(str::to_string).collect();
+    for line in &lines {
+        let accepted = match Command::decode(line) {
+            Ok(Command::Text(_)) | Err(_) => false,
+            Ok(command) => command.encode() == format!("{}\n", line),
+        };
+        assert!(accepted, "the firmware ignores {:?}", line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FailsafeAction;
+    use crate::failsafe::safe_sequence;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::default();
+        let delays: Vec<u64> = (0..7).map(|_| backoff.next().as_millis() as u64).collect();
+        assert_eq!(delays, [250, 500, 1000, 2000, 4000, 5000, 5000]);
+        backoff.reset();
+        assert_eq!(backoff.next(), MIN_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn garbage_does_not_stop_reading() {
+        let (tx, mut rx) = broadcast::channel(10);
+        let stats = SerialStats::default();
+        let mut serial: &[u8] = b"Armed...\n\xff\xfe\nAborting...\n";
+
+        let e = read_lines(&mut serial, &tx, &Recorder::disabled(), &stats).await;
+
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(rx.recv().await.unwrap(), "Armed...\n");
+        assert_eq!(rx.recv().await.unwrap(), "Aborting...\n");
+        assert_eq!((stats.lines(), stats.errors()), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn writes_one_command_per_line() {
+        let (serial, mut firmware) = tokio::io::duplex(1024);
+        let link = test_link(serial);
+        link.commands.push("rc->1700,1500,1500,1500\n".to_string());
+        let sequence: Vec<String> = safe_sequence(FailsafeAction::Abort)
+            .iter()
+            .map(|command| command.encode())
+            .collect();
+        link.commands.preempt(&sequence);
+        // Clients may leave out the newline
+        link.commands.push("command->enable_motors".to_string());
+
+        // The writer only runs once the test waits
+        assert_eq!(
+            firmware_reads(&mut firmware, 3).await,
+            [
+                "rc->1000,1500,1500,1500",
+                "command->abort",
+                "command->enable_motors"
+            ]
+        );
+    }
+}
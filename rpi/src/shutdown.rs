@@ -0,0 +1,131 @@
+use protocol::BridgeEvent;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::bridge::Bridge;
+use crate::delivery;
+use crate::failsafe::safe_sequence;
+use crate::recorder::Entry;
+use crate::serial::LinkState;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Waits for Ctrl-C or SIGTERM, returns which one arrived
+pub async fn wait_for_signal() -> &'static str {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            eprintln!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return "SIGINT";
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = terminate.recv() => "SIGTERM",
+    }
+}
+
+// Replaces whatever is still queued with the safe sequence, waits for it to be
+// written and for every client to close, at most `shutdown.timeout_ms`
+pub async fn run(bridge: &Bridge, reason: &str) {
+    let config = &bridge.config.shutdown;
+    let sequence: Vec<String> = safe_sequence(config.action)
+        .iter()
+        .map(|command| command.encode())
+        .collect();
+    let mut written = bridge.serial.written.subscribe();
+    delivery::cancel(bridge, None, "bridge shutting down");
+    bridge.serial.commands.close(&sequence);
+
+    let event = BridgeEvent::Shutdown {
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        reason: reason.to_string(),
+        sent: sequence
+            .iter()
+            .map(|line| line.trim_end().to_string())
+            .collect(),
+    };
+    println!("{}", event.describe());
+    bridge.recorder.record(Entry::Event {
+        event: event.clone(),
+    });
+    // Every client closes its connection once it has forwarded the event
+    let _ = bridge.events.send(event);
+
+    let finished = async {
+        wait_written(bridge, &mut written, sequence).await;
+        while bridge.clients.load(Ordering::Relaxed) > 0 {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    };
+    let timeout = Duration::from_millis(config.timeout_ms);
+    if tokio::time::timeout(timeout, finished).await.is_err() {
+        eprintln!(
+            "Shutdown timed out after {} ms, {} client(s) still connected",
+            config.timeout_ms,
+            bridge.clients.load(Ordering::Relaxed)
+        );
+    }
+}
+
+async fn wait_written(
+    bridge: &Bridge,
+    written: &mut broadcast::Receiver<String>,
+    mut remaining: Vec<String>,
+) {
+    // A replay drops every command
+    if bridge.config.replay.file.is_some() {
+        return;
+    }
+    if *bridge.serial.state.borrow() != LinkState::Up {
+        eprintln!("Serial port down, the safe sequence cannot be written");
+        return;
+    }
+    while !remaining.is_empty() {
+        match written.recv().await {
+            Ok(line) => {
+                if let Some(index) = remaining.iter().position(|l| *l == line) {
+                    remaining.remove(index);
+                }
+            }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::recorder::Recorder;
+    use crate::serial::{firmware_reads, test_link};
+
+    #[tokio::test]
+    async fn firmware_gets_throttle_low_then_abort() {
+        let (serial, mut firmware) = tokio::io::duplex(1024);
+        let bridge = Bridge::new(
+            Config::default(),
+            test_link(serial),
+            Recorder::disabled(),
+            None,
+        );
+        let sticks = "rc->1700,1500,1500,1500\n".to_string();
+        bridge.serial.commands.push(sticks);
+
+        run(&bridge, "SIGTERM").await;
+        assert_eq!(
+            firmware_reads(&mut firmware, 2).await,
+            ["rc->1000,1500,1500,1500", "command->abort"]
+        );
+        bridge.serial.commands.push("command->arm\n".to_string());
+        assert_eq!(bridge.serial.commands.stats().depth, 0);
+    }
+}